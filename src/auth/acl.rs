@@ -0,0 +1,104 @@
+use bson::{doc, Document};
+use juniper::{graphql_value, FieldError};
+use serde::{Deserialize, Serialize};
+
+use crate::auth::{Permission, Principal};
+
+#[derive(juniper::GraphQLEnum, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Access to a sample granted to a user (`user:<id>`) or a group (`group:<name>`)
+#[derive(juniper::GraphQLObject, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AclEntry {
+    pub principal: String,
+    pub access: Access,
+}
+
+impl AclEntry {
+    /// Checks the ACL principal is of the form `user:<id>` or `group:<name>`
+    pub fn validate_principal(principal: &str) -> Result<(), FieldError> {
+        let mut parts = principal.splitn(2, ':');
+        match (parts.next(), parts.next()) {
+            (Some("user"), Some(name)) | (Some("group"), Some(name)) if !name.is_empty() => Ok(()),
+            _ => Err(format!(
+                "Invalid principal {}, expected user:<id> or group:<name>",
+                principal
+            )
+            .into()),
+        }
+    }
+}
+
+impl Principal {
+    /// The ACL principals the caller matches, i.e. its user id and groups
+    pub fn acl_principals(&self) -> Vec<String> {
+        let mut principals: Vec<String> = self
+            .identity
+            .iter()
+            .map(|identity| format!("user:{}", identity.id))
+            .collect();
+        principals.extend(self.groups.iter().map(|group| format!("group:{}", group)));
+        principals
+    }
+
+    /// Callers with `samples:manage_all` bypass ownership and ACLs
+    pub fn bypasses_acl(&self) -> bool {
+        self.has_permission(Permission::ManageAllSamples)
+    }
+}
+
+/// Every sample has an owner, samples predating ownership are given one by the migrations.
+/// Callers without an identity own nothing.
+fn owner_clauses(principal: &Principal) -> Vec<Document> {
+    let ids: Vec<String> = principal
+        .identity
+        .iter()
+        .map(|identity| identity.id.clone())
+        .collect();
+    vec![doc! { "owner_id": { "$in": ids } }]
+}
+
+/// Restricts samples to those the principal owns or was granted any access to
+pub fn acl_read_filter(principal: &Principal) -> Option<Document> {
+    if principal.bypasses_acl() {
+        return None;
+    }
+    let mut clauses = owner_clauses(principal);
+    clauses.push(doc! { "acl.principal": { "$in": principal.acl_principals() } });
+    Some(doc! { "$or": clauses })
+}
+
+/// Restricts samples to those the principal owns or was granted write access to
+pub fn acl_write_filter(principal: &Principal) -> Option<Document> {
+    if principal.bypasses_acl() {
+        return None;
+    }
+    let mut clauses = owner_clauses(principal);
+    clauses.push(doc! {
+        "acl": {
+            "$elemMatch": {
+                "principal": { "$in": principal.acl_principals() },
+                "access": "Write",
+            }
+        }
+    });
+    Some(doc! { "$or": clauses })
+}
+
+/// Restricts samples to those the principal owns, used for deletes and for changing the ACL
+pub fn acl_owner_filter(principal: &Principal) -> Option<Document> {
+    if principal.bypasses_acl() {
+        return None;
+    }
+    Some(doc! { "$or": owner_clauses(principal) })
+}
+
+pub fn no_access(access: &str) -> FieldError {
+    FieldError::new(
+        format!("Forbidden: no {} access to this sample", access),
+        graphql_value!({ "code": "FORBIDDEN" }),
+    )
+}
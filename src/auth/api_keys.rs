@@ -0,0 +1,240 @@
+use actix_web::dev::Payload;
+use actix_web::{Error, FromRequest, HttpRequest};
+use bson::doc;
+use chrono::{DateTime, TimeZone, Utc};
+use futures::future::{err, ok, Ready};
+use juniper::FieldError;
+use log::warn;
+use mongodb_base_service::{BaseService, Node, NodeDetails, ID};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::auth::{forbidden, Identity, Permission, Principal};
+use crate::db::Clients;
+use crate::schema::{now, Context};
+
+/// Prefix of every generated key so they are easy to spot in logs and secret scanners
+const KEY_PREFIX: &str = "sk_";
+
+/// A service to service API key, only the hash of the secret is stored
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    #[serde(rename = "_id")]
+    pub id: ID,
+    pub node: NodeDetails,
+    pub name: String,
+    /// The first characters of the secret, to help identify a key
+    pub prefix: String,
+    pub hash: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<i64>,
+    pub revoked_at: Option<i64>,
+    pub rotated_at: Option<i64>,
+    /// Requests made with the key are scoped to this tenant
+    pub tenant_id: Option<String>,
+}
+
+impl Node for ApiKey {
+    fn node(&self) -> &NodeDetails {
+        &self.node
+    }
+}
+
+impl ApiKey {
+    pub fn is_active(&self, timestamp: i64) -> bool {
+        self.revoked_at.is_none() && self.expires_at.map_or(true, |expires| expires > timestamp)
+    }
+
+    fn principal(&self) -> Principal {
+        let id = match bson::to_bson(&self.id) {
+            Ok(bson::Bson::ObjectId(oid)) => oid.to_hex(),
+            _ => self.prefix.clone(),
+        };
+        Principal {
+            identity: Some(Identity {
+                id: format!("api-key:{}", id),
+                email: None,
+                name: Some(self.name.clone()),
+            }),
+            role: None,
+            permissions: self
+                .scopes
+                .iter()
+                .filter_map(|scope| Permission::parse(scope))
+                .collect(),
+            groups: vec![],
+            impersonated_by: None,
+            tenant: self.tenant_id.clone(),
+            tenants: vec![],
+        }
+    }
+}
+
+#[juniper::object(Context = Context, description = "A service to service API key")]
+impl ApiKey {
+    fn id(&self) -> &ID {
+        &self.id
+    }
+
+    fn date_created(&self) -> Option<DateTime<Utc>> {
+        self.node.date_created()
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    fn scopes(&self) -> &Vec<String> {
+        &self.scopes
+    }
+
+    fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.expires_at.map(|expires| Utc.timestamp(expires, 0))
+    }
+
+    fn revoked_at(&self) -> Option<DateTime<Utc>> {
+        self.revoked_at.map(|revoked| Utc.timestamp(revoked, 0))
+    }
+
+    fn rotated_at(&self) -> Option<DateTime<Utc>> {
+        self.rotated_at.map(|rotated| Utc.timestamp(rotated, 0))
+    }
+
+    fn tenant_id(&self) -> &Option<String> {
+        &self.tenant_id
+    }
+
+    fn active(&self) -> bool {
+        self.is_active(now() as i64)
+    }
+}
+
+/// An API key along with its secret, which is only ever returned once
+#[derive(juniper::GraphQLObject)]
+#[graphql(Context = Context)]
+pub struct ApiKeySecret {
+    pub api_key: ApiKey,
+    /// Send as `Authorization: ApiKey <secret>` or `x-api-key: <secret>`
+    pub secret: String,
+}
+
+#[derive(juniper::GraphQLInputObject)]
+pub struct NewApiKey {
+    pub name: String,
+    /// Permissions granted to the key, e.g. `samples:read`
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct ApiKeyInsert {
+    pub name: String,
+    pub prefix: String,
+    pub hash: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<i64>,
+    /// The real caller when the key was managed through `x-act-as`
+    pub impersonated_by: Option<Identity>,
+    pub tenant_id: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ApiKeyRotation {
+    pub prefix: String,
+    pub hash: String,
+    pub rotated_at: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+    /// The real caller when the key was managed through `x-act-as`
+    pub impersonated_by: Option<Identity>,
+}
+
+#[derive(Serialize)]
+pub struct ApiKeyRevocation {
+    pub revoked_at: i64,
+    /// The real caller when the key was managed through `x-act-as`
+    pub impersonated_by: Option<Identity>,
+}
+
+pub fn hash_secret(secret: &str) -> String {
+    format!("{:x}", Sha256::digest(secret.as_bytes()))
+}
+
+/// Generates a new secret, returning it along with its prefix and hash
+pub fn generate_secret() -> (String, String, String) {
+    let secret = format!(
+        "{}{}{}",
+        KEY_PREFIX,
+        Uuid::new_v4().to_simple(),
+        Uuid::new_v4().to_simple()
+    );
+    let prefix = secret[..KEY_PREFIX.len() + 8].to_string();
+    let hash = hash_secret(&secret);
+    (secret, prefix, hash)
+}
+
+/// Checks that every scope is a known permission held by the principal, so keys can't be used
+/// to grant more than their creator has
+pub fn validate_scopes(principal: &Principal, scopes: &[String]) -> Result<(), FieldError> {
+    for scope in scopes {
+        match Permission::parse(scope) {
+            Some(permission) if principal.has_permission(permission) => {}
+            Some(permission) => return Err(forbidden(permission)),
+            None => return Err(format!("Unknown scope {}", scope).into()),
+        }
+    }
+    Ok(())
+}
+
+/// Resolves the principal for an API key secret, if it is active
+pub fn authenticate_api_key(clients: &Clients, secret: &str) -> Option<Principal> {
+    let service = clients.mongo.get_mongo_service("api_keys").unwrap();
+    let document = match service.data_source().find_one(
+        doc! { "hash": hash_secret(secret), "revoked_at": null },
+        None,
+    ) {
+        Ok(document) => document?,
+        Err(e) => {
+            warn!("Unable to look up api key: {}", e);
+            return None;
+        }
+    };
+    match bson::from_bson::<ApiKey>(bson::Bson::Document(document)) {
+        Ok(api_key) if api_key.is_active(now() as i64) => Some(api_key.principal()),
+        Ok(_) => None,
+        Err(e) => {
+            warn!("Unable to read api key: {}", e);
+            None
+        }
+    }
+}
+
+/// The raw API key sent through `Authorization: ApiKey <secret>` or `x-api-key`
+pub struct ApiKeyHeader(pub String);
+
+impl FromRequest for ApiKeyHeader {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let headers = req.headers();
+        if let Some(Ok(key)) = headers.get("x-api-key").map(|key| key.to_str()) {
+            return ok(ApiKeyHeader(key.trim().to_string()));
+        }
+        if let Some(Ok(header)) = headers.get("authorization").map(|auth| auth.to_str()) {
+            let mut parts = header.splitn(2, ' ');
+            if let (Some(scheme), Some(key)) = (parts.next(), parts.next()) {
+                if scheme.eq_ignore_ascii_case("apikey") {
+                    return ok(ApiKeyHeader(key.trim().to_string()));
+                }
+            }
+        }
+        err(actix_web::error::ErrorUnauthorized("Missing api key"))
+    }
+}
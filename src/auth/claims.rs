@@ -0,0 +1,67 @@
+use actix_web::dev::Payload;
+use actix_web::{web, Error, FromRequest, HttpRequest};
+use futures::future::{err, ok, Ready};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::auth::{is_revoked, Issuers};
+
+/// The verified claims of a bearer token
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Claims {
+    pub iss: String,
+    pub sub: String,
+    pub exp: u64,
+    pub iat: Option<u64>,
+    pub jti: Option<String>,
+    pub email: Option<String>,
+    pub name: Option<String>,
+    pub hd: Option<String>,
+    /// Every other claim, e.g. `groups` or `scope`
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    let header = req.headers().get("authorization")?.to_str().ok()?;
+    let mut parts = header.splitn(2, ' ');
+    match (parts.next(), parts.next()) {
+        (Some(scheme), Some(token)) if scheme.eq_ignore_ascii_case("bearer") => Some(token.trim()),
+        _ => None,
+    }
+}
+
+impl FromRequest for Claims {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let issuers = match req.app_data::<web::Data<Arc<Issuers>>>() {
+            Some(issuers) => issuers,
+            None => {
+                return err(actix_web::error::ErrorInternalServerError(
+                    "Issuers are not configured",
+                ))
+            }
+        };
+        let token = match bearer_token(req) {
+            Some(token) => token,
+            None => return err(actix_web::error::ErrorUnauthorized("Missing bearer token")),
+        };
+        match issuers.validate(token) {
+            Ok(claims) if is_revoked(&claims) => {
+                debug!("Rejected revoked bearer token for {}", claims.sub);
+                err(actix_web::error::ErrorUnauthorized("Revoked bearer token"))
+            }
+            Ok(claims) => ok(claims),
+            Err(e) => {
+                debug!("Rejected bearer token: {}", e);
+                err(actix_web::error::ErrorUnauthorized("Invalid bearer token"))
+            }
+        }
+    }
+}
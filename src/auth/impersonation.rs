@@ -0,0 +1,80 @@
+use actix_web::dev::Payload;
+use actix_web::{Error, FromRequest, HttpRequest};
+use futures::future::{err, ok, Ready};
+use log::{info, warn};
+
+use super::stored_role;
+use crate::auth::{Identity, Permission, Principal, DEFAULT_ROLE};
+use crate::db::Clients;
+
+/// The identity a support engineer wants to act as, sent through `x-act-as`
+pub struct ActAsHeader(pub String);
+
+impl FromRequest for ActAsHeader {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        match req.headers().get("x-act-as").map(|value| value.to_str()) {
+            Some(Ok(value)) if !value.trim().is_empty() => {
+                ok(ActAsHeader(value.trim().to_string()))
+            }
+            _ => err(actix_web::error::ErrorBadRequest("Missing x-act-as header")),
+        }
+    }
+}
+
+impl Principal {
+    /// The identity actually making the request, which differs from `identity` when impersonating
+    pub fn real_identity(&self) -> Option<&Identity> {
+        self.impersonated_by.as_ref().or(self.identity.as_ref())
+    }
+}
+
+/// Swaps the effective identity of a principal holding `identity:impersonate` for the given
+/// user id or email. The effective permissions are those of the user's role, capped by the
+/// permissions of the real caller so impersonation can't be used to escalate.
+pub fn impersonate(clients: &Clients, real: Principal, target: &str) -> Result<Principal, String> {
+    let real_id = real
+        .identity
+        .as_ref()
+        .map(|identity| identity.id.clone())
+        .unwrap_or_else(|| Identity::service().id);
+    if !real.has_permission(Permission::Impersonate) {
+        warn!(
+            "{} attempted to act as {} without permission",
+            real_id, target
+        );
+        return Err(format!(
+            "Acting as another user requires {}",
+            Permission::Impersonate.as_str()
+        ));
+    }
+    let identity = Identity {
+        id: target.to_string(),
+        email: if target.contains('@') {
+            Some(target.to_lowercase())
+        } else {
+            None
+        },
+        name: None,
+    };
+    let role = stored_role(clients, &identity)?.role.or(*DEFAULT_ROLE);
+    let permissions = role
+        .map(|role| role.permissions())
+        .unwrap_or_default()
+        .intersection(&real.permissions)
+        .copied()
+        .collect();
+    info!("{} is acting as {}", real_id, target);
+    Ok(Principal {
+        identity: Some(identity),
+        role,
+        permissions,
+        groups: vec![],
+        tenant: real.tenant,
+        tenants: vec![],
+        impersonated_by: Some(real.identity.unwrap_or_else(Identity::service)),
+    })
+}
@@ -0,0 +1,302 @@
+use actix_web::client::Client;
+use jsonwebtoken::{
+    dangerous_unsafe_decode, decode, decode_header, Algorithm, DecodingKey, Validation,
+};
+use log::{error, info, warn};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::sync::RwLock;
+
+use crate::auth::{Claims, REQUIRED_EMAIL_DOMAIN};
+
+lazy_static! {
+    static ref GOOGLE_CLIENT_ID: Option<String> = env::var("GOOGLE_CLIENT_ID").ok();
+}
+
+/// Where the signing keys of an issuer are published
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum JwksSource {
+    /// A JWKS document, e.g. `https://www.googleapis.com/oauth2/v3/certs`
+    Url { url: String },
+    /// An OpenID Connect discovery document pointing at the JWKS through `jwks_uri`
+    Discovery { url: String },
+    /// A JWKS document on the local file system
+    File { path: String },
+}
+
+/// Claims a token must carry beyond a valid signature
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ClaimRules {
+    /// Required `hd` claim or email domain
+    pub domain: Option<String>,
+    /// The caller must belong to at least one of these groups
+    #[serde(default)]
+    pub groups: Vec<String>,
+    /// Name of the claim holding the groups, defaults to `groups`
+    pub groups_claim: Option<String>,
+    /// The token must be granted all of these scopes through `scope` or `scp`
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct IssuerConfig {
+    /// The `iss` claim of tokens minted by this issuer
+    pub issuer: String,
+    /// Other `iss` values used by the same issuer
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    pub jwks: Vec<JwksSource>,
+    /// Accepted `aud` values, the audience is not checked when empty
+    #[serde(default)]
+    pub audiences: Vec<String>,
+    #[serde(default)]
+    pub rules: ClaimRules,
+}
+
+impl IssuerConfig {
+    /// Google sign-in, using `CERTS`, `REQUIRED_EMAIL_DOMAIN` and `GOOGLE_CLIENT_ID`
+    fn google() -> IssuerConfig {
+        let jwks = dotenv::var("CERTS")
+            .unwrap_or("https://www.googleapis.com/oauth2/v2/certs".to_owned())
+            .split(",")
+            .map(|url| JwksSource::Url {
+                url: url.trim().to_owned(),
+            })
+            .collect();
+        IssuerConfig {
+            issuer: "https://accounts.google.com".to_string(),
+            aliases: vec!["accounts.google.com".to_string()],
+            jwks,
+            audiences: GOOGLE_CLIENT_ID.iter().cloned().collect(),
+            rules: ClaimRules {
+                domain: Some(REQUIRED_EMAIL_DOMAIN.to_string()),
+                ..ClaimRules::default()
+            },
+        }
+    }
+
+    fn matches(&self, iss: &str) -> bool {
+        self.issuer == iss || self.aliases.iter().any(|alias| alias == iss)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct Jwk {
+    kid: String,
+    kty: String,
+    alg: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct DiscoveryDocument {
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct UnverifiedClaims {
+    iss: String,
+}
+
+struct TrustedIssuer {
+    config: IssuerConfig,
+    keys: RwLock<HashMap<String, Jwk>>,
+}
+
+/// All issuers whose tokens are accepted, along with their current signing keys
+pub struct Issuers {
+    issuers: Vec<TrustedIssuer>,
+}
+
+impl Issuers {
+    pub fn new(configs: Vec<IssuerConfig>) -> Issuers {
+        Issuers {
+            issuers: configs
+                .into_iter()
+                .map(|config| TrustedIssuer {
+                    config,
+                    keys: RwLock::new(HashMap::new()),
+                })
+                .collect(),
+        }
+    }
+
+    /// Reads the issuers from `TRUSTED_ISSUERS` (JSON) or the file at `TRUSTED_ISSUERS_FILE`,
+    /// defaulting to Google sign-in.
+    pub fn from_env() -> Issuers {
+        let configs = match (
+            env::var("TRUSTED_ISSUERS").ok(),
+            env::var("TRUSTED_ISSUERS_FILE").ok(),
+        ) {
+            (Some(json), _) => {
+                serde_json::from_str(&json).expect("Unable to parse TRUSTED_ISSUERS")
+            }
+            (None, Some(path)) => {
+                let json = fs::read_to_string(&path)
+                    .expect(&format!("Unable to read TRUSTED_ISSUERS_FILE {}", path));
+                serde_json::from_str(&json).expect("Unable to parse TRUSTED_ISSUERS_FILE")
+            }
+            (None, None) => vec![IssuerConfig::google()],
+        };
+        Issuers::new(configs)
+    }
+
+    /// Pulls down the signing keys of every issuer
+    pub async fn refresh(&self) {
+        for issuer in &self.issuers {
+            let mut keys = HashMap::new();
+            for source in &issuer.config.jwks {
+                match fetch_keys(source).await {
+                    Ok(set) => keys.extend(set.keys.into_iter().map(|key| (key.kid.clone(), key))),
+                    Err(e) => error!(
+                        "Unable to load keys for issuer {} from {:?}: {}",
+                        issuer.config.issuer, source, e
+                    ),
+                }
+            }
+            info!(
+                "Loaded {} keys for issuer {}",
+                keys.len(),
+                issuer.config.issuer
+            );
+            if !keys.is_empty() {
+                *issuer.keys.write().unwrap() = keys;
+            }
+        }
+    }
+
+    /// Validates the token against the issuer named by its `iss` claim. The `iss` of the
+    /// returned claims is the configured issuer rather than any of its aliases.
+    pub fn validate(&self, token: &str) -> Result<Claims, String> {
+        let header = decode_header(token).map_err(|e| e.to_string())?;
+        let iss = dangerous_unsafe_decode::<UnverifiedClaims>(token)
+            .map_err(|e| e.to_string())?
+            .claims
+            .iss;
+        let issuer = self
+            .issuers
+            .iter()
+            .find(|issuer| issuer.config.matches(&iss))
+            .ok_or(format!("Untrusted issuer {}", iss))?;
+
+        let kid = header.kid.ok_or("Token is missing a key id")?;
+        let keys = issuer.keys.read().unwrap();
+        let key = keys.get(&kid).ok_or(format!("Unknown key id {}", kid))?;
+        if key.kty != "RSA" {
+            return Err(format!("Unsupported key type {}", key.kty));
+        }
+        let algorithm = match header.alg {
+            Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 => header.alg,
+            other => return Err(format!("Unsupported algorithm {:?}", other)),
+        };
+        if let Some(alg) = &key.alg {
+            if alg != &format!("{:?}", algorithm) {
+                return Err(format!("Key {} is not used for {:?}", kid, algorithm));
+            }
+        }
+        let decoding_key = DecodingKey::from_rsa_components(
+            key.n.as_ref().ok_or("Key is missing its modulus")?,
+            key.e.as_ref().ok_or("Key is missing its exponent")?,
+        );
+
+        let mut validation = Validation::new(algorithm);
+        if !issuer.config.audiences.is_empty() {
+            validation.set_audience(&issuer.config.audiences);
+        }
+        let mut claims = decode::<Claims>(token, &decoding_key, &validation)
+            .map_err(|e| e.to_string())?
+            .claims;
+        check_rules(&issuer.config.rules, &claims)?;
+        claims.iss = issuer.config.issuer.clone();
+        Ok(claims)
+    }
+}
+
+async fn fetch_json<T: serde::de::DeserializeOwned>(url: &str) -> Result<T, String> {
+    Client::default()
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?
+        .json::<T>()
+        .limit(1024 * 1024)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+async fn fetch_keys(source: &JwksSource) -> Result<JwkSet, String> {
+    match source {
+        JwksSource::Url { url } => fetch_json(url).await,
+        JwksSource::Discovery { url } => {
+            let discovery: DiscoveryDocument = fetch_json(url).await?;
+            fetch_json(&discovery.jwks_uri).await
+        }
+        JwksSource::File { path } => {
+            let json = fs::read_to_string(path).map_err(|e| e.to_string())?;
+            serde_json::from_str(&json).map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// Reads a claim holding either a space separated string or an array of strings
+pub(crate) fn string_list(value: Option<&Value>) -> Vec<String> {
+    match value {
+        Some(Value::String(value)) => value.split_whitespace().map(|s| s.to_string()).collect(),
+        Some(Value::Array(values)) => values
+            .iter()
+            .filter_map(|value| value.as_str().map(|s| s.to_string()))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Issuers send `email_verified` as a boolean, some as a string
+fn email_verified(claims: &Claims) -> bool {
+    match claims.extra.get("email_verified") {
+        Some(Value::Bool(verified)) => *verified,
+        Some(Value::String(verified)) => verified == "true",
+        _ => false,
+    }
+}
+
+fn check_rules(rules: &ClaimRules, claims: &Claims) -> Result<(), String> {
+    if let Some(domain) = &rules.domain {
+        if !email_verified(claims) {
+            return Err("Token email is not verified".to_string());
+        }
+        let email_domain = claims
+            .email
+            .as_ref()
+            .and_then(|email| email.rsplit('@').next());
+        if claims.hd.as_ref() != Some(domain) && email_domain != Some(domain.as_str()) {
+            return Err(format!("Token is not from the {} domain", domain));
+        }
+    }
+    if !rules.groups.is_empty() {
+        let claim = rules.groups_claim.as_deref().unwrap_or("groups");
+        let groups = string_list(claims.extra.get(claim));
+        if !rules.groups.iter().any(|group| groups.contains(group)) {
+            return Err("Token is not in any of the required groups".to_string());
+        }
+    }
+    if !rules.scopes.is_empty() {
+        let mut scopes = string_list(claims.extra.get("scope"));
+        scopes.extend(string_list(claims.extra.get("scp")));
+        if let Some(missing) = rules.scopes.iter().find(|scope| !scopes.contains(scope)) {
+            warn!("Token for {} is missing scope {}", claims.sub, missing);
+            return Err(format!("Token is missing scope {}", missing));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,331 @@
+mod acl;
+mod api_keys;
+mod claims;
+mod impersonation;
+mod issuers;
+mod revocations;
+mod roles;
+mod scope;
+mod tenancy;
+
+pub use acl::*;
+pub use api_keys::*;
+pub use claims::*;
+pub use impersonation::*;
+pub use issuers::*;
+pub use revocations::*;
+pub use roles::*;
+pub use scope::*;
+pub use tenancy::*;
+
+use bson::doc;
+use cached::TimedCache;
+use juniper::{graphql_value, FieldError};
+use log::warn;
+use mongodb_base_service::{BaseService, ID};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::env;
+
+use crate::db::{id_string, Clients};
+
+lazy_static! {
+    pub static ref DISABLE_AUTH: u8 = env::var("DISABLE_AUTH")
+        .unwrap_or("".to_string())
+        .parse()
+        .unwrap_or(0);
+    pub static ref REQUIRED_EMAIL_DOMAIN: String =
+        env::var("REQUIRED_EMAIL_DOMAIN").unwrap_or("gmail.com".to_string());
+    static ref ROLE_CACHE_TTL: u64 = env::var("ROLE_CACHE_TTL")
+        .unwrap_or("".to_string())
+        .parse()
+        .unwrap_or(60);
+    static ref ROLE_CLAIM_MAPPINGS: Vec<ClaimMapping> =
+        parse_claim_mappings(&env::var("ROLE_CLAIM_MAPPINGS").unwrap_or("".to_string()));
+    static ref REQUIRE_AUTH_FOR_READS: u8 = env::var("REQUIRE_AUTH_FOR_READS")
+        .unwrap_or("".to_string())
+        .parse()
+        .unwrap_or(0);
+    static ref GROUPS_CLAIM: String = env::var("GROUPS_CLAIM").unwrap_or("groups".to_string());
+    static ref SERVICE_IDENTITY: String =
+        env::var("SERVICE_IDENTITY").unwrap_or("{{project-name}}".to_string());
+}
+
+/// The verified identity of a caller
+#[derive(juniper::GraphQLObject, Clone, Debug, Serialize, Deserialize)]
+#[graphql(description = "The identity of a caller, captured at write time")]
+pub struct Identity {
+    pub id: String,
+    pub email: Option<String>,
+    pub name: Option<String>,
+}
+
+impl Identity {
+    /// The identity used for writes when auth is disabled
+    pub fn service() -> Identity {
+        Identity {
+            id: SERVICE_IDENTITY.to_string(),
+            email: None,
+            name: None,
+        }
+    }
+
+    /// An identity only known by id, e.g. a client supplied `createdById`
+    pub fn from_id(id: &ID) -> Identity {
+        Identity {
+            id: id_string(id),
+            email: None,
+            name: None,
+        }
+    }
+
+    /// The id stored on the node details of written documents
+    pub fn node_id(&self) -> ID {
+        ID::String(self.id.clone())
+    }
+
+    fn from_claims(claims: &Claims) -> Identity {
+        Identity {
+            id: claims.sub.clone(),
+            email: claims.email.clone(),
+            name: claims.name.clone(),
+        }
+    }
+}
+
+/// The caller of a request along with everything it is allowed to do
+#[derive(Clone, Debug)]
+pub struct Principal {
+    pub identity: Option<Identity>,
+    pub role: Option<Role>,
+    pub permissions: HashSet<Permission>,
+    /// Groups of the caller, matched against `group:<name>` ACL entries
+    pub groups: Vec<String>,
+    /// The real caller when acting as `identity` through `x-act-as`
+    pub impersonated_by: Option<Identity>,
+    /// The tenant every read and write of the request is scoped to
+    pub tenant: Option<String>,
+    /// Tenants the caller is a member of through the `user_roles` collection, which it may pick
+    /// with `x-tenant-id`
+    pub tenants: Vec<String>,
+}
+
+impl Principal {
+    /// Anonymous callers get `ANONYMOUS_PERMISSIONS`, or nothing when `REQUIRE_AUTH_FOR_READS` is set
+    pub fn anonymous() -> Principal {
+        let permissions = if *REQUIRE_AUTH_FOR_READS == 1 {
+            HashSet::new()
+        } else {
+            ANONYMOUS_PERMISSIONS.clone()
+        };
+        Principal {
+            identity: None,
+            role: None,
+            permissions,
+            groups: vec![],
+            impersonated_by: None,
+            tenant: None,
+            tenants: vec![],
+        }
+    }
+
+    /// Used when auth is disabled, every permission is granted
+    pub fn unrestricted() -> Principal {
+        Principal {
+            identity: None,
+            role: Some(Role::Admin),
+            permissions: Permission::all().into_iter().collect(),
+            groups: vec![],
+            impersonated_by: None,
+            tenant: None,
+            tenants: vec![],
+        }
+    }
+
+    fn with_role(
+        identity: Identity,
+        role: Option<Role>,
+        groups: Vec<String>,
+        tenants: Vec<String>,
+    ) -> Principal {
+        Principal {
+            identity: Some(identity),
+            role,
+            permissions: role.map(|role| role.permissions()).unwrap_or_default(),
+            groups,
+            impersonated_by: None,
+            tenant: None,
+            tenants,
+        }
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        self.identity.is_some()
+    }
+
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.permissions.contains(&permission)
+    }
+}
+
+/// A claim value that grants a role, e.g. `email:alice@example.com=admin`
+struct ClaimMapping {
+    claim: String,
+    value: String,
+    role: Role,
+}
+
+fn parse_claim_mappings(value: &str) -> Vec<ClaimMapping> {
+    value
+        .split(';')
+        .filter_map(|entry| {
+            let mut parts = entry.splitn(2, '=');
+            let mut claim = parts.next()?.splitn(2, ':');
+            Some(ClaimMapping {
+                claim: claim.next()?.trim().to_string(),
+                value: claim.next()?.trim().to_string(),
+                role: Role::parse(parts.next()?)?,
+            })
+        })
+        .collect()
+}
+
+fn claim_matches(claims: &Value, mapping: &ClaimMapping) -> bool {
+    match claims.get(&mapping.claim) {
+        Some(Value::String(value)) => value == &mapping.value,
+        Some(Value::Array(values)) => values
+            .iter()
+            .any(|value| value.as_str() == Some(mapping.value.as_str())),
+        _ => false,
+    }
+}
+
+fn highest_role(roles: Vec<Role>) -> Option<Role> {
+    [Role::Admin, Role::Editor, Role::Viewer]
+        .iter()
+        .find(|role| roles.contains(role))
+        .copied()
+}
+
+/// What the `user_roles` collection holds about a caller
+#[derive(Clone, Debug, Default)]
+pub struct StoredRole {
+    pub role: Option<Role>,
+    pub tenants: Vec<String>,
+}
+
+cached_key_result! {
+    STORED_ROLES: TimedCache<String, StoredRole> =
+        TimedCache::with_lifespan_and_capacity(*ROLE_CACHE_TTL, 10000);
+    Key = { format!("{:?}", identity.id) };
+    fn stored_role(clients: &Clients, identity: &Identity) -> Result<StoredRole, String> = {
+        let service = clients.mongo.get_mongo_service("user_roles").unwrap();
+        let mut keys = vec![identity.id.clone()];
+        if let Some(email) = &identity.email {
+            keys.push(email.to_lowercase());
+        }
+        match service
+            .data_source()
+            .find_one(doc! { "_id": { "$in": keys } }, None)
+        {
+            Ok(Some(document)) => Ok(StoredRole {
+                role: document.get_str("role").ok().and_then(Role::parse),
+                tenants: document
+                    .get_array("tenants")
+                    .map(|tenants| {
+                        tenants
+                            .iter()
+                            .filter_map(|tenant| tenant.as_str().map(|tenant| tenant.to_string()))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            }),
+            Ok(None) => Ok(StoredRole::default()),
+            Err(e) => {
+                // failed lookups aren't cached, and the caller is denied rather than given
+                // another role until the role store is back
+                warn!("Unable to look up stored role for {}: {}", identity.id, e);
+                Err("Unable to look up the role of the caller".to_string())
+            }
+        }
+    }
+}
+
+/// Claims are only extracted once they are verified against a trusted issuer
+pub fn validate_claims(claims: &Option<Claims>) -> bool {
+    claims.is_some()
+}
+
+/// Resolves the principal for a request. Bearer tokens take precedence over API keys, whose
+/// permissions are their scopes. Roles of token callers come from the `user_roles` collection
+/// first, then from `ROLE_CLAIM_MAPPINGS`, falling back to `DEFAULT_ROLE`. Requests are denied
+/// while the `user_roles` collection can't be read.
+pub fn authenticate(
+    clients: &Clients,
+    claims: &Option<Claims>,
+    api_key: &Option<ApiKeyHeader>,
+) -> Result<Principal, String> {
+    if *DISABLE_AUTH == 1 {
+        return Ok(Principal::unrestricted());
+    }
+    let claims = match (claims, api_key) {
+        (Some(claims), _) => claims,
+        (None, Some(ApiKeyHeader(secret))) => {
+            return Ok(authenticate_api_key(clients, secret).unwrap_or_else(Principal::anonymous))
+        }
+        (None, None) => return Ok(Principal::anonymous()),
+    };
+    let identity = Identity::from_claims(claims);
+    let claim_values = serde_json::to_value(claims).unwrap_or(Value::Null);
+    let stored = stored_role(clients, &identity)?;
+    let role = stored.role.or_else(|| {
+        let mapped = ROLE_CLAIM_MAPPINGS
+            .iter()
+            .filter(|mapping| claim_matches(&claim_values, mapping))
+            .map(|mapping| mapping.role)
+            .collect();
+        highest_role(mapped).or(*DEFAULT_ROLE)
+    });
+    let groups = string_list(claims.extra.get(GROUPS_CLAIM.as_str()));
+    Ok(Principal::with_role(identity, role, groups, stored.tenants))
+}
+
+pub fn unauthenticated() -> FieldError {
+    FieldError::new(
+        "Unauthorized",
+        graphql_value!({ "code": "UNAUTHENTICATED" }),
+    )
+}
+
+pub fn forbidden(permission: Permission) -> FieldError {
+    FieldError::new(
+        format!("Forbidden: requires {}", permission.as_str()),
+        graphql_value!({ "code": "FORBIDDEN" }),
+    )
+}
+
+/// The identity recorded as the author of a write. Client supplied ids are only honoured for
+/// callers holding the impersonate permission.
+pub fn resolve_author(
+    principal: &Principal,
+    supplied_id: Option<ID>,
+) -> Result<Identity, FieldError> {
+    match supplied_id {
+        Some(id) if principal.has_permission(Permission::Impersonate) => Ok(Identity::from_id(&id)),
+        Some(_) => Err(forbidden(Permission::Impersonate)),
+        None => Ok(principal.identity.clone().unwrap_or_else(Identity::service)),
+    }
+}
+
+/// Checks that the principal may resolve the given root field
+pub fn authorize(principal: &Principal, field: &str) -> Result<(), FieldError> {
+    let permission = required_permission(field);
+    if principal.has_permission(permission) {
+        Ok(())
+    } else if !principal.is_authenticated() {
+        Err(unauthenticated())
+    } else {
+        Err(forbidden(permission))
+    }
+}
@@ -0,0 +1,172 @@
+use chrono::{DateTime, TimeZone, Utc};
+use log::{info, warn};
+use mongodb_base_service::{BaseService, Node, NodeDetails, ID};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+
+use crate::auth::{Claims, Identity};
+use crate::db::Clients;
+use crate::schema::Context;
+
+lazy_static! {
+    static ref REVOKED: RwLock<RevocationList> = RwLock::new(RevocationList::default());
+}
+
+/// A stored revocation of a single token (`jti`), or of the tokens of a subject
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Revocation {
+    #[serde(rename = "_id")]
+    pub id: ID,
+    pub node: NodeDetails,
+    pub jti: Option<String>,
+    pub subject: Option<String>,
+    /// The issuer of the subject, subject revocations stored without one apply to every issuer
+    pub issuer: Option<String>,
+    /// Tokens of the subject issued before this time are rejected, every token when missing
+    pub issued_before: Option<i64>,
+    pub reason: Option<String>,
+}
+
+impl Node for Revocation {
+    fn node(&self) -> &NodeDetails {
+        &self.node
+    }
+}
+
+#[juniper::object(Context = Context, description = "A revoked token or subject")]
+impl Revocation {
+    fn id(&self) -> &ID {
+        &self.id
+    }
+
+    fn date_created(&self) -> Option<DateTime<Utc>> {
+        self.node.date_created()
+    }
+
+    fn jti(&self) -> &Option<String> {
+        &self.jti
+    }
+
+    fn subject(&self) -> &Option<String> {
+        &self.subject
+    }
+
+    fn issuer(&self) -> &Option<String> {
+        &self.issuer
+    }
+
+    fn issued_before(&self) -> Option<DateTime<Utc>> {
+        self.issued_before.map(|issued| Utc.timestamp(issued, 0))
+    }
+
+    fn reason(&self) -> &Option<String> {
+        &self.reason
+    }
+}
+
+#[derive(Serialize)]
+pub struct RevocationInsert {
+    pub jti: Option<String>,
+    pub subject: Option<String>,
+    pub issuer: Option<String>,
+    pub issued_before: Option<i64>,
+    pub reason: Option<String>,
+    pub impersonated_by: Option<Identity>,
+}
+
+/// The in-memory view of the `revocations` collection checked on every request
+#[derive(Default)]
+pub struct RevocationList {
+    jtis: HashSet<String>,
+    /// Per issuer and subject, the time before which tokens are revoked, `None` revokes every
+    /// token. Subjects are only unique within their issuer.
+    subjects: HashMap<(Option<String>, String), Option<i64>>,
+}
+
+impl RevocationList {
+    pub fn revoke_token(&mut self, jti: &str) {
+        self.jtis.insert(jti.to_string());
+    }
+
+    pub fn revoke_subject(
+        &mut self,
+        issuer: Option<&str>,
+        subject: &str,
+        issued_before: Option<i64>,
+    ) {
+        let key = (issuer.map(|issuer| issuer.to_string()), subject.to_string());
+        let entry = self.subjects.entry(key).or_insert(issued_before);
+        *entry = match (*entry, issued_before) {
+            (Some(current), Some(issued_before)) => Some(current.max(issued_before)),
+            _ => None,
+        };
+    }
+
+    fn add(&mut self, revocation: &Revocation) {
+        if let Some(jti) = &revocation.jti {
+            self.revoke_token(jti);
+        }
+        if let Some(subject) = &revocation.subject {
+            let issuer = revocation.issuer.as_deref();
+            self.revoke_subject(issuer, subject, revocation.issued_before);
+        }
+    }
+
+    /// Tokens without an `iat` can't be proven to postdate a subject revocation
+    pub fn is_revoked(&self, claims: &Claims) -> bool {
+        if let Some(jti) = &claims.jti {
+            if self.jtis.contains(jti) {
+                return true;
+            }
+        }
+        [Some(claims.iss.clone()), None].iter().any(|issuer| {
+            match self.subjects.get(&(issuer.clone(), claims.sub.clone())) {
+                Some(Some(issued_before)) => {
+                    claims.iat.map_or(true, |iat| (iat as i64) < *issued_before)
+                }
+                Some(None) => true,
+                None => false,
+            }
+        })
+    }
+}
+
+pub fn is_revoked(claims: &Claims) -> bool {
+    REVOKED.read().unwrap().is_revoked(claims)
+}
+
+/// Applies a new revocation right away rather than waiting for the next refresh
+pub fn record_revocation(revocation: &Revocation) {
+    REVOKED.write().unwrap().add(revocation);
+}
+
+/// Reloads the revocation list from the `revocations` collection
+pub fn refresh_revocations(clients: &Clients) {
+    let service = clients.mongo.get_mongo_service("revocations").unwrap();
+    let cursor = match service.data_source().find(None, None) {
+        Ok(cursor) => cursor,
+        Err(e) => {
+            warn!("Unable to load revocations: {}", e);
+            return;
+        }
+    };
+    let mut list = RevocationList::default();
+    let mut count = 0;
+    for document in cursor {
+        match document.map_err(|e| e.to_string()).and_then(|document| {
+            bson::from_bson::<Revocation>(bson::Bson::Document(document)).map_err(|e| e.to_string())
+        }) {
+            Ok(revocation) => {
+                list.add(&revocation);
+                count += 1;
+            }
+            Err(e) => {
+                warn!("Unable to read revocation: {}", e);
+                return;
+            }
+        }
+    }
+    info!("Loaded {} revocations", count);
+    *REVOKED.write().unwrap() = list;
+}
@@ -0,0 +1,178 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::env;
+
+lazy_static! {
+    static ref ROLE_PERMISSIONS: HashMap<Role, HashSet<Permission>> =
+        parse_role_permissions(&env::var("ROLE_PERMISSIONS").unwrap_or("".to_string()));
+    static ref FIELD_PERMISSIONS: HashMap<String, Permission> =
+        parse_field_permissions(&env::var("FIELD_PERMISSIONS").unwrap_or("".to_string()));
+    pub static ref ANONYMOUS_PERMISSIONS: HashSet<Permission> = Permission::parse_list(
+        &env::var("ANONYMOUS_PERMISSIONS").unwrap_or("samples:read".to_string())
+    );
+    pub static ref DEFAULT_ROLE: Option<Role> =
+        Role::parse(&env::var("DEFAULT_ROLE").unwrap_or("editor".to_string()));
+}
+
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Editor,
+    Admin,
+}
+
+impl Role {
+    pub fn parse(value: &str) -> Option<Role> {
+        match value.trim().to_lowercase().as_str() {
+            "viewer" => Some(Role::Viewer),
+            "editor" => Some(Role::Editor),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+
+    /// Permissions granted to the role, either from `ROLE_PERMISSIONS` or the defaults
+    pub fn permissions(&self) -> HashSet<Permission> {
+        match ROLE_PERMISSIONS.get(self) {
+            Some(permissions) => permissions.clone(),
+            None => self.default_permissions(),
+        }
+    }
+
+    fn default_permissions(&self) -> HashSet<Permission> {
+        match self {
+            Role::Viewer => vec![Permission::ReadSamples],
+            Role::Editor => vec![
+                Permission::ReadSamples,
+                Permission::ReadUnpublished,
+                Permission::ReadAudit,
+                Permission::WriteSamples,
+            ],
+            Role::Admin => Permission::all(),
+        }
+        .into_iter()
+        .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub enum Permission {
+    ReadSamples,
+    /// Allows reading unpublished samples
+    ReadUnpublished,
+    /// Allows reading who created and updated a sample
+    ReadAudit,
+    WriteSamples,
+    DeleteSamples,
+    /// Allows writes to be recorded on behalf of another identity
+    Impersonate,
+    ManageApiKeys,
+    /// Bypasses sample ownership and ACLs
+    ManageAllSamples,
+    /// Allows revoking tokens and sessions
+    RevokeTokens,
+    /// Allows searching the audit log of every sample
+    ReadAuditLog,
+    /// Allows picking any tenant with `x-tenant-id`
+    AccessAllTenants,
+}
+
+impl Permission {
+    pub fn all() -> Vec<Permission> {
+        vec![
+            Permission::ReadSamples,
+            Permission::ReadUnpublished,
+            Permission::ReadAudit,
+            Permission::WriteSamples,
+            Permission::DeleteSamples,
+            Permission::Impersonate,
+            Permission::ManageApiKeys,
+            Permission::ManageAllSamples,
+            Permission::RevokeTokens,
+            Permission::ReadAuditLog,
+            Permission::AccessAllTenants,
+        ]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::ReadSamples => "samples:read",
+            Permission::ReadUnpublished => "samples:read_unpublished",
+            Permission::ReadAudit => "samples:read_audit",
+            Permission::WriteSamples => "samples:write",
+            Permission::DeleteSamples => "samples:delete",
+            Permission::Impersonate => "identity:impersonate",
+            Permission::ManageApiKeys => "apikeys:manage",
+            Permission::ManageAllSamples => "samples:manage_all",
+            Permission::RevokeTokens => "tokens:revoke",
+            Permission::ReadAuditLog => "audit:read",
+            Permission::AccessAllTenants => "tenants:access_all",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Permission> {
+        Permission::all()
+            .into_iter()
+            .find(|permission| permission.as_str() == value.trim())
+    }
+
+    /// Parses a comma or space separated list of permissions, ignoring unknown entries
+    pub fn parse_list(value: &str) -> HashSet<Permission> {
+        value
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter_map(Permission::parse)
+            .collect()
+    }
+}
+
+/// The permission required to resolve a root `Query` or `Mutation` field
+pub fn required_permission(field: &str) -> Permission {
+    if let Some(permission) = FIELD_PERMISSIONS.get(field) {
+        return *permission;
+    }
+    match field {
+        "createSample"
+        | "updateSample"
+        | "addValuesToSample"
+        | "updateValueForSample"
+        | "removeValueFromSample"
+        | "addAvailabilityWindow"
+        | "removeAvailabilityWindow"
+        | "shareSample"
+        | "unshareSample"
+        | "transferSampleOwnership" => Permission::WriteSamples,
+        "deleteSample" => Permission::DeleteSamples,
+        "apiKeys" | "createApiKey" | "rotateApiKey" | "revokeApiKey" => Permission::ManageApiKeys,
+        "auditTrail" => Permission::ReadAudit,
+        "auditLog" => Permission::ReadAuditLog,
+        "revocations" | "revokeToken" | "revokeSubject" | "deleteRevocation" => {
+            Permission::RevokeTokens
+        }
+        _ => Permission::ReadSamples,
+    }
+}
+
+/// Parses `viewer=samples:read;editor=samples:read,samples:write`
+fn parse_role_permissions(value: &str) -> HashMap<Role, HashSet<Permission>> {
+    value
+        .split(';')
+        .filter_map(|entry| {
+            let mut parts = entry.splitn(2, '=');
+            let role = Role::parse(parts.next()?)?;
+            Some((role, Permission::parse_list(parts.next()?)))
+        })
+        .collect()
+}
+
+/// Parses `deleteSample=samples:write,createSample=samples:delete`
+fn parse_field_permissions(value: &str) -> HashMap<String, Permission> {
+    value
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.splitn(2, '=');
+            let field = parts.next()?.trim().to_string();
+            Some((field, Permission::parse(parts.next()?)?))
+        })
+        .collect()
+}
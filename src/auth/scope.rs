@@ -0,0 +1,42 @@
+use bson::{doc, Document};
+
+use crate::auth::{acl_read_filter, tenant_filter, Permission, Principal};
+use crate::db::and_filters;
+
+/// The samples a principal is allowed to read. The key partitions cached results so that
+/// restricted samples never leak across callers.
+#[derive(Clone, Debug)]
+pub struct ReadScope {
+    pub filter: Option<Document>,
+    pub key: String,
+}
+
+/// Combines the tenant, published and ACL restrictions of a principal. Callers bound by ACLs
+/// get a key of their own since they may each see different samples.
+pub fn read_scope(principal: &Principal) -> ReadScope {
+    let tenant = Some(tenant_filter(principal));
+    let tenant_key = principal.tenant.as_deref().unwrap_or("-");
+    let (published, key) = if principal.has_permission(Permission::ReadUnpublished) {
+        (None, "all".to_string())
+    } else {
+        (
+            Some(doc! { "published": { "$ne": false } }),
+            "published".to_string(),
+        )
+    };
+    match acl_read_filter(principal) {
+        Some(acl) => ReadScope {
+            filter: and_filters(vec![tenant, published, Some(acl)]),
+            key: format!(
+                "{}/{}:{}",
+                tenant_key,
+                key,
+                principal.acl_principals().join("|")
+            ),
+        },
+        None => ReadScope {
+            filter: and_filters(vec![tenant, published]),
+            key: format!("{}/{}", tenant_key, key),
+        },
+    }
+}
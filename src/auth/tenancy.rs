@@ -0,0 +1,86 @@
+use actix_web::dev::Payload;
+use actix_web::{Error, FromRequest, HttpRequest};
+use bson::{doc, Bson, Document};
+use futures::future::{err, ok, Ready};
+use std::env;
+
+use crate::auth::{Claims, Permission, Principal};
+
+lazy_static! {
+    static ref TENANT_CLAIM: String = env::var("TENANT_CLAIM").unwrap_or("tenant".to_string());
+}
+
+/// The tenant requested through `x-tenant-id`
+pub struct TenantHeader(pub String);
+
+impl FromRequest for TenantHeader {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ();
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        match req.headers().get("x-tenant-id").map(|value| value.to_str()) {
+            Some(Ok(value)) if !value.trim().is_empty() => {
+                ok(TenantHeader(value.trim().to_string()))
+            }
+            _ => err(actix_web::error::ErrorBadRequest(
+                "Missing x-tenant-id header",
+            )),
+        }
+    }
+}
+
+/// Tenant ids end up in database names, so they are restricted to letters, digits, `-` and `_`
+pub fn validate_tenant(tenant: &str) -> Result<(), String> {
+    if !tenant.is_empty()
+        && tenant.len() <= 64
+        && tenant
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        Ok(())
+    } else {
+        Err(format!("Invalid tenant {}", tenant))
+    }
+}
+
+/// Resolves the tenant of a request. A tenant bound to the caller, through the `TENANT_CLAIM`
+/// of its token or its API key, is used by default. `x-tenant-id` may only pick that tenant,
+/// one the caller is a member of, or any tenant with `tenants:access_all`.
+pub fn resolve_tenant(
+    principal: &Principal,
+    claims: &Option<Claims>,
+    header: &Option<TenantHeader>,
+) -> Result<Option<String>, String> {
+    let bound = claims
+        .as_ref()
+        .and_then(|claims| claims.extra.get(TENANT_CLAIM.as_str()))
+        .and_then(|tenant| tenant.as_str())
+        .map(|tenant| tenant.to_string())
+        .or_else(|| principal.tenant.clone());
+    let requested = header.as_ref().map(|TenantHeader(tenant)| tenant.clone());
+    let tenant = match (bound, requested) {
+        (bound, Some(requested)) => {
+            let member = bound.as_ref() == Some(&requested)
+                || principal.tenants.contains(&requested)
+                || principal.has_permission(Permission::AccessAllTenants);
+            if !member {
+                return Err(format!("Not a member of tenant {}", requested));
+            }
+            requested
+        }
+        (Some(tenant), None) => tenant,
+        (None, None) => return Ok(None),
+    };
+    validate_tenant(&tenant)?;
+    Ok(Some(tenant))
+}
+
+/// Restricts documents to the tenant of the principal. Callers without a tenant only see
+/// documents that don't belong to any tenant.
+pub fn tenant_filter(principal: &Principal) -> Document {
+    match &principal.tenant {
+        Some(tenant) => doc! { "tenant_id": tenant.clone() },
+        None => doc! { "tenant_id": Bson::Null },
+    }
+}
@@ -0,0 +1,320 @@
+use bson::{doc, Bson, Document};
+use log::{info, warn};
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::options::UpdateOptions;
+use mongodb::{Client, Database};
+use mongodb_base_service::ID;
+use std::env;
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+use super::{
+    invalidate_local, invalidate_shared, write_tags, SampleWrite, Tag, CACHE_CAPACITY, CACHE_TTL,
+};
+use crate::db::id_string;
+
+/// Error codes of deployments without change streams: standalone servers and servers
+/// predating them
+const UNSUPPORTED: [i32; 2] = [40573, 40324];
+
+/// Error codes of resume tokens the oplog no longer reaches or that don't fit the stream
+const STALE_TOKEN: [i32; 3] = [260, 280, 286];
+
+/// How long a `getMore` waits for changes before returning an empty batch
+const AWAIT_MS: i32 = 1000;
+
+/// The tenant whose database `<db_name>-<tenant>` a change was made in, `None` for the shared
+/// database
+fn database_tenant(event: &Document, db_name: &str) -> Option<String> {
+    let database = event.get_document("ns").ok()?.get_str("db").ok()?;
+    let tenant = database.strip_prefix(db_name)?.strip_prefix('-')?;
+    Some(tenant.to_string())
+}
+
+/// What a change to a sample made in the databases of `db_name` evicts, `None` when it can't
+/// tell and every result has to go. Deletes don't carry the name of the sample, which only
+/// matters to results it was part of, nor its tenant, which is only known in tenant databases.
+/// Updates lose the previous name likewise.
+pub fn change_tags(event: &Document, db_name: &str) -> Option<Vec<Tag>> {
+    let write = match event.get_str("operationType").ok()? {
+        "insert" => SampleWrite::Created,
+        "update" | "replace" => SampleWrite::Updated,
+        "delete" => SampleWrite::Deleted,
+        _ => return None,
+    };
+    let id = event.get_document("documentKey").ok()?.get("_id")?;
+    let id: ID = bson::from_bson(id.clone()).ok()?;
+    if write == SampleWrite::Deleted {
+        let tenant = Some(database_tenant(event, db_name)?);
+        return Some(write_tags(&tenant, write, &id_string(&id), &None, &None));
+    }
+    // updates are looked up after the fact, the sample may be gone by then
+    let sample = event.get_document("fullDocument").ok()?;
+    let tenant = sample
+        .get_str("tenant_id")
+        .ok()
+        .map(|tenant| tenant.to_string());
+    let after = Some(sample.clone());
+    Some(write_tags(&tenant, write, &id_string(&id), &None, &after))
+}
+
+fn is_duplicate_key(error: &mongodb::error::Error) -> bool {
+    match error.kind.as_ref() {
+        ErrorKind::WriteError(WriteFailure::WriteError(error)) => error.code == 11000,
+        ErrorKind::CommandError(error) => error.code == 11000,
+        _ => false,
+    }
+}
+
+struct WatchError {
+    code: Option<i32>,
+    message: String,
+}
+
+impl WatchError {
+    fn has_code(&self, codes: &[i32]) -> bool {
+        self.code.map_or(false, |code| codes.contains(&code))
+    }
+}
+
+impl fmt::Display for WatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<mongodb::error::Error> for WatchError {
+    fn from(error: mongodb::error::Error) -> WatchError {
+        let code = match error.kind.as_ref() {
+            ErrorKind::CommandError(command) => Some(command.code),
+            _ => None,
+        };
+        WatchError {
+            code,
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<bson::ordered::ValueAccessError> for WatchError {
+    fn from(error: bson::ordered::ValueAccessError) -> WatchError {
+        WatchError {
+            code: None,
+            message: format!("Unexpected change stream reply: {:?}", error),
+        }
+    }
+}
+
+/// Runs a command, turning replies that aren't ok into errors
+fn run(database: &Database, command: Document) -> Result<Document, WatchError> {
+    let reply = database.run_command(command, None)?;
+    let ok = match reply.get("ok") {
+        Some(Bson::FloatingPoint(ok)) => *ok == 1.,
+        Some(Bson::I32(ok)) => *ok == 1,
+        Some(Bson::I64(ok)) => *ok == 1,
+        _ => false,
+    };
+    if ok {
+        return Ok(reply);
+    }
+    Err(WatchError {
+        code: reply.get_i32("code").ok(),
+        message: reply
+            .get_str("errmsg")
+            .unwrap_or("command failed")
+            .to_string(),
+    })
+}
+
+/// Follows the changes made to samples through any replica, or directly in the database
+struct SampleWatcher {
+    client: Client,
+    db_name: String,
+    /// Watches every database of the deployment in database-per-tenant mode
+    tenant_databases: bool,
+    /// The position of this watcher, preferred over the stored one when resuming since another
+    /// replica may have stored a later one
+    token: Option<Bson>,
+    /// The last change seen by this watcher, which the stored token has to be at for it to take
+    /// over the next change
+    last_change: Option<Bson>,
+}
+
+impl SampleWatcher {
+    fn token_id(&self) -> &'static str {
+        if self.tenant_databases {
+            "tenant-samples"
+        } else {
+            "samples"
+        }
+    }
+
+    fn load_token(&self) -> Option<Bson> {
+        let tokens = self
+            .client
+            .database(&self.db_name)
+            .collection("cache_resume_tokens");
+        match tokens.find_one(doc! { "_id": self.token_id() }, None) {
+            Ok(stored) => stored.and_then(|stored| stored.get("token").cloned()),
+            Err(e) => {
+                warn!("Unable to load the samples resume token: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Moves the stored token from `previous` to `token`, telling whether this watcher did. Every
+    /// replica sees the same changes, so only the first one to reach a change moves the token
+    /// past it.
+    fn store_token(&self, previous: &Option<Bson>, token: &Option<Bson>) -> bool {
+        let tokens = self
+            .client
+            .database(&self.db_name)
+            .collection("cache_resume_tokens");
+        let mut options = UpdateOptions::default();
+        options.upsert = Some(true);
+        let filter = doc! {
+            "_id": self.token_id(),
+            "token": previous.clone().unwrap_or(Bson::Null),
+        };
+        let update = doc! { "$set": {
+            "token": token.clone().unwrap_or(Bson::Null),
+            "updated_at": crate::schema::now() as i64,
+        } };
+        match tokens.update_one(filter, update, options) {
+            Ok(result) => result.modified_count == 1 || result.upserted_id.is_some(),
+            // the upsert of a replica that is behind collides with the stored token
+            Err(e) if is_duplicate_key(&e) => false,
+            Err(e) => {
+                warn!("Unable to store the samples resume token: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Evicts what a change affects from this replica, and from the shared cache when this
+    /// replica is the first to see the change, so the shared cache is evicted once per change
+    fn apply(&mut self, event: &Document) {
+        let tags = change_tags(event, &self.db_name);
+        invalidate_local(tags.as_deref());
+        let token = event.get("_id").cloned();
+        if self.store_token(&self.last_change, &token) {
+            invalidate_shared(tags.as_deref());
+        }
+        self.last_change = token.clone();
+        self.token = token;
+    }
+
+    /// Watches until the stream closes or fails
+    fn watch(&mut self) -> Result<(), WatchError> {
+        let token = match &self.token {
+            Some(token) => Some(token.clone()),
+            None => {
+                let stored = self.load_token().filter(|token| *token != Bson::Null);
+                self.last_change = stored.clone();
+                stored
+            }
+        };
+        let mut stream = doc! { "fullDocument": "updateLookup" };
+        if let Some(token) = &token {
+            stream.insert("resumeAfter", token.clone());
+        }
+        let mut pipeline = vec![];
+        let (database, aggregate, collection) = if self.tenant_databases {
+            stream.insert("allChangesForCluster", true);
+            pipeline.push(Bson::Document(doc! { "$changeStream": stream }));
+            let databases = format!("^{}(-|$)", self.db_name);
+            pipeline.push(Bson::Document(doc! { "$match": {
+                "ns.coll": "samples",
+                "ns.db": { "$regex": databases },
+            } }));
+            (
+                self.client.database("admin"),
+                Bson::I32(1),
+                "$cmd.aggregate",
+            )
+        } else {
+            pipeline.push(Bson::Document(doc! { "$changeStream": stream }));
+            let database = self.client.database(&self.db_name);
+            (database, Bson::String("samples".to_string()), "samples")
+        };
+        let command =
+            doc! { "aggregate": aggregate, "pipeline": Bson::Array(pipeline), "cursor": {} };
+        let reply = match run(&database, command) {
+            Err(e) if token.is_some() && e.has_code(&STALE_TOKEN) => {
+                warn!("Unable to resume the samples change stream: {}", e);
+                // changes since the token are lost, so no cached result can be trusted
+                invalidate_local(None);
+                invalidate_shared(None);
+                self.store_token(&token, &None);
+                self.token = None;
+                self.last_change = None;
+                return Ok(());
+            }
+            reply => reply?,
+        };
+        info!("Watching samples to evict cached results");
+        let mut cursor = reply.get_document("cursor")?.clone();
+        let mut batch = cursor.get_array("firstBatch")?.clone();
+        loop {
+            for event in batch.iter().filter_map(|event| event.as_document()) {
+                self.apply(event);
+            }
+            // only resumes this watcher, the stored token stays at a change for the others
+            if let Some(token) = cursor.get("postBatchResumeToken") {
+                self.token = Some(token.clone());
+            }
+            let id = cursor.get_i64("id")?;
+            if id == 0 {
+                return Ok(());
+            }
+            let more = doc! { "getMore": id, "collection": collection, "maxTimeMS": AWAIT_MS };
+            cursor = run(&database, more)?.get_document("cursor")?.clone();
+            batch = cursor.get_array("nextBatch")?.clone();
+        }
+    }
+}
+
+/// Evicts the cached results of this replica affected by changes made through other replicas,
+/// or directly in the database, as seen on the `samples` change stream. The position in the
+/// stream is stored in `cache_resume_tokens` so watching resumes where it stopped. Deployments
+/// without change streams rely on `CACHE_TTL` alone.
+pub fn watch_samples() {
+    if *CACHE_TTL == 0 || *CACHE_CAPACITY == 0 {
+        return;
+    }
+    let mongo_url = env::var("MONGO_URL").expect("MONGO_URL must be set");
+    let db_name = env::var("MONGO_DB_NAME").expect("MONGO_DB_NAME must be set");
+    let tenant_databases = env::var("TENANT_DATABASES").unwrap_or("".to_string()) == "1";
+    thread::spawn(move || {
+        let client = match Client::with_uri_str(&mongo_url) {
+            Ok(client) => client,
+            Err(e) => {
+                warn!("Unable to watch samples: {}", e);
+                return;
+            }
+        };
+        let mut watcher = SampleWatcher {
+            client,
+            db_name,
+            tenant_databases,
+            token: None,
+            last_change: None,
+        };
+        loop {
+            match watcher.watch() {
+                Err(e) if e.has_code(&UNSUPPORTED) => {
+                    info!(
+                        "Change streams aren't supported, cached results expire after CACHE_TTL: {}",
+                        e
+                    );
+                    return;
+                }
+                Err(e) => warn!("Lost the samples change stream: {}", e),
+                Ok(()) => {}
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+    });
+}
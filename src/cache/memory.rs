@@ -0,0 +1,159 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::{CacheBackend, Tag};
+
+struct CacheEntry<V> {
+    value: V,
+    expires_at: Instant,
+    tags: Vec<Tag>,
+}
+
+struct CacheState<V> {
+    entries: HashMap<String, CacheEntry<V>>,
+    keys_by_tag: HashMap<Tag, HashSet<String>>,
+    /// Bumped by every invalidation, so a result built meanwhile isn't stored stale
+    generation: u64,
+}
+
+impl<V> CacheState<V> {
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            for tag in entry.tags {
+                if let Some(keys) = self.keys_by_tag.get_mut(&tag) {
+                    keys.remove(key);
+                    if keys.is_empty() {
+                        self.keys_by_tag.remove(&tag);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Makes room for an entry, dropping expired entries first and then the one expiring soonest
+    fn make_room(&mut self, capacity: usize) {
+        if self.entries.len() < capacity {
+            return;
+        }
+        let now = Instant::now();
+        let expired: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.expires_at <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            self.remove(&key);
+        }
+        while self.entries.len() >= capacity {
+            let soonest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.expires_at)
+                .map(|(key, _)| key.clone());
+            match soonest {
+                Some(key) => self.remove(&key),
+                None => break,
+            }
+        }
+    }
+}
+
+/// Keeps results in the memory of this process, up to `capacity` of them
+pub struct MemoryBackend<V> {
+    lifespan: Duration,
+    capacity: usize,
+    state: Mutex<CacheState<V>>,
+}
+
+impl<V> MemoryBackend<V> {
+    pub fn new(lifespan: Duration, capacity: usize) -> MemoryBackend<V> {
+        MemoryBackend {
+            lifespan,
+            capacity,
+            state: Mutex::new(CacheState {
+                entries: HashMap::new(),
+                keys_by_tag: HashMap::new(),
+                generation: 0,
+            }),
+        }
+    }
+}
+
+impl<V: Clone + Send> CacheBackend<V> for MemoryBackend<V> {
+    fn get(&self, key: &str) -> Option<V> {
+        let mut state = self.state.lock().unwrap();
+        let fresh = state
+            .entries
+            .get(key)
+            .map(|entry| entry.expires_at > Instant::now());
+        match fresh {
+            Some(true) => Some(state.entries[key].value.clone()),
+            Some(false) => {
+                state.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn generation(&self) -> u64 {
+        self.state.lock().unwrap().generation
+    }
+
+    fn put(
+        &self,
+        generation: u64,
+        key: String,
+        value: &V,
+        tags: Vec<Tag>,
+        valid_for: Option<Duration>,
+    ) {
+        let lifespan = valid_for.map_or(self.lifespan, |valid_for| valid_for.min(self.lifespan));
+        let mut state = self.state.lock().unwrap();
+        if state.generation != generation {
+            return;
+        }
+        state.remove(&key);
+        if lifespan == Duration::from_secs(0) {
+            return;
+        }
+        state.make_room(self.capacity);
+        for tag in &tags {
+            state
+                .keys_by_tag
+                .entry(tag.clone())
+                .or_insert_with(HashSet::new)
+                .insert(key.clone());
+        }
+        let entry = CacheEntry {
+            value: value.clone(),
+            expires_at: Instant::now() + lifespan,
+            tags,
+        };
+        state.entries.insert(key, entry);
+    }
+
+    fn invalidate(&self, tags: &[Tag]) {
+        let mut state = self.state.lock().unwrap();
+        state.generation += 1;
+        for tag in tags {
+            let keys = state.keys_by_tag.remove(tag).unwrap_or_default();
+            for key in keys {
+                state.remove(&key);
+            }
+        }
+    }
+
+    fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.generation += 1;
+        state.entries.clear();
+        state.keys_by_tag.clear();
+    }
+
+    fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+}
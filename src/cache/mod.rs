@@ -0,0 +1,325 @@
+use bson::{Bson, Document};
+use juniper::FieldError;
+use log::debug;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::models::{SampleConnection, SampleSearchConnection, StatusCount, ValueStatistics};
+
+pub mod changes;
+pub mod memory;
+pub mod shared;
+
+use memory::MemoryBackend;
+use shared::{connect_shared_cache, SharedBackend, SharedCache};
+
+lazy_static! {
+    static ref CACHE_CAPACITY: usize = env::var("CACHE_CAPACITY")
+        .unwrap_or("".to_string())
+        .parse()
+        .unwrap_or(10000);
+    static ref CACHE_TTL: u64 = env::var("CACHE_TTL")
+        .unwrap_or("".to_string())
+        .parse()
+        .unwrap_or(60);
+    /// Root fields that are never cached, e.g. `allSamples,searchSamples`
+    static ref CACHE_DISABLED_QUERIES: HashSet<String> = env::var("CACHE_DISABLED_QUERIES")
+        .unwrap_or("".to_string())
+        .split(',')
+        .map(|query| query.trim().to_string())
+        .filter(|query| !query.is_empty())
+        .collect();
+    /// Results shared with other replicas, see `shared::connect_shared_cache`
+    static ref SHARED: Option<Arc<SharedCache>> = connect_shared_cache();
+    pub static ref ALL_SAMPLES: QueryCache<SampleConnection> = QueryCache::new("allSamples");
+    pub static ref SEARCH_SAMPLES: QueryCache<SampleSearchConnection> =
+        QueryCache::new("searchSamples");
+    pub static ref SAMPLES_BY_STATUS: QueryCache<SampleConnection> =
+        QueryCache::new("samplesByStatus");
+    pub static ref SAMPLE_STATUS_COUNTS: QueryCache<Vec<StatusCount>> =
+        QueryCache::new("sampleStatusCounts");
+    pub static ref SAMPLE_BY_NAME: QueryCache<SampleConnection> = QueryCache::new("sampleByNames");
+    pub static ref VALUE_STATISTICS: QueryCache<ValueStatistics> =
+        QueryCache::new("valueStatistics");
+}
+
+/// What a cached result depends on. Tags are scoped to a tenant since ids and names are only
+/// unique within one.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Tag {
+    /// Which samples match, changed by any write that can add a sample to a result or take one
+    /// away
+    Samples(Option<String>),
+    /// The embedded values of samples, for results filtering, sorting or aggregating on them
+    Values(Option<String>),
+    /// A sample within the result
+    Sample(Option<String>, String),
+    /// A name looked up by `sampleByNames`
+    Name(Option<String>, String),
+}
+
+/// The kind of write made to a sample
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SampleWrite {
+    Created,
+    /// Any change to the fields, ACL, owner or availability windows
+    Updated,
+    Deleted,
+    /// A change to the embedded values only
+    Values,
+}
+
+/// The tags invalidated by a write to a sample, from the stored document before and after it
+pub fn write_tags(
+    tenant: &Option<String>,
+    write: SampleWrite,
+    id: &str,
+    before: &Option<Document>,
+    after: &Option<Document>,
+) -> Vec<Tag> {
+    let mut tags = vec![Tag::Sample(tenant.clone(), id.to_string())];
+    match write {
+        SampleWrite::Values => tags.push(Tag::Values(tenant.clone())),
+        _ => {
+            tags.push(Tag::Samples(tenant.clone()));
+            let names = before
+                .iter()
+                .chain(after.iter())
+                .filter_map(|document| document.get_str("name").ok());
+            for name in names {
+                let tag = Tag::Name(tenant.clone(), name.to_string());
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+        }
+    }
+    tags
+}
+
+/// Fields holding embedded values or aggregates over them
+const VALUE_FIELDS: [&str; 5] = [
+    "values",
+    "min_value",
+    "max_value",
+    "avg_value",
+    "value_count",
+];
+
+/// Whether a filter or sort looks at embedded values, making results depend on them
+pub fn uses_values(document: &Document) -> bool {
+    document.iter().any(|(key, value)| {
+        let field = key.split('.').next().unwrap_or("");
+        VALUE_FIELDS.contains(&field) || bson_uses_values(value)
+    })
+}
+
+fn bson_uses_values(value: &Bson) -> bool {
+    match value {
+        Bson::Document(document) => uses_values(document),
+        Bson::Array(values) => values.iter().any(bson_uses_values),
+        _ => false,
+    }
+}
+
+/// The tags of a list of samples: its membership, each sample in it and, when asked, the values
+pub fn list_tags<I>(tenant: &Option<String>, ids: I, values: bool) -> Vec<Tag>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut tags = vec![Tag::Samples(tenant.clone())];
+    if values {
+        tags.push(Tag::Values(tenant.clone()));
+    }
+    tags.extend(ids.into_iter().map(|id| Tag::Sample(tenant.clone(), id)));
+    tags
+}
+
+/// Where a query cache keeps its results
+pub trait CacheBackend<V>: Send + Sync {
+    /// The result stored under `key`, unless it expired
+    fn get(&self, key: &str) -> Option<V>;
+
+    /// Counts invalidations, read before building a result and handed back to `put`
+    fn generation(&self) -> u64;
+
+    /// Stores a result for `CACHE_TTL` or `valid_for`, whichever is shorter, unless an
+    /// invalidation happened since `generation`
+    fn put(
+        &self,
+        generation: u64,
+        key: String,
+        value: &V,
+        tags: Vec<Tag>,
+        valid_for: Option<Duration>,
+    );
+
+    /// Evicts the results this process holds that depend on any of the tags
+    fn invalidate(&self, tags: &[Tag]);
+
+    /// Evicts every result this process holds
+    fn clear(&self);
+
+    /// The number of results this process holds
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The cached results of a root query field, each stored along with the tags it depends on so
+/// that writes evict exactly the results they affect
+pub struct QueryCache<V> {
+    query: &'static str,
+    enabled: bool,
+    backend: Box<dyn CacheBackend<V>>,
+}
+
+impl<V> QueryCache<V>
+where
+    V: Clone + Send + Serialize + DeserializeOwned + 'static,
+{
+    /// A cache following `CACHE_TTL`, `CACHE_CAPACITY` and `CACHE_DISABLED_QUERIES`, kept in
+    /// Redis when the shared cache is configured
+    pub fn new(query: &'static str) -> QueryCache<V> {
+        let enabled = !CACHE_DISABLED_QUERIES.contains(query);
+        let cache = QueryCache::with_settings(query, *CACHE_TTL, *CACHE_CAPACITY, enabled);
+        match &*SHARED {
+            Some(shared) => {
+                let near = MemoryBackend::new(Duration::from_secs(*CACHE_TTL), *CACHE_CAPACITY);
+                cache.with_backend(Box::new(SharedBackend::new(query, near, shared.clone())))
+            }
+            None => cache,
+        }
+    }
+
+    /// A cache kept in memory
+    pub fn with_settings(
+        query: &'static str,
+        ttl: u64,
+        capacity: usize,
+        enabled: bool,
+    ) -> QueryCache<V> {
+        QueryCache {
+            query,
+            enabled: enabled && ttl > 0 && capacity > 0,
+            backend: Box::new(MemoryBackend::new(Duration::from_secs(ttl), capacity)),
+        }
+    }
+
+    pub fn with_backend(self, backend: Box<dyn CacheBackend<V>>) -> QueryCache<V> {
+        QueryCache { backend, ..self }
+    }
+
+    /// The cached result for `key`, or a new one built along with the tags it depends on.
+    /// Callers asking for `no_cache` always get a new result, which is stored for the others.
+    pub fn get_or_build<F>(&self, no_cache: bool, key: String, build: F) -> Result<V, FieldError>
+    where
+        F: FnOnce() -> Result<(V, Vec<Tag>), FieldError>,
+    {
+        self.get_or_build_expiring(no_cache, key, || {
+            build().map(|(value, tags)| (value, tags, None))
+        })
+    }
+
+    /// Like `get_or_build`, for results that only hold for a while, e.g. until a sample changes
+    /// status. They are kept for that long at most, and not at all when it is zero.
+    pub fn get_or_build_expiring<F>(
+        &self,
+        no_cache: bool,
+        key: String,
+        build: F,
+    ) -> Result<V, FieldError>
+    where
+        F: FnOnce() -> Result<(V, Vec<Tag>, Option<Duration>), FieldError>,
+    {
+        if !self.enabled {
+            return build().map(|(value, _, _)| value);
+        }
+        if !no_cache {
+            if let Some(value) = self.backend.get(&key) {
+                debug!("{} served from the cache", self.query);
+                return Ok(value);
+            }
+        }
+        let generation = self.backend.generation();
+        let (value, tags, valid_for) = build()?;
+        self.backend.put(generation, key, &value, tags, valid_for);
+        Ok(value)
+    }
+
+    /// Evicts the results depending on any of the tags
+    pub fn invalidate(&self, tags: &[Tag]) {
+        self.backend.invalidate(tags);
+    }
+
+    /// Evicts every result this process holds
+    pub fn clear(&self) {
+        self.backend.clear();
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.backend.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Evicts the results this process holds in every query cache, all of them when `tags` is
+/// `None`
+fn invalidate_local(tags: Option<&[Tag]>) {
+    match tags {
+        Some(tags) => {
+            ALL_SAMPLES.invalidate(tags);
+            SEARCH_SAMPLES.invalidate(tags);
+            SAMPLES_BY_STATUS.invalidate(tags);
+            SAMPLE_STATUS_COUNTS.invalidate(tags);
+            SAMPLE_BY_NAME.invalidate(tags);
+            VALUE_STATISTICS.invalidate(tags);
+        }
+        None => {
+            ALL_SAMPLES.clear();
+            SEARCH_SAMPLES.clear();
+            SAMPLES_BY_STATUS.clear();
+            SAMPLE_STATUS_COUNTS.clear();
+            SAMPLE_BY_NAME.clear();
+            VALUE_STATISTICS.clear();
+        }
+    }
+}
+
+/// Evicts the results of the shared cache depending on any of the tags, all of them when `tags`
+/// is `None`, without telling the other replicas, which see the same change themselves
+fn invalidate_shared(tags: Option<&[Tag]>) {
+    if let Some(shared) = &*SHARED {
+        shared.evict(tags);
+    }
+}
+
+/// Starts following the invalidations made through other replicas when the shared cache is
+/// configured
+pub fn subscribe() {
+    if let Some(shared) = &*SHARED {
+        shared.subscribe(invalidate_local);
+    }
+}
+
+/// Evicts the results of every query cache depending on any of the tags
+pub fn invalidate(tags: &[Tag]) {
+    if tags.is_empty() {
+        return;
+    }
+    invalidate_local(Some(tags));
+    if let Some(shared) = &*SHARED {
+        shared.invalidate(tags);
+    }
+}
@@ -0,0 +1,527 @@
+use bson::{Bson, Document};
+use log::warn;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::memory::MemoryBackend;
+use super::{CacheBackend, Tag, CACHE_TTL};
+
+/// Opens the cache shared by every replica at `CACHE_REDIS_URL` when the `redis-cache` feature
+/// is enabled. Entries are kept for `CACHE_REDIS_TTL` seconds, `CACHE_TTL` by default, under
+/// keys starting with `CACHE_REDIS_PREFIX`, through up to `CACHE_REDIS_POOL_SIZE` connections.
+pub fn connect_shared_cache() -> Option<Arc<SharedCache>> {
+    let url = env::var("CACHE_REDIS_URL").ok()?;
+    let prefix = env::var("CACHE_REDIS_PREFIX").unwrap_or("sample-service:".to_string());
+    let ttl = env::var("CACHE_REDIS_TTL")
+        .unwrap_or("".to_string())
+        .parse()
+        .unwrap_or(*CACHE_TTL);
+    let pool_size = env::var("CACHE_REDIS_POOL_SIZE")
+        .unwrap_or("".to_string())
+        .parse()
+        .unwrap_or(16);
+    match SharedCache::connect(&url, &prefix, ttl, pool_size) {
+        Ok(cache) => Some(Arc::new(cache)),
+        Err(e) => {
+            warn!("The shared cache is disabled: {}", e);
+            None
+        }
+    }
+}
+
+/// A result as stored in the shared cache, along with its tags for the near caches it fills
+#[derive(Serialize, Deserialize)]
+struct Stored<V> {
+    value: V,
+    tags: Vec<Tag>,
+}
+
+fn encode<V: Serialize>(stored: &Stored<V>) -> Result<Vec<u8>, String> {
+    let document = match bson::to_bson(stored).map_err(|e| e.to_string())? {
+        Bson::Document(document) => document,
+        _ => return Err("Unable to encode a cached result".to_string()),
+    };
+    let mut bytes = vec![];
+    bson::encode_document(&mut bytes, &document).map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+fn decode<V: DeserializeOwned>(bytes: &[u8]) -> Result<Stored<V>, String> {
+    let document: Document = bson::decode_document(&mut &bytes[..]).map_err(|e| e.to_string())?;
+    bson::from_bson(Bson::Document(document)).map_err(|e| e.to_string())
+}
+
+/// Keeps results in the shared cache, with a near cache in this process in front of it. Near
+/// caches of other replicas learn about invalidations over pub/sub.
+pub struct SharedBackend<V> {
+    query: &'static str,
+    near: MemoryBackend<V>,
+    shared: Arc<SharedCache>,
+}
+
+impl<V> SharedBackend<V> {
+    pub fn new(query: &'static str, near: MemoryBackend<V>, shared: Arc<SharedCache>) -> Self {
+        SharedBackend {
+            query,
+            near,
+            shared,
+        }
+    }
+}
+
+impl<V> CacheBackend<V> for SharedBackend<V>
+where
+    V: Clone + Send + Serialize + DeserializeOwned,
+{
+    fn get(&self, key: &str) -> Option<V> {
+        if let Some(value) = self.near.get(key) {
+            return Some(value);
+        }
+        let generation = self.near.generation();
+        let (bytes, remaining) = self.shared.get(self.query, key)?;
+        match decode::<V>(&bytes) {
+            Ok(stored) => {
+                let value = stored.value.clone();
+                let key = key.to_string();
+                self.near
+                    .put(generation, key, &stored.value, stored.tags, Some(remaining));
+                Some(value)
+            }
+            Err(e) => {
+                warn!("Unable to read a cached {} result: {}", self.query, e);
+                None
+            }
+        }
+    }
+
+    /// The invalidation sequence of the shared cache, the near cache is only filled from it
+    fn generation(&self) -> u64 {
+        self.shared.generation().unwrap_or(u64::MAX)
+    }
+
+    fn put(
+        &self,
+        generation: u64,
+        key: String,
+        value: &V,
+        tags: Vec<Tag>,
+        valid_for: Option<Duration>,
+    ) {
+        let stored = Stored {
+            value: value.clone(),
+            tags,
+        };
+        match encode(&stored) {
+            Ok(bytes) => self.shared.put(
+                generation,
+                self.query,
+                &key,
+                &bytes,
+                &stored.tags,
+                valid_for,
+            ),
+            Err(e) => warn!("Unable to cache a {} result: {}", self.query, e),
+        }
+    }
+
+    /// Evicts the near cache only, shared entries are evicted once per write by
+    /// `SharedCache::invalidate`
+    fn invalidate(&self, tags: &[Tag]) {
+        self.near.invalidate(tags);
+    }
+
+    fn clear(&self) {
+        self.near.clear();
+    }
+
+    fn len(&self) -> usize {
+        self.near.len()
+    }
+}
+
+#[cfg(feature = "redis-cache")]
+pub use self::redis_cache::SharedCache;
+
+#[cfg(not(feature = "redis-cache"))]
+pub use self::disabled::SharedCache;
+
+#[cfg(feature = "redis-cache")]
+mod redis_cache {
+    use log::{info, warn};
+    use r2d2::Pool;
+    use redis::{Client, Connection, RedisResult, Script};
+    use serde::{Deserialize, Serialize};
+    use std::thread;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    use super::Tag;
+
+    /// An invalidation published to every replica
+    #[derive(Serialize, Deserialize)]
+    struct Invalidation {
+        /// The replica the write was made through, which has already evicted its results
+        origin: String,
+        tags: Vec<Tag>,
+    }
+
+    /// Stores an entry and adds it to the sets of its tags, unless the cache was cleared or one
+    /// of its tags invalidated after the sequence the entry was built at. Keys past the entry and
+    /// the sequence of the last clear come in pairs of a tag set and the sequence of its last
+    /// invalidation. Tag sets live as long as their longest entry.
+    const PUT: &str = r"
+        for i = 2, #KEYS, 2 do
+            if tonumber(redis.call('GET', KEYS[i]) or '0') > tonumber(ARGV[1]) then
+                return 0
+            end
+        end
+        redis.call('SET', KEYS[1], ARGV[2], 'PX', ARGV[3])
+        for i = 3, #KEYS, 2 do
+            redis.call('SADD', KEYS[i], KEYS[1])
+            if redis.call('PTTL', KEYS[i]) < tonumber(ARGV[3]) then
+                redis.call('PEXPIRE', KEYS[i], ARGV[3])
+            end
+        end
+        return 1
+    ";
+
+    /// Advances the sequence, records it as the last invalidation of each tag and deletes the
+    /// entries in their sets. Keys come in pairs as for `PUT`.
+    const INVALIDATE: &str = r"
+        local sequence = redis.call('INCR', KEYS[1])
+        for i = 2, #KEYS, 2 do
+            for _, entry in ipairs(redis.call('SMEMBERS', KEYS[i])) do
+                redis.call('DEL', entry)
+            end
+            redis.call('DEL', KEYS[i])
+            redis.call('SET', KEYS[i + 1], sequence, 'PX', ARGV[1])
+        end
+        return 1
+    ";
+
+    /// Advances the sequence and records it as the last clear, entries are deleted separately
+    const CLEAR: &str = r"
+        local sequence = redis.call('INCR', KEYS[1])
+        redis.call('SET', KEYS[2], sequence, 'PX', ARGV[1])
+        return sequence
+    ";
+
+    /// Results shared by every replica in Redis
+    pub struct SharedCache {
+        client: Client,
+        pool: Pool<Client>,
+        prefix: String,
+        lifespan: Duration,
+        /// Tells the invalidations of this replica apart on the channel
+        origin: String,
+        put: Script,
+        invalidate: Script,
+        clear: Script,
+    }
+
+    impl SharedCache {
+        pub fn connect(
+            url: &str,
+            prefix: &str,
+            ttl: u64,
+            pool_size: u32,
+        ) -> Result<SharedCache, String> {
+            let client = Client::open(url).map_err(|e| e.to_string())?;
+            // requests fall back to building results rather than wait long for a connection
+            let pool = Pool::builder()
+                .max_size(pool_size)
+                .connection_timeout(Duration::from_secs(1))
+                .build(client.clone())
+                .map_err(|e| e.to_string())?;
+            info!("Sharing cached results through Redis");
+            Ok(SharedCache {
+                client,
+                pool,
+                prefix: prefix.to_string(),
+                lifespan: Duration::from_secs(ttl),
+                origin: Uuid::new_v4().to_string(),
+                put: Script::new(PUT),
+                invalidate: Script::new(INVALIDATE),
+                clear: Script::new(CLEAR),
+            })
+        }
+
+        /// Runs a command on a pooled connection, broken connections are dropped by the pool
+        fn with_connection<T, F>(&self, command: F) -> Option<T>
+        where
+            F: FnOnce(&mut Connection) -> RedisResult<T>,
+        {
+            let result = self
+                .pool
+                .get()
+                .map_err(|e| e.to_string())
+                .and_then(|mut connection| command(&mut connection).map_err(|e| e.to_string()));
+            match result {
+                Ok(value) => Some(value),
+                Err(e) => {
+                    warn!("Unable to reach the shared cache: {}", e);
+                    None
+                }
+            }
+        }
+
+        fn entry_key(&self, query: &str, key: &str) -> String {
+            format!("{}{}:{}", self.prefix, query, key)
+        }
+
+        /// Tenants only hold letters, digits, `-` and `_`, so `*` stands for no tenant
+        fn tag_name(tag: &Tag) -> String {
+            let tenant = |tenant: &Option<String>| tenant.as_deref().unwrap_or("*").to_string();
+            match tag {
+                Tag::Samples(tenant_id) => format!("samples/{}", tenant(tenant_id)),
+                Tag::Values(tenant_id) => format!("values/{}", tenant(tenant_id)),
+                Tag::Sample(tenant_id, id) => format!("sample/{}/{}", tenant(tenant_id), id),
+                Tag::Name(tenant_id, name) => format!("name/{}/{}", tenant(tenant_id), name),
+            }
+        }
+
+        /// The set of entries depending on a tag
+        fn tag_key(&self, tag: &Tag) -> String {
+            format!("{}tag:{}", self.prefix, Self::tag_name(tag))
+        }
+
+        /// The sequence of the last invalidation of a tag
+        fn tag_generation_key(&self, tag: &Tag) -> String {
+            format!("{}invalidated:{}", self.prefix, Self::tag_name(tag))
+        }
+
+        /// Advanced by every invalidation, results are built at the sequence read beforehand
+        fn generation_key(&self) -> String {
+            format!("{}generation", self.prefix)
+        }
+
+        /// The sequence of the last clear
+        fn cleared_key(&self) -> String {
+            format!("{}cleared", self.prefix)
+        }
+
+        fn channel(&self) -> String {
+            format!("{}invalidations", self.prefix)
+        }
+
+        /// A stored result and how much longer it is kept
+        pub fn get(&self, query: &str, key: &str) -> Option<(Vec<u8>, Duration)> {
+            let key = self.entry_key(query, key);
+            let (bytes, remaining): (Option<Vec<u8>>, i64) =
+                self.with_connection(|connection| {
+                    redis::pipe()
+                        .cmd("GET")
+                        .arg(&key)
+                        .cmd("PTTL")
+                        .arg(&key)
+                        .query(connection)
+                })?;
+            Some((bytes?, Duration::from_millis(remaining.max(0) as u64)))
+        }
+
+        pub fn generation(&self) -> Option<u64> {
+            let generation: Option<u64> = self.with_connection(|connection| {
+                redis::cmd("GET")
+                    .arg(self.generation_key())
+                    .query(connection)
+            })?;
+            Some(generation.unwrap_or(0))
+        }
+
+        pub fn put(
+            &self,
+            generation: u64,
+            query: &str,
+            key: &str,
+            bytes: &[u8],
+            tags: &[Tag],
+            valid_for: Option<Duration>,
+        ) {
+            let lifespan =
+                valid_for.map_or(self.lifespan, |valid_for| valid_for.min(self.lifespan));
+            if lifespan.as_millis() == 0 {
+                return;
+            }
+            let mut invocation = self.put.prepare_invoke();
+            invocation
+                .key(self.entry_key(query, key))
+                .key(self.cleared_key());
+            for tag in tags {
+                invocation
+                    .key(self.tag_key(tag))
+                    .key(self.tag_generation_key(tag));
+            }
+            invocation
+                .arg(generation)
+                .arg(bytes)
+                .arg(lifespan.as_millis() as u64);
+            let _: Option<i32> = self.with_connection(|connection| invocation.invoke(connection));
+        }
+
+        /// Evicts the entries depending on any of the tags, every entry when `tags` is `None`
+        pub fn evict(&self, tags: Option<&[Tag]>) {
+            let tags = match tags {
+                Some(tags) => tags,
+                None => return self.evict_all(),
+            };
+            let mut invocation = self.invalidate.prepare_invoke();
+            invocation.key(self.generation_key());
+            for tag in tags {
+                invocation
+                    .key(self.tag_key(tag))
+                    .key(self.tag_generation_key(tag));
+            }
+            // results built before the invalidation are rejected for as long as they'd be kept
+            invocation.arg(self.lifespan.as_millis() as u64);
+            let _: Option<i32> = self.with_connection(|connection| invocation.invoke(connection));
+        }
+
+        /// Rejects the results being built, then deletes the entries and tag sets under the
+        /// prefix a batch at a time
+        fn evict_all(&self) {
+            let mut invocation = self.clear.prepare_invoke();
+            invocation
+                .key(self.generation_key())
+                .key(self.cleared_key())
+                .arg(self.lifespan.as_millis() as u64);
+            let _: Option<u64> = self.with_connection(|connection| invocation.invoke(connection));
+            let kept = [self.generation_key(), self.cleared_key()];
+            let invalidated = format!("{}invalidated:", self.prefix);
+            let keys: Option<Vec<String>> = self.with_connection(|connection| {
+                let keys = redis::cmd("SCAN")
+                    .cursor_arg(0)
+                    .arg("MATCH")
+                    .arg(format!("{}*", self.prefix))
+                    .arg("COUNT")
+                    .arg(1000)
+                    .iter::<String>(connection)?
+                    .collect::<Vec<String>>();
+                Ok(keys)
+            });
+            let keys: Vec<String> = keys
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|key| !kept.contains(key) && !key.starts_with(&invalidated))
+                .collect();
+            for batch in keys.chunks(1000) {
+                let _: Option<i32> = self.with_connection(|connection| {
+                    redis::cmd("DEL").arg(batch.to_vec()).query(connection)
+                });
+            }
+        }
+
+        /// Evicts the entries depending on any of the tags and tells the other replicas to
+        /// evict them from their near caches
+        pub fn invalidate(&self, tags: &[Tag]) {
+            self.evict(Some(tags));
+            let invalidation = Invalidation {
+                origin: self.origin.clone(),
+                tags: tags.to_vec(),
+            };
+            if let Ok(message) = serde_json::to_string(&invalidation) {
+                let _: Option<i32> = self.with_connection(|connection| {
+                    redis::cmd("PUBLISH")
+                        .arg(self.channel())
+                        .arg(&message)
+                        .query(connection)
+                });
+            }
+        }
+
+        /// Listens to the invalidations of other replicas on a thread of its own. The near
+        /// caches may have missed some while the subscription was down, so `on_invalidation`
+        /// gets `None` to clear them every time it is (re)established.
+        pub fn subscribe<F>(&self, on_invalidation: F)
+        where
+            F: Fn(Option<&[Tag]>) + Send + 'static,
+        {
+            let client = self.client.clone();
+            let channel = self.channel();
+            let origin = self.origin.clone();
+            thread::spawn(move || loop {
+                if let Err(e) = listen(&client, &channel, &origin, &on_invalidation) {
+                    warn!("Lost the shared cache invalidations: {}", e);
+                }
+                thread::sleep(Duration::from_secs(1));
+            });
+        }
+    }
+
+    fn listen<F>(
+        client: &Client,
+        channel: &str,
+        origin: &str,
+        on_invalidation: &F,
+    ) -> RedisResult<()>
+    where
+        F: Fn(Option<&[Tag]>),
+    {
+        let mut connection = client.get_connection()?;
+        let mut pubsub = connection.as_pubsub();
+        pubsub.subscribe(channel)?;
+        on_invalidation(None);
+        loop {
+            let message: String = pubsub.get_message()?.get_payload()?;
+            match serde_json::from_str::<Invalidation>(&message) {
+                Ok(invalidation) if invalidation.origin != origin => {
+                    on_invalidation(Some(&invalidation.tags))
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Ignoring a malformed cache invalidation: {}", e),
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "redis-cache"))]
+mod disabled {
+    use std::time::Duration;
+
+    use super::Tag;
+
+    /// Stands in for the shared cache when the `redis-cache` feature is disabled
+    pub struct SharedCache;
+
+    impl SharedCache {
+        pub fn connect(
+            _url: &str,
+            _prefix: &str,
+            _ttl: u64,
+            _pool_size: u32,
+        ) -> Result<SharedCache, String> {
+            Err("the redis-cache feature is not enabled".to_string())
+        }
+
+        pub fn get(&self, _query: &str, _key: &str) -> Option<(Vec<u8>, Duration)> {
+            None
+        }
+
+        pub fn generation(&self) -> Option<u64> {
+            None
+        }
+
+        pub fn put(
+            &self,
+            _generation: u64,
+            _query: &str,
+            _key: &str,
+            _bytes: &[u8],
+            _tags: &[Tag],
+            _valid_for: Option<Duration>,
+        ) {
+        }
+
+        pub fn evict(&self, _tags: Option<&[Tag]>) {}
+
+        pub fn invalidate(&self, _tags: &[Tag]) {}
+
+        pub fn subscribe<F>(&self, _on_invalidation: F)
+        where
+            F: Fn(Option<&[Tag]>) + Send + 'static,
+        {
+        }
+    }
+}
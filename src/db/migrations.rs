@@ -0,0 +1,155 @@
+use bson::{doc, Bson};
+use log::{info, warn};
+use mongodb_base_service::{BaseService, DataSources, ID};
+
+use crate::auth::Identity;
+use crate::db::id_string;
+use crate::models::{Availability, Embedded, ValueAggregates};
+
+/// Brings documents written by older versions up to date, run at startup and whenever the
+/// database of a tenant is first used
+pub fn migrate(data_sources: &DataSources) {
+    backfill_value_aggregates(data_sources);
+    backfill_occurrences(data_sources);
+    backfill_owners(data_sources);
+}
+
+/// Stores the value aggregates on samples written before they were denormalized, or when only
+/// `min_value` was, which skipped zeros
+fn backfill_value_aggregates(data_sources: &DataSources) {
+    let service = data_sources.get_mongo_service("samples").unwrap();
+    let collection = service.data_source();
+    let filter = doc! { "value_count": { "$exists": false } };
+    let cursor = match collection.find(filter, None) {
+        Ok(cursor) => cursor,
+        Err(e) => {
+            warn!("Unable to backfill value aggregates: {}", e);
+            return;
+        }
+    };
+    let mut count = 0;
+    for document in cursor {
+        let document = match document {
+            Ok(document) => document,
+            Err(e) => {
+                warn!("Unable to backfill value aggregates: {}", e);
+                return;
+            }
+        };
+        let values: Vec<Embedded> = match document.get("values") {
+            Some(values @ Bson::Array(_)) => bson::from_bson(values.clone()).unwrap_or_default(),
+            _ => vec![],
+        };
+        let id = document.get("_id").cloned().unwrap_or(Bson::Null);
+        let result = ValueAggregates::of(&values)
+            .to_document()
+            .map_err(|e| e.message().to_string());
+        let result = result.and_then(|set| {
+            collection
+                .update_one(doc! { "_id": id }, doc! { "$set": set }, None)
+                .map_err(|e| e.to_string())
+        });
+        match result {
+            Ok(_) => count += 1,
+            Err(e) => warn!("Unable to backfill value aggregates: {}", e),
+        }
+    }
+    if count > 0 {
+        info!("Backfilled value aggregates on {} samples", count);
+    }
+}
+
+/// Stores the occurrences of samples written before availability windows, made up of their
+/// dates. Until then status filters fall back to the dates.
+fn backfill_occurrences(data_sources: &DataSources) {
+    let service = data_sources.get_mongo_service("samples").unwrap();
+    let collection = service.data_source();
+    let filter = doc! { "occurrences": { "$exists": false } };
+    let cursor = match collection.find(filter, None) {
+        Ok(cursor) => cursor,
+        Err(e) => {
+            warn!("Unable to backfill occurrences: {}", e);
+            return;
+        }
+    };
+    let mut count = 0;
+    for document in cursor {
+        let document = match document {
+            Ok(document) => document,
+            Err(e) => {
+                warn!("Unable to backfill occurrences: {}", e);
+                return;
+            }
+        };
+        let date = |field| match document.get(field) {
+            Some(Bson::I32(date)) => Some(*date as i64),
+            Some(Bson::I64(date)) => Some(*date),
+            _ => None,
+        };
+        let availability = Availability::of(&[], date("available_date"), date("expiration_date"));
+        let id = document.get("_id").cloned().unwrap_or(Bson::Null);
+        let result = availability
+            .to_document()
+            .map_err(|e| e.message().to_string());
+        let result = result.and_then(|set| {
+            collection
+                .update_one(doc! { "_id": id }, doc! { "$set": set }, None)
+                .map_err(|e| e.to_string())
+        });
+        match result {
+            Ok(_) => count += 1,
+            Err(e) => warn!("Unable to backfill occurrences: {}", e),
+        }
+    }
+    if count > 0 {
+        info!("Backfilled occurrences on {} samples", count);
+    }
+}
+
+/// Gives samples written before ownership to the identity that created them, or to the service
+/// identity when it isn't known, leaving them to `samples:manage_all` callers and their ACLs
+fn backfill_owners(data_sources: &DataSources) {
+    let service = data_sources.get_mongo_service("samples").unwrap();
+    let collection = service.data_source();
+    let filter = doc! { "owner_id": null };
+    let cursor = match collection.find(filter, None) {
+        Ok(cursor) => cursor,
+        Err(e) => {
+            warn!("Unable to backfill owners: {}", e);
+            return;
+        }
+    };
+    let mut count = 0;
+    for document in cursor {
+        let document = match document {
+            Ok(document) => document,
+            Err(e) => {
+                warn!("Unable to backfill owners: {}", e);
+                return;
+            }
+        };
+        let created_by = document
+            .get_document("created_by")
+            .ok()
+            .and_then(|identity| identity.get_str("id").ok())
+            .map(|id| id.to_string());
+        let node_created_by = document
+            .get_document("node")
+            .ok()
+            .and_then(|node| node.get("created_by"))
+            .and_then(|id| bson::from_bson::<ID>(id.clone()).ok())
+            .map(|id| id_string(&id));
+        let owner_id = created_by
+            .or(node_created_by)
+            .unwrap_or_else(|| Identity::service().id);
+        let id = document.get("_id").cloned().unwrap_or(Bson::Null);
+        let set = doc! { "$set": { "owner_id": owner_id } };
+        match collection.update_one(doc! { "_id": id }, set, None) {
+            Ok(_) => count += 1,
+            Err(e) => warn!("Unable to backfill owners: {}", e),
+        }
+    }
+    if count > 0 {
+        info!("Backfilled owners on {} samples", count);
+    }
+}
@@ -1,8 +1,50 @@
+pub mod migrations;
 pub mod mongo;
+pub mod search_index;
 
-use mongodb_base_service::DataSources;
+use bson::oid::ObjectId;
+use bson::{doc, Bson, Document};
+use mongodb_base_service::{DataSources, ID};
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct Clients {
     pub mongo: DataSources,
+    /// Set in database-per-tenant mode
+    pub tenant_databases: Option<mongo::TenantDatabases>,
+    /// Set when fuzzy search is enabled through `SEARCH_INDEX_DIR`
+    pub search_index: Option<Arc<search_index::SearchIndex>>,
+}
+
+/// Builds a filter that matches a document by its `_id`
+pub fn id_filter(id: &ID) -> Document {
+    doc! { "_id": bson::to_bson(id).unwrap_or(Bson::Null) }
+}
+
+/// Parses an id rendered by `id_string`, hex strings are taken to be object ids
+pub fn id_from_string(id: &str) -> Bson {
+    match ObjectId::with_string(id) {
+        Ok(oid) => Bson::ObjectId(oid),
+        Err(_) => Bson::String(id.to_string()),
+    }
+}
+
+/// Renders an id as a plain string, using the hex form of object ids
+pub fn id_string(id: &ID) -> String {
+    match bson::to_bson(id) {
+        Ok(Bson::String(id)) => id,
+        Ok(Bson::ObjectId(oid)) => oid.to_hex(),
+        Ok(other) => other.to_string(),
+        Err(_) => format!("{:?}", id),
+    }
+}
+
+/// Combines filters so that all of them have to match
+pub fn and_filters(filters: Vec<Option<Document>>) -> Option<Document> {
+    let mut filters: Vec<Document> = filters.into_iter().flatten().collect();
+    match filters.len() {
+        0 => None,
+        1 => filters.pop(),
+        _ => Some(doc! { "$and": filters }),
+    }
 }
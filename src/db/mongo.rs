@@ -1,7 +1,129 @@
-use bson::doc;
-use mongodb::Client;
+use bson::{doc, Bson};
+use mongodb::{Client, Database};
 use mongodb_base_service::DataSources;
+use std::collections::HashMap;
 use std::env;
+use std::sync::{Arc, RwLock};
+
+use crate::db::migrations;
+
+lazy_static! {
+    static ref TEXT_SEARCH_LANGUAGE: String =
+        env::var("TEXT_SEARCH_LANGUAGE").unwrap_or("english".to_string());
+}
+
+/// Services stored in the database of each tenant when `TENANT_DATABASES` is set
+fn create_tenant_services(data_sources: &mut DataSources, database: &Database) {
+    data_sources.create_mongo_service(
+        "samples",
+        &database.collection("samples"),
+        Some(doc! { "node.date_created": -1 }),
+    );
+
+    // every sort ends with `_id` so that cursors are stable, the indexes used for sorting too
+    let sort_fields = vec![
+        "name",
+        "node.date_created",
+        "node.date_modified",
+        "available_date",
+        "expiration_date",
+        "min_value",
+        "max_value",
+        "avg_value",
+        "value_count",
+    ];
+    let indexes: Vec<Bson> = sort_fields
+        .into_iter()
+        .map(|field| {
+            Bson::Document(doc! {
+                "key": { field: 1, "_id": 1 },
+                "name": format!("{}_id", field.replace('.', "_")),
+            })
+        })
+        .collect();
+    let _result = database.run_command(
+        doc! { "createIndexes": "samples", "indexes": indexes },
+        None,
+    );
+
+    // searchSamples ranks by relevance, names weigh more than descriptions
+    let _result = database.run_command(
+        doc! {
+            "createIndexes": "samples",
+            "indexes": [{
+                "key": { "name": "text", "description": "text" },
+                "name": "text",
+                "weights": { "name": 10, "description": 1 },
+                "default_language": TEXT_SEARCH_LANGUAGE.as_str(),
+            }],
+        },
+        None,
+    );
+}
+
+/// The databases of each tenant, created lazily as tenants show up
+#[derive(Clone)]
+pub struct TenantDatabases {
+    client: Client,
+    db_name: String,
+    databases: Arc<RwLock<HashMap<String, Arc<DataSources>>>>,
+}
+
+impl TenantDatabases {
+    /// Tenant databases on the server at `mongo_url`, named after `db_name`
+    pub fn new(mongo_url: &str, db_name: &str) -> TenantDatabases {
+        TenantDatabases {
+            client: Client::with_uri_str(mongo_url).expect("Failed to initialize client."),
+            db_name: db_name.to_string(),
+            databases: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// The services of a tenant, stored in the `<MONGO_DB_NAME>-<tenant>` database
+    pub fn get(&self, tenant: &str) -> Arc<DataSources> {
+        if let Some(data_sources) = self.databases.read().unwrap().get(tenant) {
+            return data_sources.clone();
+        }
+        let mut databases = self.databases.write().unwrap();
+        databases
+            .entry(tenant.to_string())
+            .or_insert_with(|| {
+                let mut data_sources = DataSources::new();
+                let database = self
+                    .client
+                    .database(&format!("{}-{}", self.db_name, tenant));
+                create_tenant_services(&mut data_sources, &database);
+                migrations::migrate(&data_sources);
+                Arc::new(data_sources)
+            })
+            .clone()
+    }
+
+    /// The tenants whose database already exists
+    pub fn tenants(&self) -> Result<Vec<String>, String> {
+        let prefix = format!("{}-", self.db_name);
+        let names = self
+            .client
+            .list_database_names(None)
+            .map_err(|e| e.to_string())?;
+        Ok(names
+            .into_iter()
+            .filter_map(|name| name.strip_prefix(&prefix).map(|tenant| tenant.to_string()))
+            .collect())
+    }
+}
+
+/// Set up database-per-tenant mode when `TENANT_DATABASES=1`, tenant scoped services are then
+/// served from a database of their own rather than the shared one
+#[allow(dead_code)]
+pub fn connect_tenants() -> Option<TenantDatabases> {
+    if env::var("TENANT_DATABASES").unwrap_or("".to_string()) != "1" {
+        return None;
+    }
+    let mongo_url = env::var("MONGO_URL").expect("MONGO_URL must be set");
+    let mongo_db_name = env::var("MONGO_DB_NAME").expect("MONGO_DB_NAME must be set");
+    Some(TenantDatabases::new(&mongo_url, &mongo_db_name))
+}
 
 #[allow(dead_code)]
 pub fn connect() -> DataSources {
@@ -14,11 +136,41 @@ pub fn connect() -> DataSources {
         .expect("Failed to initialize client.")
         .database(&mongo_db_name);
 
+    create_tenant_services(&mut data_sources, &client);
+    data_sources.create_mongo_service("user_roles", &client.collection("user_roles"), None);
     data_sources.create_mongo_service(
-        "samples",
-        &client.collection("samples"),
+        "api_keys",
+        &client.collection("api_keys"),
         Some(doc! { "node.date_created": -1 }),
     );
+    data_sources.create_mongo_service(
+        "revocations",
+        &client.collection("revocations"),
+        Some(doc! { "node.date_created": -1 }),
+    );
+    data_sources.create_mongo_service(
+        "audit_log",
+        &client.collection("audit_log"),
+        Some(doc! { "node.date_created": -1 }),
+    );
+
+    // api keys are looked up by the hash of their secret on every request
+    let _result = client.run_command(
+        doc! {
+            "createIndexes": "api_keys",
+            "indexes": [{ "key": { "hash": 1 }, "name": "hash", "unique": true }],
+        },
+        None,
+    );
+
+    // the audit trail of a sample is read by target, newest first
+    let _result = client.run_command(
+        doc! {
+            "createIndexes": "audit_log",
+            "indexes": [{ "key": { "target_id": 1, "node.date_created": -1 }, "name": "target_id" }],
+        },
+        None,
+    );
 
     return data_sources;
 }
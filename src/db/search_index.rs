@@ -0,0 +1,277 @@
+use bson::Document;
+use log::{info, warn};
+use mongodb_base_service::{BaseService, DataSources};
+use std::env;
+use std::sync::Arc;
+
+use crate::db::{id_string, Clients};
+
+/// How many candidates a fuzzy search takes from the index at a time before scoping them
+pub const FUZZY_BATCH_SIZE: usize = 1000;
+
+/// The most candidates a fuzzy search ranks through, matches past it are left out of the
+/// results and their count
+pub const MAX_FUZZY_CANDIDATES: usize = 10_000;
+
+/// The searchable parts of a sample as stored in the local index
+pub struct IndexedSample {
+    pub id: String,
+    pub tenant_id: Option<String>,
+    pub name: String,
+    pub description: Option<String>,
+}
+
+impl IndexedSample {
+    pub fn from_document(document: &Document) -> Option<IndexedSample> {
+        Some(IndexedSample {
+            id: id_string(&bson::from_bson(document.get("_id")?.clone()).ok()?),
+            tenant_id: document.get_str("tenant_id").ok().map(|t| t.to_string()),
+            name: document.get_str("name").ok()?.to_string(),
+            description: document.get_str("description").ok().map(|d| d.to_string()),
+        })
+    }
+
+    /// Samples are keyed by tenant as ids are only unique within a database
+    fn key(&self) -> String {
+        index_key(&self.tenant_id, &self.id)
+    }
+}
+
+/// Stands for samples without a tenant, `*` can't appear in a tenant id
+const NO_TENANT: &str = "*";
+
+fn index_key(tenant_id: &Option<String>, id: &str) -> String {
+    format!("{}/{}", tenant_id.as_deref().unwrap_or(NO_TENANT), id)
+}
+
+/// Opens the index in `SEARCH_INDEX_DIR` when the `fuzzy-search` feature is enabled
+#[allow(dead_code)]
+pub fn connect_search_index() -> Option<Arc<SearchIndex>> {
+    let dir = env::var("SEARCH_INDEX_DIR").ok()?;
+    match SearchIndex::open(&dir) {
+        Ok(index) => Some(Arc::new(index)),
+        Err(e) => {
+            warn!("Fuzzy search is disabled: {}", e);
+            None
+        }
+    }
+}
+
+impl SearchIndex {
+    /// Indexes every sample of the shared database and of the tenant databases created so far
+    pub fn rebuild(&self, clients: &Clients) {
+        self.rebuild_database(&clients.mongo);
+        if let Some(tenant_databases) = &clients.tenant_databases {
+            match tenant_databases.tenants() {
+                Ok(tenants) => {
+                    for tenant in tenants {
+                        self.rebuild_database(&tenant_databases.get(&tenant));
+                    }
+                }
+                Err(e) => warn!("Unable to index tenant databases: {}", e),
+            }
+        }
+    }
+
+    fn rebuild_database(&self, data_sources: &DataSources) {
+        let service = data_sources.get_mongo_service("samples").unwrap();
+        let cursor = match service.data_source().find(None, None) {
+            Ok(cursor) => cursor,
+            Err(e) => {
+                warn!("Unable to build the search index: {}", e);
+                return;
+            }
+        };
+        let samples: Vec<IndexedSample> = cursor
+            .filter_map(|document| document.ok())
+            .filter_map(|document| IndexedSample::from_document(&document))
+            .collect();
+        info!("Indexing {} samples for fuzzy search", samples.len());
+        if let Err(e) = self.upsert(&samples) {
+            warn!("Unable to build the search index: {}", e);
+        }
+    }
+}
+
+#[cfg(feature = "fuzzy-search")]
+pub use self::tantivy_index::SearchIndex;
+
+#[cfg(not(feature = "fuzzy-search"))]
+pub use self::disabled::SearchIndex;
+
+#[cfg(feature = "fuzzy-search")]
+mod tantivy_index {
+    use std::sync::Mutex;
+    use tantivy::collector::TopDocs;
+    use tantivy::directory::MmapDirectory;
+    use tantivy::query::{BooleanQuery, FuzzyTermQuery, Occur, Query, TermQuery};
+    use tantivy::schema::{Field, IndexRecordOption, Schema, STORED, STRING, TEXT};
+    use tantivy::{Index, IndexReader, IndexWriter, ReloadPolicy, Term};
+
+    use super::{index_key, IndexedSample, NO_TENANT};
+
+    /// Heap handed to the index writer
+    const WRITER_HEAP: usize = 50_000_000;
+
+    /// A tantivy index of sample names and descriptions persisted in a local directory
+    pub struct SearchIndex {
+        reader: IndexReader,
+        writer: Mutex<IndexWriter>,
+        key: Field,
+        id: Field,
+        tenant: Field,
+        name: Field,
+        description: Field,
+    }
+
+    impl SearchIndex {
+        pub fn open(dir: &str) -> Result<SearchIndex, String> {
+            let mut builder = Schema::builder();
+            let key = builder.add_text_field("key", STRING);
+            let id = builder.add_text_field("id", STRING | STORED);
+            let tenant = builder.add_text_field("tenant", STRING);
+            let name = builder.add_text_field("name", TEXT);
+            let description = builder.add_text_field("description", TEXT);
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+            let directory = MmapDirectory::open(dir).map_err(|e| e.to_string())?;
+            let index =
+                Index::open_or_create(directory, builder.build()).map_err(|e| e.to_string())?;
+            let writer = index.writer(WRITER_HEAP).map_err(|e| e.to_string())?;
+            let reader = index
+                .reader_builder()
+                .reload_policy(ReloadPolicy::Manual)
+                .try_into()
+                .map_err(|e| e.to_string())?;
+            Ok(SearchIndex {
+                reader,
+                writer: Mutex::new(writer),
+                key,
+                id,
+                tenant,
+                name,
+                description,
+            })
+        }
+
+        pub fn upsert(&self, samples: &[IndexedSample]) -> Result<(), String> {
+            let mut writer = self.writer.lock().unwrap();
+            for sample in samples {
+                writer.delete_term(Term::from_field_text(self.key, &sample.key()));
+                let mut document = tantivy::Document::new();
+                document.add_text(self.key, &sample.key());
+                document.add_text(self.id, &sample.id);
+                document.add_text(
+                    self.tenant,
+                    sample.tenant_id.as_deref().unwrap_or(NO_TENANT),
+                );
+                document.add_text(self.name, &sample.name);
+                if let Some(description) = &sample.description {
+                    document.add_text(self.description, description);
+                }
+                writer.add_document(document);
+            }
+            writer.commit().map_err(|e| e.to_string())?;
+            // reloading right away makes a write visible to the searches that follow it
+            self.reader.reload().map_err(|e| e.to_string())
+        }
+
+        pub fn remove(&self, tenant_id: &Option<String>, id: &str) -> Result<(), String> {
+            let mut writer = self.writer.lock().unwrap();
+            writer.delete_term(Term::from_field_text(self.key, &index_key(tenant_id, id)));
+            writer.commit().map_err(|e| e.to_string())?;
+            self.reader.reload().map_err(|e| e.to_string())
+        }
+
+        /// The ids of the best matching samples of a tenant, skipping the first `offset`. Every
+        /// word has to match a name or description within an edit distance growing with its
+        /// length, the last word may be a prefix.
+        pub fn search(
+            &self,
+            tenant_id: &Option<String>,
+            search_term: &str,
+            offset: usize,
+            limit: usize,
+        ) -> Result<Vec<String>, String> {
+            let words: Vec<String> = search_term
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|word| !word.is_empty())
+                .map(|word| word.to_lowercase())
+                .collect();
+            if words.is_empty() {
+                return Ok(vec![]);
+            }
+            let tenant =
+                Term::from_field_text(self.tenant, tenant_id.as_deref().unwrap_or(NO_TENANT));
+            let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![(
+                Occur::Must,
+                Box::new(TermQuery::new(tenant, IndexRecordOption::Basic)),
+            )];
+            for (i, word) in words.iter().enumerate() {
+                let distance = match word.chars().count() {
+                    0..=3 => 0,
+                    4..=7 => 1,
+                    _ => 2,
+                };
+                let prefix = i == words.len() - 1;
+                let fields: Vec<(Occur, Box<dyn Query>)> = vec![self.name, self.description]
+                    .into_iter()
+                    .map(|field| {
+                        let term = Term::from_field_text(field, word);
+                        let query = if prefix {
+                            FuzzyTermQuery::new_prefix(term, distance, true)
+                        } else {
+                            FuzzyTermQuery::new(term, distance, true)
+                        };
+                        (Occur::Should, Box::new(query) as Box<dyn Query>)
+                    })
+                    .collect();
+                clauses.push((Occur::Must, Box::new(BooleanQuery::from(fields))));
+            }
+            let query = BooleanQuery::from(clauses);
+            let searcher = self.reader.searcher();
+            let top = searcher
+                .search(&query, &TopDocs::with_limit(offset + limit))
+                .map_err(|e| e.to_string())?;
+            let mut ids = vec![];
+            for (_score, address) in top.into_iter().skip(offset) {
+                let document = searcher.doc(address).map_err(|e| e.to_string())?;
+                if let Some(id) = document.get_first(self.id).and_then(|value| value.text()) {
+                    ids.push(id.to_string());
+                }
+            }
+            Ok(ids)
+        }
+    }
+}
+
+#[cfg(not(feature = "fuzzy-search"))]
+mod disabled {
+    use super::IndexedSample;
+
+    /// Stands in for the index when the `fuzzy-search` feature is disabled
+    pub struct SearchIndex;
+
+    impl SearchIndex {
+        pub fn open(_dir: &str) -> Result<SearchIndex, String> {
+            Err("the fuzzy-search feature is not enabled".to_string())
+        }
+
+        pub fn upsert(&self, _samples: &[IndexedSample]) -> Result<(), String> {
+            Ok(())
+        }
+
+        pub fn remove(&self, _tenant_id: &Option<String>, _id: &str) -> Result<(), String> {
+            Ok(())
+        }
+
+        pub fn search(
+            &self,
+            _tenant_id: &Option<String>,
+            _search_term: &str,
+            _offset: usize,
+            _limit: usize,
+        ) -> Result<Vec<String>, String> {
+            Ok(vec![])
+        }
+    }
+}
@@ -3,6 +3,8 @@ extern crate cached;
 #[macro_use]
 extern crate lazy_static;
 
+pub mod auth;
+pub mod cache;
 pub mod db;
 pub mod models;
 pub mod routes;
@@ -3,6 +3,8 @@ extern crate cached;
 #[macro_use]
 extern crate lazy_static;
 
+pub mod auth;
+pub mod cache;
 pub mod db;
 pub mod models;
 pub mod routes;
@@ -12,12 +14,13 @@ use actix_cors::Cors;
 use actix_web::middleware::{DefaultHeaders, Logger};
 use actix_web::{App, HttpServer};
 use dotenv::dotenv;
-use jwt_validator::CertSources;
 use std::env;
 use std::io;
 use std::sync::Arc;
+use std::time::Duration;
 use uuid::Uuid;
 
+use auth::{refresh_revocations, Issuers};
 use db::Clients;
 use schema::create_schema;
 
@@ -39,17 +42,46 @@ async fn main() -> io::Result<()> {
 
     let db_clients = Arc::new(Clients {
         mongo: db::mongo::connect(),
+        tenant_databases: db::mongo::connect_tenants(),
+        search_index: db::search_index::connect_search_index(),
     });
+    db::migrations::migrate(&db_clients.mongo);
+    if let Some(search_index) = &db_clients.search_index {
+        search_index.rebuild(&db_clients);
+    }
+    // writes made through other replicas sharing the cache evict what this one holds
+    cache::subscribe();
+    // so do writes seen on the samples change stream, wherever they were made
+    cache::changes::watch_samples();
 
-    let cert_sources: Vec<String> = dotenv::var("CERTS")
-        .unwrap_or("https://www.googleapis.com/oauth2/v2/certs".to_owned())
-        .split(",")
-        .map(|s| s.to_owned())
-        .collect();
-    // this pulls down the sources so that we can validate them
-    let certs = CertSources::new(cert_sources);
-    let _cert_result = certs.build_keys().await;
-    let certs_client = Arc::new(certs);
+    let jwks_refresh_interval: u64 = env::var("JWKS_REFRESH_INTERVAL")
+        .unwrap_or("".to_string())
+        .parse()
+        .unwrap_or(3600);
+
+    // this pulls down the signing keys of every trusted issuer so that we can validate tokens
+    let issuers = Arc::new(Issuers::from_env());
+    issuers.refresh().await;
+    let refreshing_issuers = issuers.clone();
+    actix_rt::spawn(async move {
+        loop {
+            actix_rt::time::delay_for(Duration::from_secs(jwks_refresh_interval)).await;
+            refreshing_issuers.refresh().await;
+        }
+    });
+
+    // revoked tokens are checked against an in-memory list, reloaded so that revocations made
+    // through other instances are picked up
+    let revocation_refresh_interval: u64 = env::var("REVOCATION_REFRESH_INTERVAL")
+        .unwrap_or("".to_string())
+        .parse()
+        .unwrap_or(30);
+    refresh_revocations(&db_clients);
+    let revocation_clients = db_clients.clone();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(Duration::from_secs(revocation_refresh_interval));
+        refresh_revocations(&revocation_clients);
+    });
 
     let gql = std::sync::Arc::new(create_schema());
     // Start http server
@@ -57,7 +89,7 @@ async fn main() -> io::Result<()> {
         App::new()
             .data(gql.clone())
             .data(db_clients.clone())
-            .data(certs_client.clone())
+            .data(issuers.clone())
             .wrap(Cors::new().finish())
             .wrap(DefaultHeaders::new().header("x-request-id", Uuid::new_v4().to_string()))
             .wrap(Logger::new("IP:%a DATETIME:%t REQUEST:\"%r\" STATUS: %s DURATION:%D X-REQUEST-ID:%{x-request-id}o")
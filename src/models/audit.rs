@@ -0,0 +1,230 @@
+use bson::{Bson, Document};
+use chrono::{DateTime, TimeZone, Utc};
+use mongodb_base_service::{Node, NodeDetails, ID};
+use mongodb_cursor_pagination::FindResult;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+use crate::auth::Identity;
+use crate::models::PageInfo;
+use crate::schema::Context;
+
+/// Fields left out of diffs, either bookkeeping or secrets
+const IGNORED_FIELDS: [&str; 3] = ["_id", "node", "hash"];
+
+/// A field changed by a mutation, values are rendered in their bson display form
+#[derive(juniper::GraphQLObject, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// Diffs the top level fields of a document before and after a write
+pub fn diff_documents(before: &Option<Document>, after: &Option<Document>) -> Vec<FieldChange> {
+    let empty = Document::new();
+    let before = before.as_ref().unwrap_or(&empty);
+    let after = after.as_ref().unwrap_or(&empty);
+    let fields: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    fields
+        .into_iter()
+        .filter(|field| !IGNORED_FIELDS.contains(&field.as_str()))
+        .filter_map(|field| {
+            let (old, new) = (before.get(field), after.get(field));
+            if old == new {
+                return None;
+            }
+            let render = |value: Option<&Bson>| match value {
+                None | Some(Bson::Null) => None,
+                Some(value) => Some(value.to_string()),
+            };
+            Some(FieldChange {
+                field: field.clone(),
+                before: render(old),
+                after: render(new),
+            })
+        })
+        .collect()
+}
+
+/// A mutation recorded in the `audit_log` collection
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    #[serde(rename = "_id")]
+    pub id: ID,
+    pub node: NodeDetails,
+    pub operation: String,
+    pub actor: Identity,
+    pub impersonated_by: Option<Identity>,
+    pub tenant_id: Option<String>,
+    pub target_id: String,
+    /// Other documents touched by the mutation, e.g. embedded values
+    pub related_ids: Vec<String>,
+    pub request_id: String,
+    pub timestamp: i64,
+    pub changes: Vec<FieldChange>,
+}
+
+impl Node for AuditRecord {
+    fn node(&self) -> &NodeDetails {
+        &self.node
+    }
+}
+
+#[juniper::object(Context = Context, description = "A mutation recorded in the audit log")]
+impl AuditRecord {
+    fn id(&self) -> &ID {
+        &self.id
+    }
+
+    /// The mutation, e.g. `updateSample`
+    fn operation(&self) -> &str {
+        &self.operation
+    }
+
+    /// The identity the mutation was made as
+    fn actor(&self) -> &Identity {
+        &self.actor
+    }
+
+    /// The real caller when the mutation was made through `x-act-as`
+    fn impersonated_by(&self) -> &Option<Identity> {
+        &self.impersonated_by
+    }
+
+    fn tenant_id(&self) -> &Option<String> {
+        &self.tenant_id
+    }
+
+    fn target_id(&self) -> &str {
+        &self.target_id
+    }
+
+    fn related_ids(&self) -> &Vec<String> {
+        &self.related_ids
+    }
+
+    fn request_id(&self) -> &str {
+        &self.request_id
+    }
+
+    fn timestamp(&self) -> DateTime<Utc> {
+        Utc.timestamp(self.timestamp, 0)
+    }
+
+    fn changes(&self) -> &Vec<FieldChange> {
+        &self.changes
+    }
+}
+
+#[derive(Serialize)]
+pub struct AuditInsert {
+    pub operation: String,
+    pub actor: Identity,
+    pub impersonated_by: Option<Identity>,
+    pub tenant_id: Option<String>,
+    pub target_id: String,
+    pub related_ids: Vec<String>,
+    pub request_id: String,
+    pub timestamp: i64,
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(juniper::GraphQLInputObject, Default)]
+pub struct AuditLogFilter {
+    pub actor_id: Option<String>,
+    pub operation: Option<String>,
+    pub target_id: Option<String>,
+    pub request_id: Option<String>,
+    /// Only records made at or after this time
+    pub from: Option<DateTime<Utc>>,
+    /// Only records made before this time
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl AuditLogFilter {
+    pub fn to_document(&self) -> Document {
+        let mut filter = Document::new();
+        if let Some(actor_id) = &self.actor_id {
+            filter.insert("actor.id", actor_id.clone());
+        }
+        if let Some(operation) = &self.operation {
+            filter.insert("operation", operation.clone());
+        }
+        if let Some(target_id) = &self.target_id {
+            filter.insert("target_id", target_id.clone());
+        }
+        if let Some(request_id) = &self.request_id {
+            filter.insert("request_id", request_id.clone());
+        }
+        let mut timestamp = Document::new();
+        if let Some(from) = &self.from {
+            timestamp.insert("$gte", from.timestamp());
+        }
+        if let Some(to) = &self.to {
+            timestamp.insert("$lt", to.timestamp());
+        }
+        if !timestamp.is_empty() {
+            filter.insert("timestamp", timestamp);
+        }
+        filter
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AuditRecordEdge {
+    pub cursor: String,
+    pub node: AuditRecord,
+}
+
+#[juniper::object(Context = Context)]
+impl AuditRecordEdge {
+    fn cursor(&self) -> &str {
+        &self.cursor
+    }
+
+    fn node(&self) -> &AuditRecord {
+        &self.node
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AuditRecordConnection {
+    pub page_info: PageInfo,
+    pub edges: Vec<AuditRecordEdge>,
+    pub total_count: i64,
+}
+
+#[juniper::object(Context = Context)]
+impl AuditRecordConnection {
+    fn page_info(&self) -> &PageInfo {
+        &self.page_info
+    }
+
+    fn edges(&self) -> &Vec<AuditRecordEdge> {
+        &self.edges
+    }
+
+    fn total_count(&self) -> i32 {
+        self.total_count as i32
+    }
+}
+
+impl From<FindResult<AuditRecord>> for AuditRecordConnection {
+    fn from(fr: FindResult<AuditRecord>) -> AuditRecordConnection {
+        let edges = fr
+            .edges
+            .into_iter()
+            .zip(fr.items.into_iter())
+            .map(|(edge, node)| AuditRecordEdge {
+                cursor: edge.cursor,
+                node,
+            })
+            .collect();
+        AuditRecordConnection {
+            page_info: fr.page_info.into(),
+            edges,
+            total_count: fr.total_count,
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use bson::Document;
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use juniper::FieldError;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+use crate::schema::Context;
+
+/// Recurrences without a `COUNT` or `UNTIL` are materialized up to this many occurrences
+pub const MAX_OCCURRENCES: usize = 1000;
+
+/// Occurrences starting more than 100 years after their window are never materialized
+pub const MATERIALIZATION_HORIZON: i64 = 100 * 366 * 86400;
+
+/// The most availability windows a sample may have, each of them stores up to
+/// `MAX_OCCURRENCES` occurrences on it
+pub const MAX_WINDOWS: usize = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// A subset of iCalendar RRULEs: `FREQ=WEEKLY;INTERVAL=2;COUNT=10` or
+/// `FREQ=YEARLY;UNTIL=20300101T000000Z`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    pub interval: i64,
+    pub count: Option<usize>,
+    pub until: Option<i64>,
+}
+
+impl Recurrence {
+    pub fn parse(rule: &str) -> Result<Recurrence, String> {
+        let rule = rule.trim();
+        let rule = rule.strip_prefix("RRULE:").unwrap_or(rule);
+        let mut frequency = None;
+        let mut recurrence = Recurrence {
+            frequency: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+        };
+        for part in rule.split(';').filter(|part| !part.is_empty()) {
+            let mut pair = part.splitn(2, '=');
+            let (key, value) = match (pair.next(), pair.next()) {
+                (Some(key), Some(value)) => (key.to_uppercase(), value),
+                _ => return Err(format!("Invalid recurrence rule part {}", part)),
+            };
+            match key.as_str() {
+                "FREQ" => {
+                    frequency = Some(match value.to_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(format!("Unsupported recurrence frequency {}", value)),
+                    })
+                }
+                "INTERVAL" => {
+                    recurrence.interval = match value.parse() {
+                        Ok(interval) if (1..=1000).contains(&interval) => interval,
+                        _ => return Err(format!("Invalid recurrence interval {}", value)),
+                    }
+                }
+                "COUNT" => {
+                    recurrence.count = match value.parse() {
+                        Ok(count) if (1..=MAX_OCCURRENCES).contains(&count) => Some(count),
+                        _ => return Err(format!("Invalid recurrence count {}", value)),
+                    }
+                }
+                "UNTIL" => {
+                    let until = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ")
+                        .map_err(|_| format!("Invalid recurrence end {}", value))?;
+                    recurrence.until = Some(until.timestamp());
+                }
+                _ => return Err(format!("Unsupported recurrence rule part {}", key)),
+            }
+        }
+        recurrence.frequency = frequency.ok_or("Recurrence rules need a FREQ")?;
+        Ok(recurrence)
+    }
+
+    /// Whether the recurrence stops, otherwise only its first occurrences are materialized
+    pub fn is_bounded(&self) -> bool {
+        self.count.is_some() || self.until.is_some()
+    }
+
+    /// Shifts a time by `n` periods of the recurrence, `None` past the dates chrono can represent
+    fn shift(&self, timestamp: i64, n: i64) -> Option<i64> {
+        let periods = n.checked_mul(self.interval)?;
+        match self.frequency {
+            Frequency::Daily => timestamp.checked_add(periods.checked_mul(86400)?),
+            Frequency::Weekly => timestamp.checked_add(periods.checked_mul(7 * 86400)?),
+            Frequency::Monthly => add_months(timestamp, periods),
+            Frequency::Yearly => add_months(timestamp, periods.checked_mul(12)?),
+        }
+    }
+}
+
+/// Adds calendar months, clamping the day to the length of the target month
+fn add_months(timestamp: i64, months: i64) -> Option<i64> {
+    let time = Utc.timestamp_opt(timestamp, 0).single()?.naive_utc();
+    let total = (time.year() as i64 * 12 + time.month0() as i64).checked_add(months)?;
+    let year = i32::try_from(total.div_euclid(12)).ok()?;
+    let month = total.rem_euclid(12) as u32 + 1;
+    (1..=time.day())
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+        .map(|date| date.and_time(time.time()).timestamp())
+}
+
+/// A period in which a sample is available, repeated when it has a rule
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AvailabilityWindow {
+    pub id: String,
+    pub start: i64,
+    pub end: Option<i64>,
+    pub rule: Option<String>,
+}
+
+#[juniper::object(Context = Context, description = "A period in which a sample is available")]
+impl AvailabilityWindow {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn start(&self) -> DateTime<Utc> {
+        Utc.timestamp(self.start, 0)
+    }
+
+    /// Open ended when null
+    fn end(&self) -> Option<DateTime<Utc>> {
+        self.end.map(|end| Utc.timestamp(end, 0))
+    }
+
+    /// An RRULE such as `FREQ=YEARLY;COUNT=5` repeating the window
+    fn rule(&self) -> &Option<String> {
+        &self.rule
+    }
+}
+
+#[derive(juniper::GraphQLInputObject)]
+pub struct NewAvailabilityWindow {
+    pub start: DateTime<Utc>,
+    /// Required for recurring windows
+    pub end: Option<DateTime<Utc>>,
+    pub rule: Option<String>,
+}
+
+impl NewAvailabilityWindow {
+    pub fn into_window(self, id: String) -> Result<AvailabilityWindow, FieldError> {
+        let window = AvailabilityWindow {
+            id,
+            start: self.start.timestamp(),
+            end: self.end.map(|end| end.timestamp()),
+            rule: self.rule,
+        };
+        if window.end.map_or(false, |end| end <= window.start) {
+            return Err("Availability windows must end after they start".into());
+        }
+        if let Some(rule) = &window.rule {
+            Recurrence::parse(rule)?;
+            if window.end.is_none() {
+                return Err("Recurring availability windows need an end".into());
+            }
+        }
+        Ok(window)
+    }
+}
+
+/// A single period of availability, materialized from a window
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Occurrence {
+    pub start: i64,
+    pub end: Option<i64>,
+}
+
+impl Occurrence {
+    /// Open ended occurrences never count as active, like samples without an expiration date
+    pub fn is_active(&self, timestamp: i64) -> bool {
+        self.start < timestamp && self.end.map_or(false, |end| end > timestamp)
+    }
+}
+
+impl AvailabilityWindow {
+    pub fn occurrences(&self) -> Vec<Occurrence> {
+        let recurrence = match self.rule.as_deref().map(Recurrence::parse) {
+            Some(Ok(recurrence)) => recurrence,
+            _ => {
+                return vec![Occurrence {
+                    start: self.start,
+                    end: self.end,
+                }]
+            }
+        };
+        let limit = recurrence.count.unwrap_or(MAX_OCCURRENCES);
+        let horizon = self.start.saturating_add(MATERIALIZATION_HORIZON);
+        let until = recurrence.until.map_or(horizon, |until| until.min(horizon));
+        (0..limit as i64)
+            .map(|n| {
+                let start = recurrence.shift(self.start, n)?;
+                let end = match self.end {
+                    Some(end) => Some(recurrence.shift(end, n)?),
+                    None => None,
+                };
+                Some(Occurrence { start, end })
+            })
+            .take_while(|occurrence| {
+                occurrence.map_or(false, |occurrence| occurrence.start <= until)
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// Whether the window ever ends
+    fn is_bounded(&self) -> bool {
+        let bounded_rule = match self.rule.as_deref().map(Recurrence::parse) {
+            Some(Ok(recurrence)) => recurrence.is_bounded(),
+            _ => true,
+        };
+        self.end.is_some() && bounded_rule
+    }
+}
+
+/// What is stored on a sample to filter by status: its occurrences and, as
+/// `available_date`/`expiration_date`, their bounds
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Availability {
+    pub occurrences: Vec<Occurrence>,
+    pub available_date: Option<i64>,
+    pub expiration_date: Option<i64>,
+}
+
+impl Availability {
+    /// Derives the availability from the windows of a sample. Samples without windows keep
+    /// their dates, which make up their only occurrence.
+    pub fn of(
+        windows: &[AvailabilityWindow],
+        available_date: Option<i64>,
+        expiration_date: Option<i64>,
+    ) -> Availability {
+        if windows.is_empty() {
+            let occurrences = available_date
+                .map(|start| Occurrence {
+                    start,
+                    end: expiration_date,
+                })
+                .into_iter()
+                .collect();
+            return Availability {
+                occurrences,
+                available_date,
+                expiration_date,
+            };
+        }
+        let mut occurrences: Vec<Occurrence> = windows
+            .iter()
+            .flat_map(|window| window.occurrences())
+            .collect();
+        occurrences.sort_by_key(|occurrence| occurrence.start);
+        let expiration_date = if windows.iter().all(|window| window.is_bounded()) {
+            occurrences
+                .iter()
+                .filter_map(|occurrence| occurrence.end)
+                .max()
+        } else {
+            None
+        };
+        Availability {
+            available_date: occurrences.first().map(|occurrence| occurrence.start),
+            expiration_date,
+            occurrences,
+        }
+    }
+
+    /// The `$set` storing the availability on a sample
+    pub fn to_document(&self) -> Result<Document, FieldError> {
+        match bson::to_bson(self)? {
+            bson::Bson::Document(document) => Ok(document),
+            _ => Err("Unable to store availability".into()),
+        }
+    }
+}
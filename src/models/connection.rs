@@ -0,0 +1,87 @@
+use juniper::FieldError;
+use mongodb_cursor_pagination::PageInfo as CursorPageInfo;
+use serde::{Deserialize, Serialize};
+
+use crate::schema::Context;
+
+/// Relay compliant page info shared by all connection types
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PageInfo {
+    pub has_next_page: bool,
+    pub has_previous_page: bool,
+    pub start_cursor: Option<String>,
+    pub end_cursor: Option<String>,
+}
+
+#[juniper::object(Context = Context)]
+impl PageInfo {
+    fn has_next_page(&self) -> bool {
+        self.has_next_page
+    }
+
+    fn has_previous_page(&self) -> bool {
+        self.has_previous_page
+    }
+
+    fn start_cursor(&self) -> &Option<String> {
+        &self.start_cursor
+    }
+
+    fn end_cursor(&self) -> &Option<String> {
+        &self.end_cursor
+    }
+
+    #[graphql(deprecated = "Use endCursor instead")]
+    fn next_cursor(&self) -> &Option<String> {
+        &self.end_cursor
+    }
+}
+
+impl From<CursorPageInfo> for PageInfo {
+    fn from(page_info: CursorPageInfo) -> PageInfo {
+        PageInfo {
+            has_next_page: page_info.has_next_page,
+            has_previous_page: page_info.has_previous_page,
+            start_cursor: page_info.start_cursor,
+            end_cursor: page_info.next_cursor,
+        }
+    }
+}
+
+/// Resolves the page size from the relay `first`/`last` arguments, falling back
+/// to the legacy `limit` argument.
+pub fn page_limit(
+    limit: Option<i32>,
+    first: Option<i32>,
+    last: Option<i32>,
+    before: &Option<String>,
+) -> Result<Option<i32>, FieldError> {
+    match (first, last) {
+        (Some(_), Some(_)) => Err("Only one of `first` or `last` may be provided".into()),
+        (Some(count), None) | (None, Some(count)) if count < 0 => {
+            Err("`first` and `last` must not be negative".into())
+        }
+        (None, Some(_)) if before.is_none() => {
+            Err("`last` can only be used together with a `before` cursor".into())
+        }
+        (Some(count), None) | (None, Some(count)) => Ok(Some(count)),
+        (None, None) => Ok(limit),
+    }
+}
+
+/// Pages assembled within a single aggregation hold this many items unless asked otherwise
+pub const DEFAULT_PAGE_SIZE: i32 = 100;
+
+/// The most items a page assembled within a single aggregation may hold
+pub const MAX_PAGE_SIZE: i32 = 1000;
+
+/// Applies the default and maximum page size to a limit resolved by `page_limit`
+pub fn bounded_page_size(limit: Option<i32>) -> Result<i32, FieldError> {
+    match limit {
+        Some(limit) if limit > MAX_PAGE_SIZE => {
+            Err(format!("At most {} items may be requested at once", MAX_PAGE_SIZE).into())
+        }
+        Some(limit) => Ok(limit),
+        None => Ok(DEFAULT_PAGE_SIZE),
+    }
+}
@@ -50,6 +50,10 @@ pub enum EmbeddedType {
     Another,
 }
 
+impl EmbeddedType {
+    pub const ALL: [EmbeddedType; 2] = [EmbeddedType::One, EmbeddedType::Another];
+}
+
 #[derive(Serialize, Deserialize, juniper::GraphQLInputObject)]
 pub struct NewEmbedded {
     #[serde(rename = "_id")]
@@ -0,0 +1,218 @@
+use bson::{doc, Bson, Document};
+use chrono::{DateTime, Utc};
+use juniper::FieldError;
+
+use crate::auth::{forbidden, Permission, Principal};
+use crate::models::EmbeddedType;
+
+/// Limits that keep client supplied filters cheap to run
+const MAX_DEPTH: usize = 5;
+const MAX_IN_VALUES: usize = 100;
+const MAX_PATTERN_LENGTH: usize = 256;
+
+#[derive(juniper::GraphQLInputObject, Clone, Debug, Default)]
+pub struct StringFilter {
+    pub eq: Option<String>,
+    /// Case insensitive substring match
+    pub contains: Option<String>,
+    /// A regular expression, limited to 256 characters
+    pub regex: Option<String>,
+    #[graphql(name = "in")]
+    pub any_of: Option<Vec<String>>,
+}
+
+/// A range of dates, `from` is inclusive and `to` exclusive
+#[derive(juniper::GraphQLInputObject, Clone, Debug, Default)]
+pub struct DateRange {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// Matches samples with at least one value in the range and of the type
+#[derive(juniper::GraphQLInputObject, Clone, Debug, Default)]
+pub struct ValueFilter {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub embedded_type: Option<EmbeddedType>,
+}
+
+#[derive(juniper::GraphQLInputObject, Clone, Debug, Default)]
+pub struct SampleFilter {
+    pub name: Option<StringFilter>,
+    pub description: Option<StringFilter>,
+    pub date_created: Option<DateRange>,
+    pub date_modified: Option<DateRange>,
+    pub available_date: Option<DateRange>,
+    pub expiration_date: Option<DateRange>,
+    pub values: Option<ValueFilter>,
+    /// The id of the identity that created the sample, requires `samples:read_audit`
+    pub created_by: Option<String>,
+    /// Every filter has to match
+    pub and: Option<Vec<SampleFilter>>,
+    /// Any of the filters has to match
+    pub or: Option<Vec<SampleFilter>>,
+    /// The filter must not match
+    pub not: Option<Box<SampleFilter>>,
+}
+
+/// Escapes a literal so it can be embedded in a regular expression
+pub(crate) fn escape_regex(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+impl StringFilter {
+    fn clauses(&self, field: &str) -> Result<Vec<Document>, FieldError> {
+        let mut clauses = vec![];
+        if let Some(eq) = &self.eq {
+            clauses.push(doc! { field: eq.clone() });
+        }
+        if let Some(contains) = &self.contains {
+            clauses.push(doc! { field: { "$regex": escape_regex(contains), "$options": "i" } });
+        }
+        if let Some(regex) = &self.regex {
+            if regex.len() > MAX_PATTERN_LENGTH {
+                return Err(format!(
+                    "Regular expressions are limited to {} characters",
+                    MAX_PATTERN_LENGTH
+                )
+                .into());
+            }
+            clauses.push(doc! { field: { "$regex": regex.clone() } });
+        }
+        if let Some(any_of) = &self.any_of {
+            if any_of.len() > MAX_IN_VALUES {
+                return Err(format!("`in` is limited to {} values", MAX_IN_VALUES).into());
+            }
+            clauses.push(doc! { field: { "$in": any_of.clone() } });
+        }
+        Ok(clauses)
+    }
+}
+
+impl DateRange {
+    fn clause(&self, field: &str) -> Option<Document> {
+        let mut range = Document::new();
+        if let Some(from) = &self.from {
+            range.insert("$gte", from.timestamp());
+        }
+        if let Some(to) = &self.to {
+            range.insert("$lt", to.timestamp());
+        }
+        if range.is_empty() {
+            None
+        } else {
+            Some(doc! { field: range })
+        }
+    }
+}
+
+impl ValueFilter {
+    fn clause(&self) -> Result<Option<Document>, FieldError> {
+        let mut matches = Document::new();
+        let mut range = Document::new();
+        if let Some(min) = self.min {
+            range.insert("$gte", min);
+        }
+        if let Some(max) = self.max {
+            range.insert("$lte", max);
+        }
+        if !range.is_empty() {
+            matches.insert("value", range);
+        }
+        if let Some(embedded_type) = &self.embedded_type {
+            matches.insert("embedded_type", bson::to_bson(embedded_type)?);
+        }
+        if matches.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(doc! { "values": { "$elemMatch": matches } }))
+        }
+    }
+}
+
+impl SampleFilter {
+    /// Translates the filter into a query, values are always bound as literals so that
+    /// clients can't inject operators. Filters on fields the principal can't read are
+    /// forbidden, since they would reveal them.
+    pub fn to_document(&self, principal: &Principal) -> Result<Document, FieldError> {
+        self.to_document_at(principal, 0)
+    }
+
+    fn to_document_at(&self, principal: &Principal, depth: usize) -> Result<Document, FieldError> {
+        if depth >= MAX_DEPTH {
+            return Err(format!("Filters can be nested at most {} levels deep", MAX_DEPTH).into());
+        }
+        let mut clauses = vec![];
+        if let Some(name) = &self.name {
+            clauses.extend(name.clauses("name")?);
+        }
+        if let Some(description) = &self.description {
+            clauses.extend(description.clauses("description")?);
+        }
+        let ranges = vec![
+            (&self.date_created, "node.date_created"),
+            (&self.date_modified, "node.date_modified"),
+            (&self.available_date, "available_date"),
+            (&self.expiration_date, "expiration_date"),
+        ];
+        for (range, field) in ranges {
+            if let Some(clause) = range.as_ref().and_then(|range| range.clause(field)) {
+                clauses.push(clause);
+            }
+        }
+        if let Some(values) = &self.values {
+            clauses.extend(values.clause()?);
+        }
+        if let Some(created_by) = &self.created_by {
+            if !principal.has_permission(Permission::ReadAudit) {
+                return Err(forbidden(Permission::ReadAudit));
+            }
+            clauses.push(doc! { "created_by.id": created_by.clone() });
+        }
+        if let Some(filters) = &self.and {
+            let filters = SampleFilter::documents(filters, principal, depth)?;
+            if !filters.is_empty() {
+                clauses.push(doc! { "$and": filters });
+            }
+        }
+        if let Some(filters) = &self.or {
+            let filters = SampleFilter::documents(filters, principal, depth)?;
+            if !filters.is_empty() {
+                clauses.push(doc! { "$or": filters });
+            }
+        }
+        if let Some(filter) = &self.not {
+            let filter = filter.to_document_at(principal, depth + 1)?;
+            if !filter.is_empty() {
+                clauses.push(doc! { "$nor": [filter] });
+            }
+        }
+        Ok(match clauses.len() {
+            0 => Document::new(),
+            1 => clauses.pop().unwrap(),
+            _ => doc! { "$and": clauses },
+        })
+    }
+
+    fn documents(
+        filters: &[SampleFilter],
+        principal: &Principal,
+        depth: usize,
+    ) -> Result<Vec<Bson>, FieldError> {
+        filters
+            .iter()
+            .map(|filter| {
+                filter
+                    .to_document_at(principal, depth + 1)
+                    .map(Bson::Document)
+            })
+            .collect()
+    }
+}
@@ -1,16 +1,25 @@
+mod audit;
+mod availability;
+mod connection;
 mod embedded;
+mod filter;
 mod sample;
+mod search;
+mod sort;
+mod statistics;
+mod status;
+mod timeline;
+mod values;
 
+pub use audit::*;
+pub use availability::*;
+pub use connection::*;
 pub use embedded::*;
+pub use filter::*;
 pub use sample::*;
-
-use serde::{Deserialize, Serialize};
-
-#[derive(juniper::GraphQLEnum, Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
-pub enum Status {
-    All,
-    Available,
-    Active,
-    Expired,
-    Pending,
-}
+pub use search::*;
+pub use sort::*;
+pub use statistics::*;
+pub use status::*;
+pub use timeline::*;
+pub use values::*;
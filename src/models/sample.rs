@@ -1,10 +1,15 @@
 use bson::doc;
 use chrono::{DateTime, TimeZone, Utc};
+use juniper::FieldError;
 use mongodb_base_service::{Node, NodeDetails, ID};
-use mongodb_cursor_pagination::{Edge, FindResult, PageInfo};
+use mongodb_cursor_pagination::FindResult;
 use serde::{Deserialize, Serialize};
 
-use crate::models::Embedded;
+use crate::auth::{AclEntry, Identity, Permission, Principal};
+use crate::models::{
+    offset_cursor, page_limit, sort_values, AvailabilityWindow, Embedded, EmbeddedConnection,
+    EmbeddedFilter, EmbeddedSort, Occurrence, PageInfo, Status, ValueAggregates, ValueBreakdown,
+};
 use crate::schema::Context;
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -14,9 +19,42 @@ pub struct Sample {
     pub node: NodeDetails,
     name: String,
     description: Option<String>,
-    available_date: Option<i64>,
-    expiration_date: Option<i64>,
+    pub available_date: Option<i64>,
+    pub expiration_date: Option<i64>,
+    /// When set, `available_date` and `expiration_date` are the bounds of the windows
+    pub windows: Option<Vec<AvailabilityWindow>>,
+    /// The windows expanded into single periods, missing on samples not yet migrated
+    pub occurrences: Option<Vec<Occurrence>>,
+    published: Option<bool>,
     pub values: Option<Vec<Embedded>>,
+    pub created_by: Option<Identity>,
+    pub updated_by: Option<Identity>,
+    /// The real caller behind the last write made through `x-act-as`
+    pub impersonated_by: Option<Identity>,
+    pub owner_id: Option<String>,
+    pub acl: Option<Vec<AclEntry>>,
+    pub tenant_id: Option<String>,
+    /// The time `status` is evaluated at when a query previews another time than now
+    #[serde(skip)]
+    pub evaluated_at: Option<i64>,
+}
+
+impl Sample {
+    /// The text of one of the `SEARCHABLE_FIELDS`
+    pub fn text(&self, field: &str) -> Option<&str> {
+        match field {
+            "name" => Some(&self.name),
+            "description" => self.description.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Owners and callers with `samples:read_audit` can see who owns a sample and its ACL
+    fn shows_access(&self, principal: &Principal) -> bool {
+        let is_owner = self.owner_id.is_some()
+            && principal.identity.as_ref().map(|identity| &identity.id) == self.owner_id.as_ref();
+        is_owner || principal.has_permission(Permission::ReadAudit)
+    }
 }
 
 impl Node for Sample {
@@ -39,20 +77,70 @@ impl Sample {
         self.node.date_modified()
     }
 
-    fn created_by(&self) -> Option<&ID> {
+    /// Only visible to callers with the `samples:read_audit` permission
+    fn created_by(&self, ctx: &Context) -> Option<&Identity> {
+        if !ctx.principal.has_permission(Permission::ReadAudit) {
+            return None;
+        }
+        self.created_by.as_ref()
+    }
+
+    /// Only visible to callers with the `samples:read_audit` permission
+    fn created_by_id(&self, ctx: &Context) -> Option<&ID> {
+        if !ctx.principal.has_permission(Permission::ReadAudit) {
+            return None;
+        }
         match self.node.created_by_id() {
             Some(id) => Some(id),
             None => None,
         }
     }
 
-    fn updated_by(&self) -> Option<&ID> {
+    /// Only visible to callers with the `samples:read_audit` permission
+    fn updated_by(&self, ctx: &Context) -> Option<&Identity> {
+        if !ctx.principal.has_permission(Permission::ReadAudit) {
+            return None;
+        }
+        self.updated_by.as_ref()
+    }
+
+    /// Only visible to callers with the `samples:read_audit` permission
+    fn updated_by_id(&self, ctx: &Context) -> Option<&ID> {
+        if !ctx.principal.has_permission(Permission::ReadAudit) {
+            return None;
+        }
         match self.node.updated_by_id() {
             Some(id) => Some(id),
             None => None,
         }
     }
 
+    /// The support engineer who made the last change while acting as `updatedBy`, only visible
+    /// to callers with the `samples:read_audit` permission
+    fn impersonated_by(&self, ctx: &Context) -> Option<&Identity> {
+        if !ctx.principal.has_permission(Permission::ReadAudit) {
+            return None;
+        }
+        self.impersonated_by.as_ref()
+    }
+
+    /// The identity the sample belongs to, only visible to the owner and callers with the
+    /// `samples:read_audit` permission
+    fn owner_id(&self, ctx: &Context) -> Option<&String> {
+        if !self.shows_access(&ctx.principal) {
+            return None;
+        }
+        self.owner_id.as_ref()
+    }
+
+    /// Only visible to the owner and callers with the `samples:read_audit` permission
+    fn acl(&self, ctx: &Context) -> Vec<&AclEntry> {
+        if !self.shows_access(&ctx.principal) {
+            return vec![];
+        }
+        self.acl.iter().flatten().collect()
+    }
+
     fn name(&self) -> &str {
         &self.name
     }
@@ -61,6 +149,11 @@ impl Sample {
         &self.description
     }
 
+    /// Unpublished samples are only returned to callers with `samples:read_unpublished`
+    fn published(&self) -> bool {
+        self.published.unwrap_or(true)
+    }
+
     fn available_date(&self) -> Option<DateTime<Utc>> {
         match self.available_date {
             Some(avail) => Some(Utc.timestamp(avail, 0)),
@@ -75,32 +168,96 @@ impl Sample {
         }
     }
 
-    fn values(&self) -> &Option<Vec<Embedded>> {
-        &self.values
+    /// Computed from the stored dates with the semantics of `samplesByStatus`, so a sample
+    /// without an expiration date is `AVAILABLE` rather than `ACTIVE`. Null when the dates
+    /// don't decide it.
+    fn status(&self, ctx: &Context) -> Option<Status> {
+        let timestamp = self.evaluated_at.unwrap_or_else(|| ctx.clock.now());
+        Status::of(
+            self.available_date,
+            self.expiration_date,
+            self.occurrences.as_deref(),
+            timestamp,
+        )
+    }
+
+    /// The periods the sample is available in, empty when only its dates are set
+    fn windows(&self) -> Vec<&AvailabilityWindow> {
+        self.windows.iter().flatten().collect()
     }
 
-    fn min_value(&self) -> f64 {
-        self.values
-            .clone()
-            .unwrap_or(vec![])
+    /// The embedded values in stored order unless `sort` is given, the cursor of a value is its
+    /// id
+    fn values(
+        &self,
+        filter: Option<EmbeddedFilter>,
+        sort: Option<Vec<EmbeddedSort>>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<EmbeddedConnection, FieldError> {
+        let limit = page_limit(None, first, None, &None)?;
+        let mut values: Vec<Embedded> = self
+            .values
             .iter()
-            .fold(0., |mut acc, value| {
-                let value = value.value.unwrap_or(0.);
-                if acc == 0. {
-                    acc = value
-                } else if value < acc && value != 0. {
-                    acc = value;
-                }
-                acc
-            })
+            .flatten()
+            .filter(|value| filter.as_ref().map_or(true, |filter| filter.matches(value)))
+            .cloned()
+            .collect();
+        sort_values(&mut values, sort.as_deref().unwrap_or(&[]));
+        EmbeddedConnection::page(values, limit, after)
+    }
+
+    /// Null when no value is set
+    fn min_value(&self) -> Option<f64> {
+        self.value_aggregates().min_value
+    }
+
+    fn max_value(&self) -> Option<f64> {
+        self.value_aggregates().max_value
+    }
+
+    fn avg_value(&self) -> Option<f64> {
+        self.value_aggregates().avg_value
+    }
+
+    /// The number of embedded values, including those without a value
+    fn value_count(&self) -> i32 {
+        self.value_aggregates().value_count
+    }
+
+    /// The aggregates of the values of each embedded type
+    fn value_breakdown(&self) -> Vec<ValueBreakdown> {
+        ValueBreakdown::of(self.values.as_deref().unwrap_or(&[]))
+    }
+}
+
+impl Sample {
+    pub fn value_aggregates(&self) -> ValueAggregates {
+        ValueAggregates::of(self.values.iter().flatten())
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SampleEdge {
+    pub cursor: String,
+    pub node: Sample,
+}
+
+#[juniper::object(Context = Context)]
+impl SampleEdge {
+    fn cursor(&self) -> &str {
+        &self.cursor
+    }
+
+    fn node(&self) -> &Sample {
+        &self.node
     }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct SampleConnection {
     pub page_info: PageInfo,
-    pub edges: Vec<Edge>,
-    pub items: Vec<Sample>,
+    pub edges: Vec<SampleEdge>,
     pub total_count: i64,
 }
 
@@ -110,12 +267,13 @@ impl SampleConnection {
         &self.page_info
     }
 
-    fn edges(&self) -> &Vec<Edge> {
+    fn edges(&self) -> &Vec<SampleEdge> {
         &self.edges
     }
 
-    fn items(&self) -> &Vec<Sample> {
-        &self.items
+    #[graphql(deprecated = "Use edges { node } instead")]
+    fn items(&self) -> Vec<&Sample> {
+        self.edges.iter().map(|edge| &edge.node).collect()
     }
 
     fn total_count(&self) -> i32 {
@@ -123,16 +281,61 @@ impl SampleConnection {
     }
 }
 
+impl SampleConnection {
+    /// Pages through samples ranked outside of Mongo, cursors are offsets into the ranking
+    pub fn page(samples: Vec<Sample>, offset: i64, limit: Option<i32>) -> SampleConnection {
+        let total_count = samples.len() as i64;
+        let limit = limit.map_or(samples.len(), |limit| limit as usize);
+        let edges: Vec<SampleEdge> = samples
+            .into_iter()
+            .enumerate()
+            .skip(offset as usize)
+            .take(limit)
+            .map(|(i, node)| SampleEdge {
+                cursor: offset_cursor(i as i64),
+                node,
+            })
+            .collect();
+        SampleConnection {
+            page_info: PageInfo {
+                has_next_page: offset + (edges.len() as i64) < total_count,
+                has_previous_page: offset > 0,
+                start_cursor: edges.first().map(|edge| edge.cursor.clone()),
+                end_cursor: edges.last().map(|edge| edge.cursor.clone()),
+            },
+            edges,
+            total_count,
+        }
+    }
+
+    /// Marks the samples with the time their status is evaluated at, now when `None`
+    pub fn evaluated_at(mut self, timestamp: Option<i64>) -> SampleConnection {
+        for edge in self.edges.iter_mut() {
+            edge.node.evaluated_at = timestamp;
+        }
+        self
+    }
+}
+
 impl From<FindResult<Sample>> for SampleConnection {
     fn from(fr: FindResult<Sample>) -> SampleConnection {
+        let edges = fr
+            .edges
+            .into_iter()
+            .zip(fr.items.into_iter())
+            .map(|(edge, node)| SampleEdge {
+                cursor: edge.cursor,
+                node,
+            })
+            .collect();
         SampleConnection {
-            page_info: fr.page_info,
-            edges: fr.edges,
-            items: fr.items,
+            page_info: fr.page_info.into(),
+            edges,
             total_count: fr.total_count,
         }
     }
 }
+
 #[derive(Serialize, Deserialize, juniper::GraphQLInputObject)]
 pub struct NewSample {
     #[serde(rename = "_id")]
@@ -141,6 +344,22 @@ pub struct NewSample {
     description: Option<String>,
     available_date: Option<i32>,
     expiration_date: Option<i32>,
+    published: Option<bool>,
+}
+
+/// A new sample along with the identity of its author, who becomes its owner
+#[derive(Serialize)]
+pub struct SampleInsert {
+    #[serde(flatten)]
+    pub sample: NewSample,
+    pub created_by: Identity,
+    pub updated_by: Identity,
+    pub owner_id: String,
+    pub tenant_id: Option<String>,
+    pub impersonated_by: Option<Identity>,
+    /// New samples have no values yet
+    #[serde(flatten)]
+    pub aggregates: ValueAggregates,
 }
 
 #[derive(Serialize, Deserialize, juniper::GraphQLInputObject)]
@@ -160,4 +379,41 @@ pub struct UpdateSample {
     /// Optional updated expiration date for the content, sent as unix time stamp
     #[serde(skip_serializing_if = "Option::is_none")]
     pub expiration_date: Option<i32>,
+
+    /// Optional updated published flag, unpublished samples are hidden from most callers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published: Option<bool>,
+}
+
+/// An update to a sample along with the identity of its author
+#[derive(Serialize)]
+pub struct SampleUpdate {
+    #[serde(flatten)]
+    pub update: UpdateSample,
+    pub updated_by: Identity,
+    pub impersonated_by: Option<Identity>,
+}
+
+/// A replacement ACL for a sample
+#[derive(Serialize)]
+pub struct SampleAclUpdate {
+    pub acl: Vec<AclEntry>,
+    pub updated_by: Identity,
+    pub impersonated_by: Option<Identity>,
+}
+
+/// The availability windows of a sample
+#[derive(Serialize)]
+pub struct SampleWindowsUpdate {
+    pub windows: Vec<AvailabilityWindow>,
+    pub updated_by: Identity,
+    pub impersonated_by: Option<Identity>,
+}
+
+/// A new owner for a sample
+#[derive(Serialize)]
+pub struct SampleOwnerUpdate {
+    pub owner_id: String,
+    pub updated_by: Identity,
+    pub impersonated_by: Option<Identity>,
 }
@@ -0,0 +1,333 @@
+use bson::{doc, Bson, Document};
+use juniper::FieldError;
+use serde::{Deserialize, Serialize};
+
+use crate::models::filter::escape_regex;
+use crate::models::{PageInfo, Sample};
+use crate::schema::Context;
+
+/// Fields covered by the text index, the only ones `searchSamples` accepts
+pub const SEARCHABLE_FIELDS: [&str; 2] = ["name", "description"];
+
+/// Characters of context kept on each side of a highlighted match
+const SNIPPET_CONTEXT: usize = 40;
+
+/// Checks the requested fields against `SEARCHABLE_FIELDS`, every field when none are given
+pub fn search_fields(fields: Option<Vec<String>>) -> Result<Vec<String>, FieldError> {
+    let fields = match fields {
+        Some(fields) if !fields.is_empty() => fields,
+        _ => return Ok(SEARCHABLE_FIELDS.iter().map(|f| f.to_string()).collect()),
+    };
+    for field in &fields {
+        if !SEARCHABLE_FIELDS.contains(&field.as_str()) {
+            return Err(format!(
+                "{} can't be searched, use one of {}",
+                field,
+                SEARCHABLE_FIELDS.join(", ")
+            )
+            .into());
+        }
+    }
+    Ok(fields)
+}
+
+/// The terms a search has to match: quoted phrases and words, leaving out `-negated` ones
+pub fn search_terms(search_term: &str) -> Vec<String> {
+    let mut terms = vec![];
+    for (i, part) in search_term.split('"').enumerate() {
+        if i % 2 == 1 {
+            let phrase = part.trim();
+            if !phrase.is_empty() {
+                terms.push(phrase.to_lowercase());
+            }
+            continue;
+        }
+        terms.extend(
+            part.split_whitespace()
+                .filter(|word| !word.starts_with('-'))
+                .map(|word| word.to_lowercase()),
+        );
+    }
+    terms
+}
+
+/// Builds the `$text` query of a search. Phrases and negations use the syntax of the text
+/// index, and the fields restrict matches when they don't cover the whole index.
+pub fn text_filter(
+    search_term: &str,
+    fields: &[String],
+    language: Option<String>,
+) -> Result<Document, FieldError> {
+    let mut text = doc! { "$search": search_term };
+    if let Some(language) = language {
+        if language.is_empty() || !language.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(format!("Invalid language {}", language).into());
+        }
+        text.insert("$language", language);
+    }
+    let filter = doc! { "$text": text };
+    if fields.len() >= SEARCHABLE_FIELDS.len() {
+        return Ok(filter);
+    }
+    let clauses: Vec<Bson> = fields
+        .iter()
+        .flat_map(|field| {
+            search_terms(search_term).into_iter().map(move |term| {
+                Bson::Document(doc! { field: { "$regex": escape_regex(&term), "$options": "i" } })
+            })
+        })
+        .collect();
+    if clauses.is_empty() {
+        return Ok(filter);
+    }
+    Ok(doc! { "$and": [filter, { "$or": clauses }] })
+}
+
+/// A word matches a term when one is a prefix of the other, which approximates the stemming
+/// of the text index well enough to highlight `samples` for `sample`
+fn word_matches(word: &str, term: &str) -> bool {
+    let shorter = word.len().min(term.len());
+    if shorter < 3 {
+        return word == term;
+    }
+    word.starts_with(term) || term.starts_with(word)
+}
+
+/// The byte ranges of `text` matching any of the terms
+fn matches(text: &str, terms: &[String]) -> Vec<(usize, usize)> {
+    let lower = text.to_lowercase();
+    if lower.len() != text.len() {
+        return vec![];
+    }
+    let mut ranges = vec![];
+    for term in terms.iter().filter(|term| term.contains(' ')) {
+        ranges.extend(
+            lower
+                .match_indices(term.as_str())
+                .map(|(i, m)| (i, i + m.len())),
+        );
+    }
+    let mut start = None;
+    for (i, c) in lower
+        .char_indices()
+        .chain(std::iter::once((lower.len(), ' ')))
+    {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(from)) => {
+                let word = &lower[from..i];
+                if terms.iter().any(|term| word_matches(word, term)) {
+                    ranges.push((from, i));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    ranges.sort();
+    ranges.dedup();
+    ranges
+}
+
+/// Cuts a snippet around the first match and wraps every match in it with `<em>`
+pub fn highlight(text: &str, terms: &[String]) -> Option<String> {
+    let ranges = matches(text, terms);
+    let (first, _) = *ranges.first()?;
+    let mut from = first.saturating_sub(SNIPPET_CONTEXT);
+    while !text.is_char_boundary(from) {
+        from -= 1;
+    }
+    let mut to = (first + SNIPPET_CONTEXT * 2).min(text.len());
+    while !text.is_char_boundary(to) {
+        to += 1;
+    }
+    let mut snippet = String::new();
+    if from > 0 {
+        snippet.push('…');
+    }
+    let mut position = from;
+    for (start, end) in ranges {
+        if start < position || end > to {
+            continue;
+        }
+        snippet.push_str(&text[position..start]);
+        snippet.push_str("<em>");
+        snippet.push_str(&text[start..end]);
+        snippet.push_str("</em>");
+        position = end;
+    }
+    snippet.push_str(&text[position..to]);
+    if to < text.len() {
+        snippet.push('…');
+    }
+    Some(snippet)
+}
+
+/// A snippet of a searched field with the matching terms wrapped in `<em>`
+#[derive(juniper::GraphQLObject, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SearchHighlight {
+    pub field: String,
+    pub snippet: String,
+}
+
+/// A sample matching a search along with its relevance, served as a `SampleSearchEdge`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SampleSearchHit {
+    pub sample: Sample,
+    pub score: f64,
+    pub highlights: Vec<SearchHighlight>,
+}
+
+impl SampleSearchHit {
+    pub fn new(sample: Sample, score: f64, fields: &[String], terms: &[String]) -> Self {
+        let highlights = fields
+            .iter()
+            .filter_map(|field| {
+                let snippet = highlight(sample.text(field)?, terms)?;
+                Some(SearchHighlight {
+                    field: field.clone(),
+                    snippet,
+                })
+            })
+            .collect();
+        SampleSearchHit {
+            sample,
+            score,
+            highlights,
+        }
+    }
+}
+
+/// An edge of search results, the sample is the node like in other sample connections and its
+/// relevance sits next to it
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SampleSearchEdge {
+    pub cursor: String,
+    pub node: Sample,
+    pub score: f64,
+    pub highlights: Vec<SearchHighlight>,
+}
+
+#[juniper::object(Context = Context)]
+impl SampleSearchEdge {
+    fn cursor(&self) -> &str {
+        &self.cursor
+    }
+
+    fn node(&self) -> &Sample {
+        &self.node
+    }
+
+    /// The relevance computed by the text index, higher is better
+    fn score(&self) -> f64 {
+        self.score
+    }
+
+    fn highlights(&self) -> &Vec<SearchHighlight> {
+        &self.highlights
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SampleSearchConnection {
+    pub page_info: PageInfo,
+    pub edges: Vec<SampleSearchEdge>,
+    pub total_count: i64,
+}
+
+#[juniper::object(Context = Context)]
+impl SampleSearchConnection {
+    fn page_info(&self) -> &PageInfo {
+        &self.page_info
+    }
+
+    fn edges(&self) -> &Vec<SampleSearchEdge> {
+        &self.edges
+    }
+
+    #[graphql(deprecated = "Use edges { node } instead")]
+    fn items(&self) -> Vec<&Sample> {
+        self.edges.iter().map(|edge| &edge.node).collect()
+    }
+
+    fn total_count(&self) -> i32 {
+        self.total_count as i32
+    }
+}
+
+/// Relevance can't be seeked on, so search results are paged by offset
+pub fn offset_cursor(offset: i64) -> String {
+    format!("offset:{}", offset)
+}
+
+fn decode_offset(cursor: &str) -> Result<i64, FieldError> {
+    cursor
+        .strip_prefix("offset:")
+        .and_then(|offset| offset.parse().ok())
+        .filter(|offset: &i64| *offset >= 0)
+        .ok_or_else(|| format!("Invalid cursor {}", cursor).into())
+}
+
+/// Resolves the offset of the first result and the number of results from the relay cursors.
+/// `last` counts back from `before`, and results from `before` on are left out of the page.
+pub fn page_offset(
+    limit: Option<i32>,
+    after: &Option<String>,
+    before: &Option<String>,
+    skip: Option<i32>,
+) -> Result<(i64, Option<i32>), FieldError> {
+    let skip = skip.unwrap_or(0).max(0) as i64;
+    let before = before.as_deref().map(decode_offset).transpose()?;
+    let offset = match (after, before) {
+        (Some(after), _) => decode_offset(after)? + 1 + skip,
+        (None, Some(before)) => (before - limit.map_or(before, |limit| limit as i64)).max(0),
+        (None, None) => skip,
+    };
+    let limit = match before {
+        Some(before) => {
+            let room = (before - offset).max(0);
+            Some(limit.map_or(room, |limit| (limit as i64).min(room)) as i32)
+        }
+        None => limit,
+    };
+    Ok((offset, limit))
+}
+
+impl SampleSearchConnection {
+    /// Builds a page from the hits starting at `offset`, one more hit than the page holds is
+    /// fetched to tell whether there is a next page
+    pub fn page(
+        mut hits: Vec<SampleSearchHit>,
+        offset: i64,
+        limit: Option<i32>,
+        total_count: i64,
+    ) -> SampleSearchConnection {
+        let has_next_page = match limit {
+            Some(limit) if hits.len() > limit as usize => {
+                hits.truncate(limit as usize);
+                true
+            }
+            _ => false,
+        };
+        let edges: Vec<SampleSearchEdge> = hits
+            .into_iter()
+            .enumerate()
+            .map(|(i, hit)| SampleSearchEdge {
+                cursor: offset_cursor(offset + i as i64),
+                node: hit.sample,
+                score: hit.score,
+                highlights: hit.highlights,
+            })
+            .collect();
+        SampleSearchConnection {
+            page_info: PageInfo {
+                has_next_page,
+                has_previous_page: offset > 0,
+                start_cursor: edges.first().map(|edge| edge.cursor.clone()),
+                end_cursor: edges.last().map(|edge| edge.cursor.clone()),
+            },
+            edges,
+            total_count,
+        }
+    }
+}
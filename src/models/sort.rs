@@ -0,0 +1,67 @@
+use bson::{doc, Document};
+use juniper::FieldError;
+
+#[derive(juniper::GraphQLEnum, Clone, Copy, Debug, PartialEq)]
+pub enum SampleSortField {
+    Name,
+    DateCreated,
+    DateModified,
+    AvailableDate,
+    ExpirationDate,
+    MinValue,
+    MaxValue,
+    AvgValue,
+    ValueCount,
+}
+
+impl SampleSortField {
+    /// The stored field sorted on, the value aggregates are kept denormalized on each sample
+    pub fn path(self) -> &'static str {
+        match self {
+            SampleSortField::Name => "name",
+            SampleSortField::DateCreated => "node.date_created",
+            SampleSortField::DateModified => "node.date_modified",
+            SampleSortField::AvailableDate => "available_date",
+            SampleSortField::ExpirationDate => "expiration_date",
+            SampleSortField::MinValue => "min_value",
+            SampleSortField::MaxValue => "max_value",
+            SampleSortField::AvgValue => "avg_value",
+            SampleSortField::ValueCount => "value_count",
+        }
+    }
+}
+
+#[derive(juniper::GraphQLEnum, Clone, Copy, Debug, PartialEq)]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+#[derive(juniper::GraphQLInputObject, Clone, Debug)]
+pub struct SampleSort {
+    pub field: SampleSortField,
+    /// Ascending when omitted
+    pub direction: Option<SortDirection>,
+}
+
+/// Builds the sort of a connection, newest first when none is given. `_id` always breaks ties
+/// so that cursors stay stable when the sort keys aren't unique.
+pub fn sort_document(sort: &[SampleSort]) -> Result<Document, FieldError> {
+    if sort.is_empty() {
+        return Ok(doc! { "node.date_created": -1, "_id": 1 });
+    }
+    let mut document = Document::new();
+    for key in sort {
+        let path = key.field.path();
+        if document.contains_key(path) {
+            return Err(format!("Samples can only be sorted by {:?} once", key.field).into());
+        }
+        let direction = match key.direction {
+            Some(SortDirection::Desc) => -1,
+            _ => 1,
+        };
+        document.insert(path, direction);
+    }
+    document.insert("_id", 1);
+    Ok(document)
+}
@@ -0,0 +1,314 @@
+use bson::{doc, Bson, Document};
+use juniper::FieldError;
+use serde::{Deserialize, Serialize};
+
+use crate::models::{Embedded, EmbeddedType};
+
+/// Percentiles returned by `valueStatistics` when none are requested
+pub const DEFAULT_PERCENTILES: [f64; 3] = [50., 90., 99.];
+
+/// Histogram buckets returned by `valueStatistics` when no count is requested
+pub const DEFAULT_BUCKETS: i32 = 10;
+
+pub const MAX_BUCKETS: i32 = 100;
+
+/// Aggregates over the values of a sample, stored on it so they can be sorted on. Entries
+/// without a value are counted but left out of the others, like `$min` and `$avg` do.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ValueAggregates {
+    pub value_count: i32,
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+    pub avg_value: Option<f64>,
+}
+
+impl ValueAggregates {
+    pub fn of<'a, I>(values: I) -> ValueAggregates
+    where
+        I: IntoIterator<Item = &'a Embedded>,
+    {
+        let mut aggregates = ValueAggregates {
+            value_count: 0,
+            min_value: None,
+            max_value: None,
+            avg_value: None,
+        };
+        let (mut sum, mut count) = (0., 0);
+        for embedded in values {
+            aggregates.value_count += 1;
+            if let Some(value) = embedded.value {
+                aggregates.min_value =
+                    Some(aggregates.min_value.map_or(value, |min| min.min(value)));
+                aggregates.max_value =
+                    Some(aggregates.max_value.map_or(value, |max| max.max(value)));
+                sum += value;
+                count += 1;
+            }
+        }
+        if count > 0 {
+            aggregates.avg_value = Some(sum / count as f64);
+        }
+        aggregates
+    }
+
+    /// The `$set` storing the aggregates on a sample
+    pub fn to_document(&self) -> Result<Document, FieldError> {
+        match bson::to_bson(self)? {
+            Bson::Document(document) => Ok(document),
+            _ => Err("Unable to store value aggregates".into()),
+        }
+    }
+}
+
+/// The aggregates over the values of a sample with one embedded type
+#[derive(juniper::GraphQLObject, Clone, Debug, PartialEq)]
+pub struct ValueBreakdown {
+    pub embedded_type: EmbeddedType,
+    pub value_count: i32,
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+    pub avg_value: Option<f64>,
+}
+
+impl ValueBreakdown {
+    /// One breakdown per embedded type present in the values, in the order types are declared
+    pub fn of(values: &[Embedded]) -> Vec<ValueBreakdown> {
+        EmbeddedType::ALL
+            .iter()
+            .filter_map(|embedded_type| {
+                let aggregates = ValueAggregates::of(
+                    values
+                        .iter()
+                        .filter(|value| value.embedded_type == *embedded_type),
+                );
+                if aggregates.value_count == 0 {
+                    return None;
+                }
+                Some(ValueBreakdown {
+                    embedded_type: *embedded_type,
+                    value_count: aggregates.value_count,
+                    min_value: aggregates.min_value,
+                    max_value: aggregates.max_value,
+                    avg_value: aggregates.avg_value,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The value below which a given percentage of the values fall
+#[derive(juniper::GraphQLObject, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ValuePercentile {
+    pub percentile: f64,
+    pub value: f64,
+}
+
+/// The number of values within `[lower, upper)`, the last bucket includes its upper bound
+#[derive(juniper::GraphQLObject, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HistogramBucket {
+    pub lower: f64,
+    pub upper: f64,
+    pub count: i32,
+}
+
+/// Statistics over the values of every matching sample
+#[derive(juniper::GraphQLObject, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ValueStatistics {
+    pub count: i32,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub avg: Option<f64>,
+    pub percentiles: Vec<ValuePercentile>,
+    pub histogram: Vec<HistogramBucket>,
+}
+
+/// Checks the requested percentiles, `DEFAULT_PERCENTILES` when none are given
+pub fn statistics_percentiles(percentiles: Option<Vec<f64>>) -> Result<Vec<f64>, FieldError> {
+    let percentiles = percentiles.unwrap_or_else(|| DEFAULT_PERCENTILES.to_vec());
+    if let Some(invalid) = percentiles.iter().find(|p| !(0. ..=100.).contains(*p)) {
+        return Err(format!("Percentile {} isn't between 0 and 100", invalid).into());
+    }
+    Ok(percentiles)
+}
+
+/// Checks the requested number of histogram buckets, `DEFAULT_BUCKETS` when none is given
+pub fn statistics_buckets(buckets: Option<i32>) -> Result<usize, FieldError> {
+    match buckets.unwrap_or(DEFAULT_BUCKETS) {
+        buckets if (1..=MAX_BUCKETS).contains(&buckets) => Ok(buckets as usize),
+        buckets => Err(format!(
+            "`buckets` must be between 1 and {}, not {}",
+            MAX_BUCKETS, buckets
+        )
+        .into()),
+    }
+}
+
+/// Stages turning the samples matching `filter` into a document per value, leaving out entries
+/// without a value
+fn value_stages(
+    filter: Document,
+    embedded_type: Option<EmbeddedType>,
+) -> Result<Vec<Document>, FieldError> {
+    let mut value = doc! { "value": { "$type": "number" } };
+    if let Some(embedded_type) = embedded_type {
+        value.insert("embedded_type", bson::to_bson(&embedded_type)?);
+    }
+    let mut unwound = Document::new();
+    for (key, condition) in value.iter() {
+        unwound.insert(format!("values.{}", key), condition.clone());
+    }
+    // only samples holding a matching value are unwound
+    let samples = doc! { "$and": [filter, { "values": { "$elemMatch": value } }] };
+    Ok(vec![
+        doc! { "$match": samples },
+        doc! { "$unwind": "$values" },
+        doc! { "$match": unwound },
+    ])
+}
+
+/// Computes the count, bounds and average of the values in one pass
+pub fn summary_pipeline(
+    filter: Document,
+    embedded_type: Option<EmbeddedType>,
+) -> Result<Vec<Document>, FieldError> {
+    let mut pipeline = value_stages(filter, embedded_type)?;
+    pipeline.push(doc! { "$group": {
+        "_id": Bson::Null,
+        "count": { "$sum": 1 },
+        "min": { "$min": "$values.value" },
+        "max": { "$max": "$values.value" },
+        "avg": { "$avg": "$values.value" },
+    } });
+    Ok(pipeline)
+}
+
+/// Picks the nearest rank percentiles out of `count` values, each by sorting the values and
+/// skipping to its rank, so only one value per percentile ends up in the result
+pub fn percentile_pipeline(
+    filter: Document,
+    embedded_type: Option<EmbeddedType>,
+    percentiles: &[f64],
+    count: i64,
+) -> Result<Vec<Document>, FieldError> {
+    let mut pipeline = value_stages(filter, embedded_type)?;
+    pipeline.push(doc! { "$project": { "_id": 0, "value": "$values.value" } });
+    let mut facets = Document::new();
+    for (i, percentile) in percentiles.iter().enumerate() {
+        let rank = (percentile / 100. * count as f64).ceil() as i64;
+        let stages = vec![
+            Bson::Document(doc! { "$sort": { "value": 1 } }),
+            Bson::Document(doc! { "$skip": (rank - 1).max(0) }),
+            Bson::Document(doc! { "$limit": 1 }),
+        ];
+        facets.insert(format!("p{}", i), stages);
+    }
+    pipeline.push(doc! { "$facet": facets });
+    Ok(pipeline)
+}
+
+/// Splits `[min, max]` into buckets of equal width
+pub fn histogram_boundaries(min: f64, max: f64, buckets: usize) -> Vec<f64> {
+    let mut boundaries: Vec<f64> = (0..buckets)
+        .map(|i| min + (max - min) * i as f64 / buckets as f64)
+        .chain(std::iter::once(max))
+        .collect();
+    boundaries.dedup();
+    boundaries
+}
+
+/// Counts the values per bucket, values equal to the upper bound land in the `max` bucket
+pub fn histogram_pipeline(
+    filter: Document,
+    embedded_type: Option<EmbeddedType>,
+    boundaries: &[f64],
+) -> Result<Vec<Document>, FieldError> {
+    let mut pipeline = value_stages(filter, embedded_type)?;
+    pipeline.push(doc! { "$bucket": {
+        "groupBy": "$values.value",
+        "boundaries": boundaries.to_vec(),
+        "default": "max",
+        "output": { "count": { "$sum": 1 } },
+    } });
+    Ok(pipeline)
+}
+
+fn number(document: &Document, key: &str) -> Option<f64> {
+    match document.get(key)? {
+        Bson::FloatingPoint(value) => Some(*value),
+        Bson::I32(value) => Some(*value as f64),
+        Bson::I64(value) => Some(*value as f64),
+        _ => None,
+    }
+}
+
+impl ValueStatistics {
+    /// Reads the result of `summary_pipeline`, which has no document when nothing matched
+    pub fn from_summary(summary: Option<Document>) -> ValueStatistics {
+        let summary = summary.unwrap_or_default();
+        ValueStatistics {
+            count: number(&summary, "count").unwrap_or(0.) as i32,
+            min: number(&summary, "min"),
+            max: number(&summary, "max"),
+            avg: number(&summary, "avg"),
+            percentiles: vec![],
+            histogram: vec![],
+        }
+    }
+
+    /// Fills in the percentiles from the result of `percentile_pipeline`
+    pub fn with_percentiles(
+        mut self,
+        percentiles: &[f64],
+        ranks: Option<Document>,
+    ) -> ValueStatistics {
+        let ranks = ranks.unwrap_or_default();
+        self.percentiles = percentiles
+            .iter()
+            .enumerate()
+            .filter_map(|(i, percentile)| {
+                let rank = ranks.get_array(&format!("p{}", i)).ok()?.first()?;
+                Some(ValuePercentile {
+                    percentile: *percentile,
+                    value: number(rank.as_document()?, "value")?,
+                })
+            })
+            .collect();
+        self
+    }
+
+    /// Fills in the histogram from the results of `histogram_pipeline`, buckets without values
+    /// are missing from them
+    pub fn with_histogram(mut self, boundaries: &[f64], counts: &[Document]) -> ValueStatistics {
+        let count_of = |id: &Bson| {
+            counts
+                .iter()
+                .find(|bucket| bucket.get("_id") == Some(id))
+                .and_then(|bucket| number(bucket, "count"))
+                .unwrap_or(0.) as i32
+        };
+        self.histogram = boundaries
+            .windows(2)
+            .map(|bounds| HistogramBucket {
+                lower: bounds[0],
+                upper: bounds[1],
+                count: count_of(&Bson::FloatingPoint(bounds[0])),
+            })
+            .collect();
+        if let Some(last) = self.histogram.last_mut() {
+            last.count += count_of(&Bson::String("max".to_string()));
+        }
+        self
+    }
+
+    /// A histogram of values that are all the same
+    pub fn with_single_bucket(mut self) -> ValueStatistics {
+        if let (Some(min), Some(max)) = (self.min, self.max) {
+            self.histogram = vec![HistogramBucket {
+                lower: min,
+                upper: max,
+                count: self.count,
+            }];
+        }
+        self
+    }
+}
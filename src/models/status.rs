@@ -0,0 +1,123 @@
+use bson::{doc, Bson, Document};
+use serde::{Deserialize, Serialize};
+
+use crate::models::Occurrence;
+
+#[derive(juniper::GraphQLEnum, Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub enum Status {
+    All,
+    Available,
+    Active,
+    Expired,
+    Pending,
+}
+
+impl Status {
+    /// Every status a sample can be counted under
+    pub const ALL: [Status; 5] = [
+        Status::All,
+        Status::Available,
+        Status::Active,
+        Status::Expired,
+        Status::Pending,
+    ];
+
+    /// Matches the samples with the status at `timestamp`. Samples without the dates compared
+    /// on never match, e.g. a sample without an expiration date is neither active nor expired.
+    /// Active samples are within one of their occurrences, which only samples not yet migrated
+    /// lack.
+    pub fn filter(self, timestamp: i64) -> Option<Document> {
+        match self {
+            Status::Active => Some(doc! {
+                "$or": [
+                    {
+                        "occurrences": { "$elemMatch": {
+                            "start": { "$lt": timestamp },
+                            "end": { "$gt": timestamp },
+                        } },
+                    },
+                    {
+                        "occurrences": { "$exists": false },
+                        "available_date": { "$lt": timestamp },
+                        "expiration_date": { "$gt": timestamp },
+                    },
+                ]
+            }),
+            Status::Expired => Some(doc! {
+                "expiration_date": { "$lt": timestamp },
+            }),
+            Status::Pending => Some(doc! {
+                "available_date": { "$gt": timestamp },
+            }),
+            Status::Available => Some(doc! {
+                "available_date": { "$lt": timestamp }
+            }),
+            Status::All => None,
+        }
+    }
+
+    /// The most specific status of a sample at `timestamp`, following the semantics of
+    /// `filter`. Expiry wins over everything else, `None` when the dates don't match any status.
+    pub fn of(
+        available_date: Option<i64>,
+        expiration_date: Option<i64>,
+        occurrences: Option<&[Occurrence]>,
+        timestamp: i64,
+    ) -> Option<Status> {
+        if expiration_date.map_or(false, |expiration| expiration < timestamp) {
+            return Some(Status::Expired);
+        }
+        let active = match occurrences {
+            Some(occurrences) => occurrences
+                .iter()
+                .any(|occurrence| occurrence.is_active(timestamp)),
+            None => expiration_date.map_or(false, |expiration| expiration > timestamp),
+        };
+        match available_date {
+            Some(available) if available > timestamp => Some(Status::Pending),
+            Some(available) if available < timestamp && active => Some(Status::Active),
+            Some(available) if available < timestamp => Some(Status::Available),
+            _ => None,
+        }
+    }
+}
+
+/// Finds the earliest date of the samples matching `filter` that is not before `timestamp`,
+/// out of their occurrences and their available and expiration dates
+pub fn next_transition_pipeline(filter: Document, timestamp: i64) -> Vec<Document> {
+    vec![
+        doc! { "$match": filter },
+        doc! { "$project": { "dates": { "$concatArrays": [
+            { "$ifNull": ["$occurrences.start", []] },
+            { "$ifNull": ["$occurrences.end", []] },
+            ["$available_date", "$expiration_date"],
+        ] } } },
+        doc! { "$unwind": "$dates" },
+        doc! { "$match": { "dates": { "$gte": timestamp } } },
+        doc! { "$group": { "_id": Bson::Null, "next": { "$min": "$dates" } } },
+    ]
+}
+
+/// The first time after `timestamp` at which a status may change, from the result of
+/// `next_transition_pipeline`. Statuses compare dates strictly, so a sample is in between
+/// statuses at one of its dates and takes its next status a second later.
+pub fn next_transition(result: Option<Document>, timestamp: i64) -> Option<i64> {
+    let next = match result?.get("next")? {
+        Bson::I64(next) => *next,
+        Bson::I32(next) => *next as i64,
+        Bson::FloatingPoint(next) => next.ceil() as i64,
+        _ => return None,
+    };
+    if next > timestamp {
+        Some(next)
+    } else {
+        Some(timestamp + 1)
+    }
+}
+
+/// The number of samples with a status
+#[derive(juniper::GraphQLObject, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StatusCount {
+    pub status: Status,
+    pub count: i32,
+}
@@ -0,0 +1,326 @@
+use bson::{doc, Bson, Document};
+use chrono::{DateTime, TimeZone, Utc};
+use juniper::FieldError;
+use mongodb_base_service::ID;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::db::{id_from_string, id_string};
+use crate::models::{PageInfo, Sample};
+use crate::schema::Context;
+
+/// A change of availability, events at the same time are ordered as declared
+#[derive(
+    juniper::GraphQLEnum, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub enum TransitionKind {
+    BecomesAvailable,
+    Expires,
+}
+
+/// A sample becoming available or expiring at a point in time
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TimelineEvent {
+    pub at: i64,
+    pub kind: TransitionKind,
+    pub sample: Sample,
+}
+
+/// Events are ordered by time, kind and sample id, which also makes up their cursors
+type EventKey = (i64, TransitionKind, String);
+
+impl TimelineEvent {
+    fn key(&self) -> EventKey {
+        (self.at, self.kind, id_string(&self.sample.id))
+    }
+
+    pub fn cursor(&self) -> String {
+        let (at, kind, id) = self.key();
+        format!("{}:{}:{}", at, kind as u8, id)
+    }
+}
+
+fn decode_cursor(cursor: &str) -> Result<EventKey, FieldError> {
+    let invalid = || FieldError::from(format!("Invalid cursor {}", cursor));
+    let mut parts = cursor.splitn(3, ':');
+    let at = parts
+        .next()
+        .and_then(|at| at.parse().ok())
+        .ok_or_else(invalid)?;
+    let kind = match parts.next() {
+        Some("0") => TransitionKind::BecomesAvailable,
+        Some("1") => TransitionKind::Expires,
+        _ => return Err(invalid()),
+    };
+    let id = parts.next().ok_or_else(invalid)?.to_string();
+    Ok((at, kind, id))
+}
+
+/// Matches the events coming after `key`, or before it when `backwards`
+fn key_filter(key: &EventKey, backwards: bool) -> Document {
+    let (at, kind, id) = (key.0, key.1 as i32, id_from_string(&key.2));
+    let op = if backwards { "$lt" } else { "$gt" };
+    doc! { "$or": [
+        { "at": { op: at } },
+        { "at": at, "kind": { op: kind } },
+        { "at": at, "kind": kind, "sample_id": { op: id } },
+    ] }
+}
+
+/// Unwinds the transitions within `[from, to)` of the samples matching `filter` and pages
+/// their keys within a `$facet` along with their count. One more key than `limit` is taken
+/// in the direction of the page, counting back from `before` when only it is given, which
+/// tells whether there are more.
+pub fn timeline_pipeline(
+    filter: Document,
+    from: i64,
+    to: i64,
+    after: &Option<String>,
+    before: &Option<String>,
+    limit: i32,
+) -> Result<Vec<Document>, FieldError> {
+    let after = after.as_deref().map(decode_cursor).transpose()?;
+    let before = before.as_deref().map(decode_cursor).transpose()?;
+    let backwards = before.is_some() && after.is_none();
+    let mut page = vec![];
+    if let Some(after) = &after {
+        page.push(doc! { "$match": key_filter(after, false) });
+    }
+    if let Some(before) = &before {
+        page.push(doc! { "$match": key_filter(before, true) });
+    }
+    let direction = if backwards { -1 } else { 1 };
+    page.push(doc! { "$sort": { "at": direction, "kind": direction, "sample_id": direction } });
+    page.push(doc! { "$limit": limit as i64 + 1 });
+    let periods = doc! { "$cond": [
+        { "$isArray": "$occurrences" },
+        { "$map": {
+            "input": "$occurrences",
+            "as": "occurrence",
+            "in": { "start": "$$occurrence.start", "end": "$$occurrence.end" },
+        } },
+        [{ "start": "$available_date", "end": "$expiration_date" }],
+    ] };
+    Ok(vec![
+        doc! { "$match": filter },
+        doc! { "$project": { "periods": periods } },
+        doc! { "$unwind": "$periods" },
+        doc! { "$project": { "events": [
+            { "at": "$periods.start", "kind": TransitionKind::BecomesAvailable as i32 },
+            { "at": "$periods.end", "kind": TransitionKind::Expires as i32 },
+        ] } },
+        doc! { "$unwind": "$events" },
+        doc! { "$match": { "events.at": { "$gte": from, "$lt": to } } },
+        // overlapping windows may share a transition
+        doc! { "$group": { "_id": { "at": "$events.at", "kind": "$events.kind", "sample_id": "$_id" } } },
+        doc! { "$project": {
+            "_id": 0,
+            "at": "$_id.at",
+            "kind": "$_id.kind",
+            "sample_id": "$_id.sample_id",
+        } },
+        doc! { "$facet": {
+            "items": page,
+            "total": [{ "$count": "count" }],
+        } },
+    ])
+}
+
+/// The keys of a page of events read from `timeline_pipeline`
+pub struct TimelineKeys {
+    keys: HashSet<EventKey>,
+    pub sample_ids: Vec<Bson>,
+    pub total_count: i64,
+}
+
+impl TimelineKeys {
+    pub fn from_facet(facet: Option<Document>) -> Result<TimelineKeys, FieldError> {
+        let facet = facet.unwrap_or_default();
+        let number = |value: Option<&Bson>| match value {
+            Some(Bson::I32(value)) => Some(*value as i64),
+            Some(Bson::I64(value)) => Some(*value),
+            Some(Bson::FloatingPoint(value)) => Some(*value as i64),
+            _ => None,
+        };
+        let mut keys = HashSet::new();
+        let mut sample_ids = vec![];
+        for item in facet.get_array("items").map_or(&[][..], |items| &items[..]) {
+            let item = match item.as_document() {
+                Some(item) => item,
+                None => continue,
+            };
+            let kind = match number(item.get("kind")) {
+                Some(0) => TransitionKind::BecomesAvailable,
+                Some(1) => TransitionKind::Expires,
+                _ => continue,
+            };
+            let (at, sample_id) = match (number(item.get("at")), item.get("sample_id")) {
+                (Some(at), Some(sample_id)) => (at, sample_id.clone()),
+                _ => continue,
+            };
+            let id: ID = bson::from_bson(sample_id.clone())?;
+            keys.insert((at, kind, id_string(&id)));
+            sample_ids.push(sample_id);
+        }
+        let total_count = facet
+            .get_array("total")
+            .ok()
+            .and_then(|total| total.first())
+            .and_then(|total| total.as_document())
+            .and_then(|total| number(total.get("count")))
+            .unwrap_or(0);
+        Ok(TimelineKeys {
+            keys,
+            sample_ids,
+            total_count,
+        })
+    }
+
+    /// Keeps the events of the page out of those of its samples
+    pub fn select(&self, events: Vec<TimelineEvent>) -> Vec<TimelineEvent> {
+        events
+            .into_iter()
+            .filter(|event| self.keys.contains(&event.key()))
+            .collect()
+    }
+}
+
+/// The transitions of the samples within `[from, to)`, in order. Samples with occurrences
+/// become available and expire with each of them.
+pub fn timeline_events(samples: Vec<Sample>, from: i64, to: i64) -> Vec<TimelineEvent> {
+    let within = |at: &i64| *at >= from && *at < to;
+    let mut events: Vec<TimelineEvent> = samples
+        .into_iter()
+        .flat_map(|sample| {
+            let periods: Vec<(Option<i64>, Option<i64>)> = match &sample.occurrences {
+                Some(occurrences) => occurrences
+                    .iter()
+                    .map(|occurrence| (Some(occurrence.start), occurrence.end))
+                    .collect(),
+                None => vec![(sample.available_date, sample.expiration_date)],
+            };
+            periods
+                .into_iter()
+                .flat_map(|(available, expires)| {
+                    let available = available
+                        .filter(within)
+                        .map(|at| (at, TransitionKind::BecomesAvailable));
+                    let expires = expires
+                        .filter(within)
+                        .map(|at| (at, TransitionKind::Expires));
+                    available.into_iter().chain(expires)
+                })
+                .map(|(at, kind)| TimelineEvent {
+                    at,
+                    kind,
+                    sample: sample.clone(),
+                })
+                .collect::<Vec<TimelineEvent>>()
+        })
+        .collect();
+    events.sort_by_key(|event| event.key());
+    // overlapping windows may share a transition
+    events.dedup_by_key(|event| event.key());
+    events
+}
+
+#[juniper::object(Context = Context)]
+impl TimelineEvent {
+    fn at(&self) -> DateTime<Utc> {
+        Utc.timestamp(self.at, 0)
+    }
+
+    fn kind(&self) -> TransitionKind {
+        self.kind
+    }
+
+    fn sample(&self) -> &Sample {
+        &self.sample
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TimelineEdge {
+    pub cursor: String,
+    pub node: TimelineEvent,
+}
+
+#[juniper::object(Context = Context)]
+impl TimelineEdge {
+    fn cursor(&self) -> &str {
+        &self.cursor
+    }
+
+    fn node(&self) -> &TimelineEvent {
+        &self.node
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TimelineConnection {
+    pub page_info: PageInfo,
+    pub edges: Vec<TimelineEdge>,
+    pub total_count: i64,
+}
+
+#[juniper::object(Context = Context)]
+impl TimelineConnection {
+    fn page_info(&self) -> &PageInfo {
+        &self.page_info
+    }
+
+    fn edges(&self) -> &Vec<TimelineEdge> {
+        &self.edges
+    }
+
+    fn total_count(&self) -> i32 {
+        self.total_count as i32
+    }
+}
+
+impl TimelineConnection {
+    /// Pages through ordered events out of `total_count`, `last` counts back from `before`
+    pub fn page(
+        events: Vec<TimelineEvent>,
+        total_count: i64,
+        limit: Option<i32>,
+        after: Option<String>,
+        before: Option<String>,
+    ) -> Result<TimelineConnection, FieldError> {
+        let after = after.as_deref().map(decode_cursor).transpose()?;
+        let before = before.as_deref().map(decode_cursor).transpose()?;
+        let mut window: Vec<TimelineEvent> = events
+            .into_iter()
+            .filter(|event| after.as_ref().map_or(true, |after| event.key() > *after))
+            .filter(|event| before.as_ref().map_or(true, |before| event.key() < *before))
+            .collect();
+        let remaining = window.len();
+        let limit = limit
+            .map_or(remaining, |limit| limit as usize)
+            .min(remaining);
+        let (has_previous_page, has_next_page) = if before.is_some() && after.is_none() {
+            window.drain(..remaining - limit);
+            (remaining > limit, true)
+        } else {
+            window.truncate(limit);
+            (after.is_some(), remaining > limit)
+        };
+        let edges: Vec<TimelineEdge> = window
+            .into_iter()
+            .map(|node| TimelineEdge {
+                cursor: node.cursor(),
+                node,
+            })
+            .collect();
+        Ok(TimelineConnection {
+            page_info: PageInfo {
+                has_next_page,
+                has_previous_page,
+                start_cursor: edges.first().map(|edge| edge.cursor.clone()),
+                end_cursor: edges.last().map(|edge| edge.cursor.clone()),
+            },
+            edges,
+            total_count,
+        })
+    }
+}
@@ -0,0 +1,335 @@
+use bson::{doc, Bson, Document};
+use juniper::FieldError;
+use mongodb_base_service::{Node, ID};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+use crate::db::id_string;
+use crate::models::{offset_cursor, Embedded, EmbeddedType, PageInfo, SortDirection};
+use crate::schema::Context;
+
+/// Bounds on embedded values, both inclusive
+#[derive(juniper::GraphQLInputObject, Clone, Debug)]
+pub struct ValueRange {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl ValueRange {
+    fn contains(&self, value: Option<f64>) -> bool {
+        match value {
+            Some(value) => {
+                self.min.map_or(true, |min| value >= min)
+                    && self.max.map_or(true, |max| value <= max)
+            }
+            None => self.min.is_none() && self.max.is_none(),
+        }
+    }
+
+    /// The range as a query on a value field, entries without a value never match bounds
+    fn to_document(&self) -> Option<Document> {
+        let mut range = Document::new();
+        if let Some(min) = self.min {
+            range.insert("$gte", min);
+        }
+        if let Some(max) = self.max {
+            range.insert("$lte", max);
+        }
+        if range.is_empty() {
+            None
+        } else {
+            Some(range)
+        }
+    }
+}
+
+#[derive(juniper::GraphQLInputObject, Clone, Debug)]
+pub struct EmbeddedFilter {
+    pub embedded_type: Option<EmbeddedType>,
+    pub value_range: Option<ValueRange>,
+}
+
+impl EmbeddedFilter {
+    pub fn matches(&self, embedded: &Embedded) -> bool {
+        self.embedded_type.map_or(true, |embedded_type| {
+            embedded.embedded_type == embedded_type
+        }) && self
+            .value_range
+            .as_ref()
+            .map_or(true, |range| range.contains(embedded.value))
+    }
+}
+
+#[derive(juniper::GraphQLEnum, Clone, Copy, Debug, PartialEq)]
+pub enum EmbeddedSortField {
+    Value,
+    EmbeddedType,
+    DateCreated,
+    DateModified,
+}
+
+#[derive(juniper::GraphQLInputObject, Clone, Debug)]
+pub struct EmbeddedSort {
+    pub field: EmbeddedSortField,
+    /// Ascending when omitted
+    pub direction: Option<SortDirection>,
+}
+
+impl EmbeddedSort {
+    fn compare(&self, a: &Embedded, b: &Embedded) -> Ordering {
+        let ordering = match self.field {
+            EmbeddedSortField::Value => a.value.partial_cmp(&b.value).unwrap_or(Ordering::Equal),
+            EmbeddedSortField::EmbeddedType => {
+                (a.embedded_type as u8).cmp(&(b.embedded_type as u8))
+            }
+            EmbeddedSortField::DateCreated => a.node().date_created().cmp(&b.node().date_created()),
+            EmbeddedSortField::DateModified => {
+                a.node().date_modified().cmp(&b.node().date_modified())
+            }
+        };
+        match self.direction {
+            Some(SortDirection::Desc) => ordering.reverse(),
+            _ => ordering,
+        }
+    }
+}
+
+/// Orders values by the sorts in turn, in stored order when there are none. Ties keep their
+/// stored order, which makes id cursors stable.
+pub fn sort_values(values: &mut [Embedded], sort: &[EmbeddedSort]) {
+    values.sort_by(|a, b| {
+        sort.iter()
+            .map(|sort| sort.compare(a, b))
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    });
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EmbeddedEdge {
+    pub cursor: String,
+    pub node: Embedded,
+}
+
+#[juniper::object(Context = Context)]
+impl EmbeddedEdge {
+    fn cursor(&self) -> &str {
+        &self.cursor
+    }
+
+    fn node(&self) -> &Embedded {
+        &self.node
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EmbeddedConnection {
+    pub page_info: PageInfo,
+    pub edges: Vec<EmbeddedEdge>,
+    pub total_count: i64,
+}
+
+#[juniper::object(Context = Context)]
+impl EmbeddedConnection {
+    fn page_info(&self) -> &PageInfo {
+        &self.page_info
+    }
+
+    fn edges(&self) -> &Vec<EmbeddedEdge> {
+        &self.edges
+    }
+
+    fn total_count(&self) -> i32 {
+        self.total_count as i32
+    }
+}
+
+impl EmbeddedConnection {
+    /// Pages through ordered values, the cursor of a value is its id
+    pub fn page(
+        values: Vec<Embedded>,
+        limit: Option<i32>,
+        after: Option<String>,
+    ) -> Result<EmbeddedConnection, FieldError> {
+        let total_count = values.len() as i64;
+        let start = match &after {
+            Some(after) => match values
+                .iter()
+                .position(|value| id_string(&value.id) == *after)
+            {
+                Some(position) => position + 1,
+                None => return Err(format!("Invalid cursor {}", after).into()),
+            },
+            None => 0,
+        };
+        let remaining = values.len() - start;
+        let limit = limit
+            .map_or(remaining, |limit| limit as usize)
+            .min(remaining);
+        let edges: Vec<EmbeddedEdge> = values
+            .into_iter()
+            .skip(start)
+            .take(limit)
+            .map(|node| EmbeddedEdge {
+                cursor: id_string(&node.id),
+                node,
+            })
+            .collect();
+        Ok(EmbeddedConnection {
+            page_info: PageInfo {
+                has_next_page: remaining > limit,
+                has_previous_page: start > 0,
+                start_cursor: edges.first().map(|edge| edge.cursor.clone()),
+                end_cursor: edges.last().map(|edge| edge.cursor.clone()),
+            },
+            edges,
+            total_count,
+        })
+    }
+}
+
+/// An embedded value along with the sample holding it
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SampleValue {
+    pub sample_id: ID,
+    pub embedded: Embedded,
+}
+
+#[juniper::object(Context = Context)]
+impl SampleValue {
+    fn sample_id(&self) -> &ID {
+        &self.sample_id
+    }
+
+    fn embedded(&self) -> &Embedded {
+        &self.embedded
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SampleValueEdge {
+    pub cursor: String,
+    pub node: SampleValue,
+}
+
+#[juniper::object(Context = Context)]
+impl SampleValueEdge {
+    fn cursor(&self) -> &str {
+        &self.cursor
+    }
+
+    fn node(&self) -> &SampleValue {
+        &self.node
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SampleValueConnection {
+    pub page_info: PageInfo,
+    pub edges: Vec<SampleValueEdge>,
+    pub total_count: i64,
+}
+
+#[juniper::object(Context = Context)]
+impl SampleValueConnection {
+    fn page_info(&self) -> &PageInfo {
+        &self.page_info
+    }
+
+    fn edges(&self) -> &Vec<SampleValueEdge> {
+        &self.edges
+    }
+
+    fn total_count(&self) -> i32 {
+        self.total_count as i32
+    }
+}
+
+/// Unwinds the values of one type held by the samples matching `filter`, ordered by sample and
+/// position so offsets stay stable, and pages them within a `$facet` along with their count
+pub fn values_by_type_pipeline(
+    filter: Document,
+    embedded_type: EmbeddedType,
+    value_range: Option<ValueRange>,
+    offset: i64,
+    limit: Option<i32>,
+) -> Result<Vec<Document>, FieldError> {
+    let mut value = doc! { "embedded_type": bson::to_bson(&embedded_type)? };
+    if let Some(range) = value_range.as_ref().and_then(|range| range.to_document()) {
+        value.insert("value", range);
+    }
+    let mut unwound = Document::new();
+    for (key, condition) in value.iter() {
+        unwound.insert(format!("values.{}", key), condition.clone());
+    }
+    let samples = doc! { "$and": [filter, { "values": { "$elemMatch": value } }] };
+    let mut page = vec![doc! { "$skip": offset }];
+    if let Some(limit) = limit {
+        let limit = limit as i64 + 1;
+        page.push(doc! { "$limit": limit });
+    }
+    Ok(vec![
+        doc! { "$match": samples },
+        doc! { "$project": { "values": 1 } },
+        doc! { "$unwind": { "path": "$values", "includeArrayIndex": "position" } },
+        doc! { "$match": unwound },
+        doc! { "$sort": { "_id": 1, "position": 1 } },
+        doc! { "$project": { "_id": 0, "sample_id": "$_id", "embedded": "$values" } },
+        doc! { "$facet": {
+            "items": page,
+            "total": [{ "$count": "count" }],
+        } },
+    ])
+}
+
+impl SampleValueConnection {
+    /// Reads the result of `values_by_type_pipeline`, one more value than the page holds tells
+    /// whether there is a next page
+    pub fn from_facet(
+        facet: Option<Document>,
+        offset: i64,
+        limit: Option<i32>,
+    ) -> Result<SampleValueConnection, FieldError> {
+        let facet = facet.unwrap_or_default();
+        let mut values: Vec<SampleValue> = match facet.get("items") {
+            Some(items @ Bson::Array(_)) => bson::from_bson(items.clone())?,
+            _ => vec![],
+        };
+        let total_count = facet
+            .get_array("total")
+            .ok()
+            .and_then(|total| total.first())
+            .and_then(|total| total.as_document())
+            .and_then(|total| match total.get("count") {
+                Some(Bson::I32(count)) => Some(*count as i64),
+                Some(Bson::I64(count)) => Some(*count),
+                _ => None,
+            })
+            .unwrap_or(0);
+        let has_next_page = match limit {
+            Some(limit) if values.len() > limit as usize => {
+                values.truncate(limit as usize);
+                true
+            }
+            _ => false,
+        };
+        let edges: Vec<SampleValueEdge> = values
+            .into_iter()
+            .enumerate()
+            .map(|(i, node)| SampleValueEdge {
+                cursor: offset_cursor(offset + i as i64),
+                node,
+            })
+            .collect();
+        Ok(SampleValueConnection {
+            page_info: PageInfo {
+                has_next_page,
+                has_previous_page: offset > 0,
+                start_cursor: edges.first().map(|edge| edge.cursor.clone()),
+                end_cursor: edges.last().map(|edge| edge.cursor.clone()),
+            },
+            edges,
+            total_count,
+        })
+    }
+}
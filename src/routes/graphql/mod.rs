@@ -1,36 +1,26 @@
+use crate::auth::{
+    authenticate, impersonate, resolve_tenant, validate_claims, ActAsHeader, ApiKeyHeader, Claims,
+    TenantHeader, DISABLE_AUTH,
+};
 use crate::db::Clients;
 use crate::schema::{Context, Schema};
 
-use actix_web::{web, Error, HttpResponse};
+use actix_web::{web, Error, HttpRequest, HttpResponse};
 use juniper::http::graphiql::graphiql_source;
 use juniper::http::GraphQLRequest;
-use jwt_validator::{Claims, TestClaims};
+use juniper::InputValue;
+use serde::Deserialize;
 
 use std::env;
 use std::sync::Arc;
-
-lazy_static! {
-    static ref DISABLE_AUTH: u8 = env::var("DISABLE_AUTH")
-        .unwrap_or("".to_string())
-        .parse()
-        .unwrap_or(0);
-    static ref REQUIRED_EMAIL_DOMAIN: String =
-        env::var("REQUIRED_EMAIL_DOMAIN").unwrap_or("gmail.com".to_string());
-}
+use uuid::Uuid;
 
 fn invalid_request() -> HttpResponse {
     HttpResponse::Unauthorized().body("Invalid request")
 }
 
 pub async fn graphiql(claims: Option<Claims>) -> HttpResponse {
-    if *DISABLE_AUTH != 1
-        && (claims.is_none() || {
-            !claims.unwrap().validate(TestClaims {
-                hd: Some(REQUIRED_EMAIL_DOMAIN.to_string()),
-                ..TestClaims::default()
-            })
-        })
-    {
+    if *DISABLE_AUTH != 1 && !validate_claims(&claims) {
         return invalid_request();
     }
     let api_base = dotenv::var("API_BASE").unwrap_or("http://localhost:8080".to_owned());
@@ -41,20 +31,119 @@ pub async fn graphiql(claims: Option<Claims>) -> HttpResponse {
         .body(html)
 }
 
+/// The `x-request-id` sent by the caller or a proxy, or a new one
+fn request_id(req: &HttpRequest) -> String {
+    req.headers()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+/// A GraphQL request as posted, read before juniper's so `@noCache` can be taken out of it
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphQLBody {
+    query: String,
+    operation_name: Option<String>,
+    variables: Option<InputValue>,
+}
+
+const NO_CACHE_DIRECTIVE: &str = "@noCache";
+
+/// Takes the `@noCache` directive out of a query, which juniper doesn't know, and tells whether
+/// it was there. Strings and comments are left alone.
+pub fn strip_no_cache(query: &str) -> (String, bool) {
+    let mut stripped = String::with_capacity(query.len());
+    let mut found = false;
+    let mut rest = query;
+    while let Some(c) = rest.chars().next() {
+        let skip = if rest.starts_with("\"\"\"") {
+            rest[3..].find("\"\"\"").map_or(rest.len(), |end| end + 6)
+        } else if c == '"' {
+            let mut escaped = false;
+            rest[1..]
+                .char_indices()
+                .find(|(_, c)| {
+                    let end = !escaped && (*c == '"' || *c == '\n');
+                    escaped = !escaped && *c == '\\';
+                    end
+                })
+                .map_or(rest.len(), |(end, _)| end + 2)
+        } else if c == '#' {
+            rest.find('\n').unwrap_or(rest.len())
+        } else if rest.starts_with(NO_CACHE_DIRECTIVE)
+            && !rest[NO_CACHE_DIRECTIVE.len()..]
+                .starts_with(|c: char| c.is_ascii_alphanumeric() || c == '_')
+        {
+            found = true;
+            rest = &rest[NO_CACHE_DIRECTIVE.len()..];
+            continue;
+        } else {
+            c.len_utf8()
+        };
+        stripped.push_str(&rest[..skip]);
+        rest = &rest[skip..];
+    }
+    (stripped, found)
+}
+
+/// Whether the caller asked to bypass caches with `Cache-Control: no-cache` or `no-store`
+fn no_cache_header(req: &HttpRequest) -> bool {
+    req.headers()
+        .get("cache-control")
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |value| {
+            value.split(',').any(|directive| {
+                let directive = directive.trim().to_lowercase();
+                directive == "no-cache" || directive == "no-store"
+            })
+        })
+}
+
 pub async fn graphql(
+    req: HttpRequest,
     st: web::Data<Arc<Schema>>,
     clients: web::Data<Arc<Clients>>,
-    data: web::Json<GraphQLRequest>,
+    data: web::Json<GraphQLBody>,
     claims: Option<Claims>,
+    api_key: Option<ApiKeyHeader>,
+    act_as: Option<ActAsHeader>,
+    tenant: Option<TenantHeader>,
 ) -> Result<HttpResponse, Error> {
-    let context = Context { clients, claims };
-
+    let request_id = request_id(&req);
+    let context_request_id = request_id.clone();
+    let data = data.into_inner();
+    let (query, no_cache_directive) = strip_no_cache(&data.query);
+    let no_cache = no_cache_directive || no_cache_header(&req);
+    let data = GraphQLRequest::new(query, data.operation_name, data.variables);
     let result = web::block(move || {
+        let mut principal = match authenticate(clients.get_ref(), &claims, &api_key) {
+            Ok(principal) => principal,
+            Err(e) => return Ok(Err(e)),
+        };
+        principal.tenant = match resolve_tenant(&principal, &claims, &tenant) {
+            Ok(tenant) => tenant,
+            Err(e) => return Ok(Err(e)),
+        };
+        let principal = match act_as {
+            Some(ActAsHeader(target)) => match impersonate(clients.get_ref(), principal, &target) {
+                Ok(principal) => principal,
+                Err(e) => return Ok(Err(e)),
+            },
+            None => principal,
+        };
+        let mut context = Context::new(clients, principal, context_request_id);
+        context.no_cache = no_cache;
         let res = data.execute(&st, &context);
-        Ok::<_, serde_json::error::Error>(serde_json::to_string(&res)?)
+        Ok::<_, serde_json::error::Error>(Ok(serde_json::to_string(&res)?))
     })
     .await?;
-    Ok(HttpResponse::Ok()
-        .content_type("application/json")
-        .body(result))
+    match result {
+        Ok(body) => Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .header("x-request-id", request_id)
+            .body(body)),
+        Err(e) => Ok(HttpResponse::Forbidden().body(e)),
+    }
 }
@@ -1,4 +1,4 @@
-mod graphql;
+pub mod graphql;
 mod health;
 
 use actix_web::{web, HttpResponse};
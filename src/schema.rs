@@ -1,45 +1,99 @@
 use actix_web::web::Data;
-use bson::doc;
-use cached::TimedCache;
-use juniper::{FieldError, RootNode};
-use jwt_validator::{Claims, TestClaims};
-use log::debug;
-use mongodb_base_service::{BaseService, DeleteResponseGQL, ServiceError, ID};
+use bson::{doc, Bson, Document};
+use chrono::{DateTime, Utc};
+use juniper::{graphql_value, FieldError, RootNode};
+use log::{debug, error, warn};
+use mongodb::options::{AggregateOptions, FindOptions};
+use mongodb_base_service::{
+    mock_time, BaseService, DataSources, DeleteResponseGQL, ServiceError, ID,
+};
 use mongodb_cursor_pagination::FindResult;
-use std::env;
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
+use uuid::Uuid;
 
-use crate::db::Clients;
+use crate::auth::*;
+use crate::cache::{self, list_tags, uses_values, write_tags, SampleWrite, Tag};
+use crate::db::search_index::{IndexedSample, FUZZY_BATCH_SIZE, MAX_FUZZY_CANDIDATES};
+use crate::db::{and_filters, id_filter, id_from_string, id_string, Clients};
 use crate::models::*;
 
+/// The current unix time, following the mock time set by tests
 pub fn now() -> u64 {
-    SystemTime::now()
+    mock_time::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap()
         .as_secs()
 }
 
-lazy_static! {
-    static ref CACHE_CAPACITY: usize = env::var("CACHE_CAPACITY")
-        .unwrap_or("".to_string())
-        .parse()
-        .unwrap_or(10000);
-    static ref CACHE_TTL: u64 = env::var("CACHE_TTL")
-        .unwrap_or("".to_string())
-        .parse()
-        .unwrap_or(60);
-    static ref DISABLE_AUTH: u8 = env::var("DISABLE_AUTH")
-        .unwrap_or("".to_string())
-        .parse()
-        .unwrap_or(0);
-    static ref REQUIRED_EMAIL_DOMAIN: String =
-        env::var("REQUIRED_EMAIL_DOMAIN").unwrap_or("gmail.com".to_string());
+/// Tells the time that statuses are evaluated at
+pub trait Clock: Send + Sync {
+    fn now(&self) -> i64;
+}
+
+/// The wall clock, or the mock time in tests
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        now() as i64
+    }
+}
+
+/// A clock stopped at a point in time
+#[allow(dead_code)]
+pub struct FixedClock(pub i64);
+
+impl Clock for FixedClock {
+    fn now(&self) -> i64 {
+        self.0
+    }
 }
 
 pub struct Context {
     pub clients: Data<Arc<Clients>>,
-    pub claims: Option<Claims>,
+    pub principal: Principal,
+    /// The `x-request-id` of the request, recorded in the audit log
+    pub request_id: String,
+    pub clock: Arc<dyn Clock>,
+    /// Set by the `@noCache` directive or a `Cache-Control: no-cache` header to skip cached
+    /// results, for callers that need to read their own writes
+    pub no_cache: bool,
+    /// The database of the principal's tenant in database-per-tenant mode
+    tenant_mongo: Option<Arc<DataSources>>,
+}
+
+impl Context {
+    pub fn new(clients: Data<Arc<Clients>>, principal: Principal, request_id: String) -> Context {
+        let tenant_mongo = match (&clients.tenant_databases, &principal.tenant) {
+            (Some(databases), Some(tenant)) => Some(databases.get(tenant)),
+            _ => None,
+        };
+        Context {
+            clients,
+            principal,
+            request_id,
+            clock: Arc::new(SystemClock),
+            no_cache: false,
+            tenant_mongo,
+        }
+    }
+
+    /// Evaluates statuses at the time of another clock
+    #[allow(dead_code)]
+    pub fn with_clock(self, clock: Arc<dyn Clock>) -> Context {
+        Context { clock, ..self }
+    }
+
+    /// The data sources of the request's tenant, `samples` lives here while `api_keys`,
+    /// `user_roles` and `revocations` are always shared
+    pub fn mongo(&self) -> &DataSources {
+        match &self.tenant_mongo {
+            Some(mongo) => mongo,
+            None => &self.clients.mongo,
+        }
+    }
 }
 
 impl juniper::Context for Context {}
@@ -50,149 +104,456 @@ pub struct Query;
 impl Query {
     fn all_samples(
         ctx: &Context,
+        filter: Option<SampleFilter>,
+        sort: Option<Vec<SampleSort>>,
         limit: Option<i32>,
+        first: Option<i32>,
+        last: Option<i32>,
         after: Option<String>,
         before: Option<String>,
         skip: Option<i32>,
     ) -> Result<SampleConnection, FieldError> {
-        cached_key_result! {
-            ALL_SAMPLES: TimedCache<String, SampleConnection> =
-                TimedCache::with_lifespan_and_capacity(*CACHE_TTL, *CACHE_CAPACITY);
-            Key = { format!("{:?},{:?},{:?},{:?}", limit, after, before, skip) };
-            fn build(
-                ctx: &Clients,
-                limit: Option<i32>,
-                after: Option<String>,
-                before: Option<String>,
-                skip: Option<i32>
-            ) -> Result<SampleConnection, FieldError> = {
-                debug!("Building all samples");
-                let service = &ctx.mongo.get_mongo_service("samples").unwrap();
-                let result: Result<FindResult<Sample>, ServiceError> = service.find(None, None, limit, after, before, skip);
-                match result {
-                    Ok(all_items) => {
-                        let connection: SampleConnection = all_items.into();
-                        Ok(connection)
-                    },
-                    Err(e) => Err(FieldError::from(e))
-                }
-            }
-        }
-        build(ctx.clients.get_ref(), limit, after, before, skip).map_err(|e| e.into())
+        authorize(&ctx.principal, "allSamples")?;
+        let limit = page_limit(limit, first, last, &before)?;
+        let filter = filter
+            .map(|filter| filter.to_document(&ctx.principal))
+            .transpose()?;
+        let sort = sort_document(&sort.unwrap_or_default())?;
+        let scope = read_scope(&ctx.principal);
+        let key = format!(
+            "{},{:?},{:?},{:?},{:?},{:?},{:?}",
+            scope.key, filter, sort, limit, after, before, skip
+        );
+        let values = filter.as_ref().map_or(false, uses_values) || uses_values(&sort);
+        cache::ALL_SAMPLES.get_or_build(ctx.no_cache, key, || {
+            debug!("Building all samples");
+            let service = ctx.mongo().get_mongo_service("samples").unwrap();
+            let filter = and_filters(vec![filter, scope.filter]);
+            let result: FindResult<Sample> =
+                service.find(filter, Some(sort), limit, after, before, skip)?;
+            let connection: SampleConnection = result.into();
+            let tags = connection_tags(ctx, &connection, values);
+            Ok((connection, tags))
+        })
     }
 
+    /// Full text search over the text index, ranked by relevance unless `sort` is given.
+    /// Quoted phrases and `-negated` words are supported.
     fn search_samples(
         ctx: &Context,
         search_term: String,
-        fields: Vec<String>,
+        fields: Option<Vec<String>>,
+        language: Option<String>,
+        filter: Option<SampleFilter>,
+        sort: Option<Vec<SampleSort>>,
         limit: Option<i32>,
+        first: Option<i32>,
+        last: Option<i32>,
         after: Option<String>,
         before: Option<String>,
         skip: Option<i32>,
-    ) -> Result<SampleConnection, FieldError> {
-        cached_key_result! {
-            SEARCH_SAMPLES: TimedCache<String, SampleConnection> =
-                TimedCache::with_lifespan_and_capacity(*CACHE_TTL, *CACHE_CAPACITY);
-            Key = { format!("{:?},{:?},{:?},{:?},{:?},{:?}", search_term, fields, limit, after, before, skip) };
-            fn build(
-                ctx: &Clients,
-                search_term: String,
-                fields: Vec<String>,
-                limit: Option<i32>,
-                after: Option<String>,
-                before: Option<String>,
-                skip: Option<i32>
-            ) -> Result<SampleConnection, FieldError> = {
-                let service = &ctx.mongo.get_mongo_service("samples").unwrap();
-                let result: Result<FindResult<Sample>, ServiceError> =
-                    service.search(search_term, fields, None, limit, after, before, skip);
-                match result {
-                    Ok(all_items) => {
-                        let connection: SampleConnection = all_items.into();
-                        Ok(connection)
-                    },
-                    Err(e) => Err(FieldError::from(e))
-                }
+    ) -> Result<SampleSearchConnection, FieldError> {
+        authorize(&ctx.principal, "searchSamples")?;
+        let limit = page_limit(limit, first, last, &before)?;
+        let (offset, limit) = page_offset(limit, &after, &before, skip)?;
+        let fields = search_fields(fields)?;
+        let text = text_filter(&search_term, &fields, language)?;
+        let filter = filter
+            .map(|filter| filter.to_document(&ctx.principal))
+            .transpose()?;
+        let sort = match sort {
+            Some(sort) => sort_document(&sort)?,
+            None => doc! { "score": { "$meta": "textScore" }, "_id": 1 },
+        };
+        let scope = read_scope(&ctx.principal);
+        let key = format!(
+            "{},{:?},{:?},{:?},{:?},{:?},{:?}",
+            scope.key, search_term, text, filter, sort, limit, offset
+        );
+        let values = filter.as_ref().map_or(false, uses_values) || uses_values(&sort);
+        cache::SEARCH_SAMPLES.get_or_build(ctx.no_cache, key, || {
+            let service = ctx.mongo().get_mongo_service("samples").unwrap();
+            let collection = service.data_source();
+            let filter = and_filters(vec![Some(text), filter, scope.filter]).unwrap_or_default();
+            let total_count = collection.count_documents(filter.clone(), None)?;
+            let mut options = FindOptions::default();
+            options.projection = Some(doc! { "score": { "$meta": "textScore" } });
+            options.sort = Some(sort);
+            options.skip = Some(offset);
+            options.limit = limit.map(|limit| limit as i64 + 1);
+            let terms = search_terms(&search_term);
+            let hits = collection
+                .find(filter, options)?
+                .map(|document| -> Result<SampleSearchHit, FieldError> {
+                    let document = document?;
+                    let score = document.get_f64("score").unwrap_or(0.);
+                    let sample: Sample = bson::from_bson(Bson::Document(document))?;
+                    Ok(SampleSearchHit::new(sample, score, &fields, &terms))
+                })
+                .collect::<Result<Vec<SampleSearchHit>, FieldError>>()?;
+            let connection = SampleSearchConnection::page(hits, offset, limit, total_count);
+            let ids = connection.edges.iter().map(|edge| id_string(&edge.node.id));
+            let tags = list_tags(&ctx.principal.tenant, ids, values);
+            Ok((connection, tags))
+        })
+    }
 
+    /// Typo tolerant search over names and descriptions where the last word may be a prefix,
+    /// ranked by the local search index. Not cached since the index changes on every write.
+    fn fuzzy_search_samples(
+        ctx: &Context,
+        search_term: String,
+        filter: Option<SampleFilter>,
+        limit: Option<i32>,
+        first: Option<i32>,
+        last: Option<i32>,
+        after: Option<String>,
+        before: Option<String>,
+        skip: Option<i32>,
+    ) -> Result<SampleConnection, FieldError> {
+        authorize(&ctx.principal, "fuzzySearchSamples")?;
+        let index = match &ctx.clients.search_index {
+            Some(index) => index,
+            None => return Err("Fuzzy search is not enabled".into()),
+        };
+        let limit = page_limit(limit, first, last, &before)?;
+        let (offset, limit) = page_offset(limit, &after, &before, skip)?;
+        let filter = filter
+            .map(|filter| filter.to_document(&ctx.principal))
+            .transpose()?;
+        let filter = and_filters(vec![filter, read_scope(&ctx.principal).filter]);
+        let service = ctx.mongo().get_mongo_service("samples").unwrap();
+        // the index doesn't know what the caller may read, so candidates are scoped in batches
+        // until the ranking runs out, which keeps pages and the count right for every caller
+        let mut ranked = vec![];
+        let mut scanned = 0;
+        while scanned < MAX_FUZZY_CANDIDATES {
+            let ids = index.search(
+                &ctx.principal.tenant,
+                &search_term,
+                scanned,
+                FUZZY_BATCH_SIZE,
+            )?;
+            scanned += ids.len();
+            let candidates: Vec<Bson> = ids.iter().map(|id| id_from_string(id)).collect();
+            let batch_filter = and_filters(vec![
+                Some(doc! { "_id": { "$in": candidates } }),
+                filter.clone(),
+            ]);
+            let result: FindResult<Sample> =
+                service.find(batch_filter, None, None, None, None, None)?;
+            // mongo only scopes the candidates, their order comes from the index
+            let mut samples: HashMap<String, Sample> = result
+                .items
+                .into_iter()
+                .map(|sample| (id_string(&sample.id), sample))
+                .collect();
+            ranked.extend(ids.iter().filter_map(|id| samples.remove(id)));
+            if ids.len() < FUZZY_BATCH_SIZE {
+                break;
             }
         }
-        build(
-            ctx.clients.get_ref(),
-            search_term,
-            fields,
-            limit,
-            after,
-            before,
-            skip,
-        )
+        Ok(SampleConnection::page(ranked, offset, limit))
     }
 
+    /// Statuses are evaluated now, or at `asOf` to preview them at another time
     fn samples_by_status(
         ctx: &Context,
         status: Option<Status>,
+        as_of: Option<DateTime<Utc>>,
+        filter: Option<SampleFilter>,
+        sort: Option<Vec<SampleSort>>,
         limit: Option<i32>,
+        first: Option<i32>,
+        last: Option<i32>,
         after: Option<String>,
         before: Option<String>,
         skip: Option<i32>,
     ) -> Result<SampleConnection, FieldError> {
-        cached_key_result! {
-            SAMPLES_BY_STATUS: TimedCache<String, SampleConnection> =
-                TimedCache::with_lifespan_and_capacity(*CACHE_TTL, *CACHE_CAPACITY);
-            Key = { format!("{:?},{:?},{:?},{:?},{:?}", status, limit, after, before, skip) };
-            fn build(
-                ctx: &Clients,
-                status: Option<Status>,
-                limit: Option<i32>,
-                after: Option<String>,
-                before: Option<String>,
-                skip: Option<i32>
-            ) -> Result<SampleConnection, FieldError> = {
-                let service = &ctx.mongo.get_mongo_service("samples").unwrap();
-                let timestamp = now();
-                let filter = match status {
-                    Some(status) => match status {
-                        Status::Active => Some(doc! {
-                            "available_date": { "$lt": timestamp },
-                            "expiration_date": { "$gt": timestamp },
-                        }),
-                        Status::Expired => Some(doc! {
-                            "expiration_date": { "$lt": timestamp },
-                        }),
-                        Status::Pending => Some(doc! {
-                            "available_date": { "$gt": timestamp },
-                        }),
-                        Status::Available => Some(doc! {
-                            "available_date": { "$lt": timestamp }
-                        }),
-                        Status::All => None,
-                    },
-                    None => None,
+        authorize(&ctx.principal, "samplesByStatus")?;
+        let limit = page_limit(limit, first, last, &before)?;
+        let as_of = as_of.map(|as_of| as_of.timestamp());
+        let timestamp = as_of.unwrap_or_else(|| ctx.clock.now());
+        let filter = filter
+            .map(|filter| filter.to_document(&ctx.principal))
+            .transpose()?;
+        let sort = sort_document(&sort.unwrap_or_default())?;
+        let scope = read_scope(&ctx.principal);
+        let key = format!(
+            "{},{:?},{:?},{:?},{:?},{:?},{:?},{:?},{:?}",
+            scope.key, status, as_of, filter, sort, limit, after, before, skip
+        );
+        let values = filter.as_ref().map_or(false, uses_values) || uses_values(&sort);
+        cache::SAMPLES_BY_STATUS
+            .get_or_build_expiring(ctx.no_cache, key, || {
+                let service = ctx.mongo().get_mongo_service("samples").unwrap();
+                let status_filter = status.and_then(|status| status.filter(timestamp));
+                let filter = and_filters(vec![filter, scope.filter]);
+                let valid_for = match (&status_filter, as_of) {
+                    (Some(_), None) => status_lifespan(ctx, filter.clone(), timestamp)?,
+                    _ => None,
                 };
-                let result: Result<FindResult<Sample>, ServiceError> =
-                    service.find(filter, None, limit, after, before, skip);
-                match (result) {
-                    Ok(all_items) => {
-                        let connection: SampleConnection = all_items.into();
-                        Ok(connection)
-                    },
-                    Err(e) => Err(FieldError::from(e))
+                let filter = and_filters(vec![status_filter, filter]);
+                let result: FindResult<Sample> =
+                    service.find(filter, Some(sort), limit, after, before, skip)?;
+                let connection: SampleConnection = result.into();
+                let tags = connection_tags(ctx, &connection, values);
+                Ok((connection, tags, valid_for))
+            })
+            .map(|connection| connection.evaluated_at(as_of))
+    }
+
+    /// The number of samples with each status, counted in a single aggregation
+    fn sample_status_counts(
+        ctx: &Context,
+        filter: Option<SampleFilter>,
+    ) -> Result<Vec<StatusCount>, FieldError> {
+        authorize(&ctx.principal, "sampleStatusCounts")?;
+        let filter = filter
+            .map(|filter| filter.to_document(&ctx.principal))
+            .transpose()?;
+        let scope = read_scope(&ctx.principal);
+        let key = format!("{},{:?}", scope.key, filter);
+        let timestamp = ctx.clock.now();
+        cache::SAMPLE_STATUS_COUNTS.get_or_build_expiring(ctx.no_cache, key, || {
+            let service = ctx.mongo().get_mongo_service("samples").unwrap();
+            let filter = and_filters(vec![filter, scope.filter]).unwrap_or_default();
+            let valid_for = status_lifespan(ctx, Some(filter.clone()), timestamp)?;
+            let mut facets = Document::new();
+            for status in Status::ALL.iter() {
+                let mut stages = vec![];
+                if let Some(status_filter) = status.filter(timestamp) {
+                    stages.push(doc! { "$match": status_filter });
                 }
+                stages.push(doc! { "$count": "count" });
+                facets.insert(format!("{:?}", status), stages);
             }
+            let pipeline = vec![doc! { "$match": filter }, doc! { "$facet": facets }];
+            let counts = match service.data_source().aggregate(pipeline, None)?.next() {
+                Some(counts) => counts?,
+                None => Document::new(),
+            };
+            let status_counts: Vec<StatusCount> = Status::ALL
+                .iter()
+                .map(|status| {
+                    let count = counts
+                        .get_array(&format!("{:?}", status))
+                        .ok()
+                        .and_then(|facet| facet.first())
+                        .and_then(|facet| facet.as_document())
+                        .and_then(|facet| facet.get("count"))
+                        .and_then(|count| {
+                            count
+                                .as_i32()
+                                .or_else(|| count.as_i64().map(|count| count as i32))
+                        })
+                        .unwrap_or(0);
+                    StatusCount {
+                        status: *status,
+                        count,
+                    }
+                })
+                .collect();
+            let tags = vec![Tag::Samples(ctx.principal.tenant.clone())];
+            Ok((status_counts, tags, valid_for))
+        })
+    }
+
+    /// Statistics over the values of the matching samples, optionally of one embedded type.
+    /// Percentiles use the nearest rank and the histogram splits `[min, max]` evenly.
+    fn value_statistics(
+        ctx: &Context,
+        filter: Option<SampleFilter>,
+        embedded_type: Option<EmbeddedType>,
+        percentiles: Option<Vec<f64>>,
+        buckets: Option<i32>,
+    ) -> Result<ValueStatistics, FieldError> {
+        authorize(&ctx.principal, "valueStatistics")?;
+        let filter = filter
+            .map(|filter| filter.to_document(&ctx.principal))
+            .transpose()?;
+        let percentiles = statistics_percentiles(percentiles)?;
+        let buckets = statistics_buckets(buckets)?;
+        let scope = read_scope(&ctx.principal);
+        let key = format!(
+            "{},{:?},{:?},{:?},{}",
+            scope.key, filter, embedded_type, percentiles, buckets
+        );
+        cache::VALUE_STATISTICS.get_or_build(ctx.no_cache, key, || {
+            let service = ctx.mongo().get_mongo_service("samples").unwrap();
+            let collection = service.data_source();
+            let filter = and_filters(vec![filter, scope.filter]).unwrap_or_default();
+            let mut options = AggregateOptions::default();
+            options.allow_disk_use = Some(true);
+            let pipeline = summary_pipeline(filter.clone(), embedded_type)?;
+            let summary = collection
+                .aggregate(pipeline, Some(options.clone()))?
+                .next()
+                .transpose()?;
+            let statistics = ValueStatistics::from_summary(summary);
+            let statistics = if statistics.count > 0 && !percentiles.is_empty() {
+                let count = statistics.count as i64;
+                let pipeline =
+                    percentile_pipeline(filter.clone(), embedded_type, &percentiles, count)?;
+                let ranks = collection
+                    .aggregate(pipeline, Some(options))?
+                    .next()
+                    .transpose()?;
+                statistics.with_percentiles(&percentiles, ranks)
+            } else {
+                statistics
+            };
+            let statistics = match (statistics.min, statistics.max) {
+                (Some(min), Some(max)) if min < max => {
+                    let boundaries = histogram_boundaries(min, max, buckets);
+                    let pipeline = histogram_pipeline(filter, embedded_type, &boundaries)?;
+                    let counts = collection
+                        .aggregate(pipeline, None)?
+                        .collect::<Result<Vec<Document>, _>>()?;
+                    statistics.with_histogram(&boundaries, &counts)
+                }
+                _ => statistics.with_single_bucket(),
+            };
+            let tenant = &ctx.principal.tenant;
+            let tags = vec![Tag::Samples(tenant.clone()), Tag::Values(tenant.clone())];
+            Ok((statistics, tags))
+        })
+    }
+
+    /// The values of one type across the matching samples, with the id of the sample holding
+    /// each. Values are unwound in Mongo, ordered by sample and position within it.
+    fn values_by_type(
+        ctx: &Context,
+        embedded_type: EmbeddedType,
+        value_range: Option<ValueRange>,
+        filter: Option<SampleFilter>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> Result<SampleValueConnection, FieldError> {
+        authorize(&ctx.principal, "valuesByType")?;
+        // a page is returned within a single document, which has to stay under 16MB
+        let limit = bounded_page_size(page_limit(None, first, None, &None)?)?;
+        let (offset, limit) = page_offset(Some(limit), &after, &None, None)?;
+        let filter = filter
+            .map(|filter| filter.to_document(&ctx.principal))
+            .transpose()?;
+        let filter =
+            and_filters(vec![filter, read_scope(&ctx.principal).filter]).unwrap_or_default();
+        let pipeline = values_by_type_pipeline(filter, embedded_type, value_range, offset, limit)?;
+        let service = ctx.mongo().get_mongo_service("samples").unwrap();
+        let mut options = AggregateOptions::default();
+        options.allow_disk_use = Some(true);
+        let facet = service
+            .data_source()
+            .aggregate(pipeline, Some(options))?
+            .next()
+            .transpose()?;
+        SampleValueConnection::from_facet(facet, offset, limit)
+    }
+
+    /// Samples expiring within `duration` seconds from now, soonest first unless `sort` is given
+    fn samples_expiring_within(
+        ctx: &Context,
+        duration: i32,
+        filter: Option<SampleFilter>,
+        sort: Option<Vec<SampleSort>>,
+        first: Option<i32>,
+        last: Option<i32>,
+        after: Option<String>,
+        before: Option<String>,
+    ) -> Result<SampleConnection, FieldError> {
+        authorize(&ctx.principal, "samplesExpiringWithin")?;
+        let limit = page_limit(None, first, last, &before)?;
+        let window = UpcomingWindow {
+            field: "expiration_date",
+            duration,
+        };
+        samples_within(ctx, window, filter, sort, limit, after, before)
+    }
+
+    /// Samples becoming available within `duration` seconds from now, soonest first unless
+    /// `sort` is given
+    fn samples_becoming_available_within(
+        ctx: &Context,
+        duration: i32,
+        filter: Option<SampleFilter>,
+        sort: Option<Vec<SampleSort>>,
+        first: Option<i32>,
+        last: Option<i32>,
+        after: Option<String>,
+        before: Option<String>,
+    ) -> Result<SampleConnection, FieldError> {
+        authorize(&ctx.principal, "samplesBecomingAvailableWithin")?;
+        let limit = page_limit(None, first, last, &before)?;
+        let window = UpcomingWindow {
+            field: "available_date",
+            duration,
+        };
+        samples_within(ctx, window, filter, sort, limit, after, before)
+    }
+
+    /// The samples becoming available or expiring within `[from, to)`, in chronological order
+    fn sample_timeline(
+        ctx: &Context,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        filter: Option<SampleFilter>,
+        first: Option<i32>,
+        last: Option<i32>,
+        after: Option<String>,
+        before: Option<String>,
+    ) -> Result<TimelineConnection, FieldError> {
+        authorize(&ctx.principal, "sampleTimeline")?;
+        let limit = bounded_page_size(page_limit(None, first, last, &before)?)?;
+        let (from, to) = (from.timestamp(), to.timestamp());
+        if to <= from {
+            return Err("`to` must be after `from`".into());
         }
-        build(ctx.clients.get_ref(), status, limit, after, before, skip)
+        let filter = filter
+            .map(|filter| filter.to_document(&ctx.principal))
+            .transpose()?;
+        let window = doc! { "$or": [
+            { "available_date": { "$gte": from, "$lt": to } },
+            { "expiration_date": { "$gte": from, "$lt": to } },
+            { "occurrences": { "$elemMatch": { "start": { "$gte": from, "$lt": to } } } },
+            { "occurrences": { "$elemMatch": { "end": { "$gte": from, "$lt": to } } } },
+        ] };
+        let filter = and_filters(vec![
+            Some(window),
+            filter,
+            read_scope(&ctx.principal).filter,
+        ]);
+        // events are ordered and paged in mongo, only the samples of the page are loaded
+        let pipeline =
+            timeline_pipeline(filter.unwrap_or_default(), from, to, &after, &before, limit)?;
+        let service = ctx.mongo().get_mongo_service("samples").unwrap();
+        let mut options = AggregateOptions::default();
+        options.allow_disk_use = Some(true);
+        let facet = service
+            .data_source()
+            .aggregate(pipeline, Some(options))?
+            .next()
+            .transpose()?;
+        let keys = TimelineKeys::from_facet(facet)?;
+        let filter = doc! { "_id": { "$in": keys.sample_ids.clone() } };
+        let result: FindResult<Sample> =
+            service.find(Some(filter), None, None, None, None, None)?;
+        let events = keys.select(timeline_events(result.items, from, to));
+        TimelineConnection::page(events, keys.total_count, Some(limit), after, before)
     }
 
     // don't cache on requests by id
     fn sample_by_id(ctx: &Context, id: ID) -> Result<Sample, FieldError> {
-        let service = ctx
-            .clients
-            .get_ref()
-            .mongo
-            .get_mongo_service("samples")
-            .unwrap();
-        let result: Result<Option<Sample>, ServiceError> = service.find_one_by_id(id);
+        authorize(&ctx.principal, "sampleById")?;
+        let service = ctx.mongo().get_mongo_service("samples").unwrap();
+        let filter = and_filters(vec![
+            Some(id_filter(&id)),
+            read_scope(&ctx.principal).filter,
+        ]);
+        let result: Result<FindResult<Sample>, ServiceError> =
+            service.find(filter, None, Some(1), None, None, None);
         match result {
-            Ok(item) => match item {
+            Ok(result) => match result.items.into_iter().next() {
                 Some(item) => Ok(item),
                 None => Err("Unable to find item".into()),
             },
@@ -204,69 +565,483 @@ impl Query {
         ctx: &Context,
         names: Vec<String>,
         status: Option<Status>,
+        as_of: Option<DateTime<Utc>>,
+        sort: Option<Vec<SampleSort>>,
+        first: Option<i32>,
+        last: Option<i32>,
+        after: Option<String>,
+        before: Option<String>,
     ) -> Result<SampleConnection, FieldError> {
-        cached_key_result! {
-            SAMPLE_BY_NAME: TimedCache<String, SampleConnection> =
-                TimedCache::with_lifespan_and_capacity(*CACHE_TTL, *CACHE_CAPACITY);
-            Key = { format!("{:?},{:?}", names, status) };
-            fn build(
-                ctx: &Clients,
-                names: Vec<String>,
-                status: Option<Status>
-            ) -> Result<SampleConnection, FieldError> = {
-                let service = &ctx.mongo.get_mongo_service("samples").unwrap();
-                let timestamp = now();
-
-                let mut filter = match status {
-                    Some(status) => match status {
-                        Status::Active => Some(doc! {
-                            "name": { "$in": names },
-                            "available_date": { "$lt": timestamp },
-                            "expiration_date": { "$gt": timestamp },
-                        }),
-                        Status::Expired => Some(doc! {
-                            "name": { "$in": names },
-                            "expiration_date": { "$lt": timestamp },
-                        }),
-                        Status::Pending => Some(doc! {
-                            "name": { "$in": names },
-                            "available_date": { "$gt": timestamp },
-                        }),
-                        Status::Available => Some(doc! {
-                            "name": { "$in": names },
-                            "available_date": { "$lt": timestamp }
-                        }),
-                        Status::All => Some(doc! { "name": doc! { "$in": names } }),
-                    },
-                    None => Some(doc! { "name": { "$in": names } }),
+        authorize(&ctx.principal, "sampleByNames")?;
+        let limit = page_limit(None, first, last, &before)?;
+        let as_of = as_of.map(|as_of| as_of.timestamp());
+        let timestamp = as_of.unwrap_or_else(|| ctx.clock.now());
+        let sort = sort_document(&sort.unwrap_or_default())?;
+        let scope = read_scope(&ctx.principal);
+        let key = format!(
+            "{},{:?},{:?},{:?},{:?},{:?},{:?},{:?}",
+            scope.key, names, status, as_of, sort, limit, after, before
+        );
+        let values = uses_values(&sort);
+        cache::SAMPLE_BY_NAME
+            .get_or_build_expiring(ctx.no_cache, key, || {
+                let service = ctx.mongo().get_mongo_service("samples").unwrap();
+                let tenant = &ctx.principal.tenant;
+                // only writes to samples with one of the names change which ones match, so the
+                // names stand in for `Tag::Samples`
+                let mut tags: Vec<Tag> = names
+                    .iter()
+                    .map(|name| Tag::Name(tenant.clone(), name.clone()))
+                    .collect();
+                let names = Some(doc! { "name": { "$in": names } });
+                let status_filter = status.and_then(|status| status.filter(timestamp));
+                let filter = and_filters(vec![names, scope.filter]);
+                let valid_for = match (&status_filter, as_of) {
+                    (Some(_), None) => status_lifespan(ctx, filter.clone(), timestamp)?,
+                    _ => None,
                 };
-
-                let result: Result<FindResult<Sample>, ServiceError> = service.find(filter, None, None, None, None, None);
-                match result {
-                    Ok(all_items) => {
-                        let connection: SampleConnection = all_items.into();
-                        Ok(connection)
-                    },
-                    Err(e) => Err(FieldError::from(e))
+                let filter = and_filters(vec![status_filter, filter]);
+                let result: FindResult<Sample> =
+                    service.find(filter, Some(sort), limit, after, before, None)?;
+                let connection: SampleConnection = result.into();
+                if values {
+                    tags.push(Tag::Values(tenant.clone()));
                 }
-            }
+                tags.extend(
+                    connection
+                        .edges
+                        .iter()
+                        .map(|edge| Tag::Sample(tenant.clone(), id_string(&edge.node.id))),
+                );
+                Ok((connection, tags, valid_for))
+            })
+            .map(|connection| connection.evaluated_at(as_of))
+    }
+
+    // api keys
+    fn api_keys(ctx: &Context, include_revoked: Option<bool>) -> Result<Vec<ApiKey>, FieldError> {
+        authorize(&ctx.principal, "apiKeys")?;
+        let service = ctx.clients.mongo.get_mongo_service("api_keys").unwrap();
+        let revoked = match include_revoked {
+            Some(true) => None,
+            _ => Some(doc! { "revoked_at": null }),
+        };
+        let filter = and_filters(vec![Some(tenant_filter(&ctx.principal)), revoked]);
+        let result: FindResult<ApiKey> = service.find(filter, None, None, None, None, None)?;
+        Ok(result.items)
+    }
+
+    // audit log
+    fn audit_trail(
+        ctx: &Context,
+        sample_id: ID,
+        first: Option<i32>,
+        last: Option<i32>,
+        after: Option<String>,
+        before: Option<String>,
+    ) -> Result<AuditRecordConnection, FieldError> {
+        authorize(&ctx.principal, "auditTrail")?;
+        let limit = page_limit(None, first, last, &before)?;
+        let samples = ctx.mongo().get_mongo_service("samples").unwrap();
+        let readable = and_filters(vec![
+            Some(id_filter(&sample_id)),
+            read_scope(&ctx.principal).filter,
+        ]);
+        if samples.data_source().count_documents(readable, None)? == 0 {
+            return Err("Unable to find item".into());
         }
-        build(ctx.clients.get_ref(), names, status).map_err(|e| e.into())
+        let filter = AuditLogFilter {
+            target_id: Some(id_string(&sample_id)),
+            ..AuditLogFilter::default()
+        };
+        let filter = and_filters(vec![
+            Some(filter.to_document()),
+            Some(tenant_filter(&ctx.principal)),
+        ]);
+        let service = ctx.clients.mongo.get_mongo_service("audit_log").unwrap();
+        let result: FindResult<AuditRecord> =
+            service.find(filter, None, limit, after, before, None)?;
+        Ok(result.into())
+    }
+
+    fn audit_log(
+        ctx: &Context,
+        filter: Option<AuditLogFilter>,
+        first: Option<i32>,
+        last: Option<i32>,
+        after: Option<String>,
+        before: Option<String>,
+    ) -> Result<AuditRecordConnection, FieldError> {
+        authorize(&ctx.principal, "auditLog")?;
+        let limit = page_limit(None, first, last, &before)?;
+        let filter = and_filters(vec![
+            Some(filter.unwrap_or_default().to_document()),
+            Some(tenant_filter(&ctx.principal)),
+        ]);
+        let service = ctx.clients.mongo.get_mongo_service("audit_log").unwrap();
+        let result: FindResult<AuditRecord> =
+            service.find(filter, None, limit, after, before, None)?;
+        Ok(result.into())
+    }
+
+    // revocations
+    fn revocations(ctx: &Context) -> Result<Vec<Revocation>, FieldError> {
+        authorize(&ctx.principal, "revocations")?;
+        let service = ctx.clients.mongo.get_mongo_service("revocations").unwrap();
+        let result: FindResult<Revocation> = service.find(None, None, None, None, None, None)?;
+        Ok(result.items)
     }
 }
 
 pub struct Mutation;
 
-fn has_auth(ctx: &Context) -> bool {
-    if ctx.claims.is_none()
-        || !ctx.claims.clone().unwrap().validate(TestClaims {
-            hd: Some(REQUIRED_EMAIL_DOMAIN.to_string()),
-            ..TestClaims::default()
-        })
-    {
-        return false;
+/// The stored document targeted by a mutation, diffed before and after the write
+fn snapshot(mongo: &DataSources, name: &str, id: &ID) -> Option<Document> {
+    let service = mongo.get_mongo_service(name).unwrap();
+    match service.data_source().find_one(id_filter(id), None) {
+        Ok(document) => document,
+        Err(e) => {
+            warn!(
+                "Unable to read {} {} for the audit log: {}",
+                name,
+                id_string(id),
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Records a mutation in the `audit_log` collection. The write has already gone through when
+/// the record can't be stored, so the mutation fails with `AUDIT_FAILED` and the whole record
+/// is logged for it to be recovered.
+fn audit(
+    ctx: &Context,
+    operation: &str,
+    target_id: &ID,
+    related_ids: Vec<String>,
+    before: Option<Document>,
+    after: Option<Document>,
+) -> Result<(), FieldError> {
+    let actor = ctx
+        .principal
+        .identity
+        .clone()
+        .unwrap_or_else(Identity::service);
+    let node_id = Some(actor.node_id());
+    let record = AuditInsert {
+        operation: operation.to_string(),
+        actor,
+        impersonated_by: ctx.principal.impersonated_by.clone(),
+        tenant_id: ctx.principal.tenant.clone(),
+        target_id: id_string(target_id),
+        related_ids,
+        request_id: ctx.request_id.clone(),
+        timestamp: now() as i64,
+        changes: diff_documents(&before, &after),
+    };
+    let logged = serde_json::to_string(&record).unwrap_or_default();
+    let service = ctx.clients.mongo.get_mongo_service("audit_log").unwrap();
+    let result: Result<ID, ServiceError> = service.insert_one(record, node_id);
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            error!(
+                "Unable to record {} of {} in the audit log: {}, record: {}",
+                operation,
+                id_string(target_id),
+                e,
+                logged
+            );
+            Err(FieldError::new(
+                format!(
+                    "{} was applied but could not be recorded in the audit log",
+                    operation
+                ),
+                graphql_value!({ "code": "AUDIT_FAILED" }),
+            ))
+        }
+    }
+}
+
+/// Evicts the cached query results a write to a sample affects
+fn evict(
+    ctx: &Context,
+    write: SampleWrite,
+    id: &ID,
+    before: &Option<Document>,
+    after: &Option<Document>,
+) {
+    let tenant = &ctx.principal.tenant;
+    cache::invalidate(&write_tags(tenant, write, &id_string(id), before, after));
+}
+
+/// The tags of a page of samples, see `cache::list_tags`
+fn connection_tags(ctx: &Context, connection: &SampleConnection, values: bool) -> Vec<Tag> {
+    let ids = connection.edges.iter().map(|edge| id_string(&edge.node.id));
+    list_tags(&ctx.principal.tenant, ids, values)
+}
+
+/// How long results filtered by status at `timestamp` hold: until a sample matching `filter`
+/// changes status, `None` when none will. The clock only tells whole seconds, so a second is
+/// given up to never serve a result past the change.
+fn status_lifespan(
+    ctx: &Context,
+    filter: Option<Document>,
+    timestamp: i64,
+) -> Result<Option<Duration>, FieldError> {
+    let service = ctx.mongo().get_mongo_service("samples").unwrap();
+    let pipeline = next_transition_pipeline(filter.unwrap_or_default(), timestamp);
+    let result = service
+        .data_source()
+        .aggregate(pipeline, None)?
+        .next()
+        .transpose()?;
+    Ok(next_transition(result, timestamp)
+        .map(|next| Duration::from_secs((next - timestamp - 1).max(0) as u64)))
+}
+
+/// Keeps the fuzzy search index in step with a sample after a write, a sample that is gone is
+/// removed. Failures are logged since they only degrade fuzzy search.
+fn reindex(ctx: &Context, id: &ID, after: &Option<Document>) {
+    let index = match &ctx.clients.search_index {
+        Some(index) => index,
+        None => return,
+    };
+    let result = match after.as_ref().and_then(IndexedSample::from_document) {
+        Some(sample) => index.upsert(&[sample]),
+        None => index.remove(&ctx.principal.tenant, &id_string(id)),
+    };
+    if let Err(e) = result {
+        warn!(
+            "Unable to update the search index for {}: {}",
+            id_string(id),
+            e
+        );
+    }
+}
+
+/// Looks up an api key of the principal's tenant
+fn find_api_key(ctx: &Context, id: &ID) -> Result<ApiKey, FieldError> {
+    let service = ctx.clients.mongo.get_mongo_service("api_keys").unwrap();
+    let filter = and_filters(vec![
+        Some(id_filter(id)),
+        Some(tenant_filter(&ctx.principal)),
+    ]);
+    let result: FindResult<ApiKey> = service.find(filter, None, Some(1), None, None, None)?;
+    match result.items.into_iter().next() {
+        Some(api_key) => Ok(api_key),
+        None => Err("Unable to find api key".into()),
+    }
+}
+
+/// Stores a revocation and applies it to the in-memory list straight away
+fn revoke(
+    ctx: &Context,
+    operation: &str,
+    revocation: RevocationInsert,
+) -> Result<Revocation, FieldError> {
+    let author = resolve_author(&ctx.principal, None)?;
+    let service = ctx.clients.mongo.get_mongo_service("revocations").unwrap();
+    let inserted_id: ID = service.insert_one(revocation, Some(author.node_id()))?;
+    let after = snapshot(&ctx.clients.mongo, "revocations", &inserted_id);
+    let revocation = match service.find_one_by_id(inserted_id.clone())? {
+        Some(revocation) => revocation,
+        None => return Err("Unable to retrieve revocation after insert".into()),
+    };
+    // applied before auditing, a stored revocation takes effect whether or not it is audited
+    record_revocation(&revocation);
+    audit(ctx, operation, &inserted_id, vec![], None, after)?;
+    Ok(revocation)
+}
+
+/// Records the author of a change to a sample's embedded values on the sample itself
+fn stamp_updated_by(ctx: &Context, sample_id: &ID, author: &Identity) -> Result<(), FieldError> {
+    let service = ctx.mongo().get_mongo_service("samples").unwrap();
+    service.data_source().update_one(
+        id_filter(sample_id),
+        doc! { "$set": {
+            "updated_by": bson::to_bson(author)?,
+            "impersonated_by": bson::to_bson(&ctx.principal.impersonated_by)?,
+        } },
+        None,
+    )?;
+    Ok(())
+}
+
+/// A date field of samples and how far ahead of now to look on it
+struct UpcomingWindow {
+    field: &'static str,
+    duration: i32,
+}
+
+/// Samples whose date field falls after now and at most `duration` seconds later. These
+/// operational queries depend on the current time and aren't cached.
+fn samples_within(
+    ctx: &Context,
+    window: UpcomingWindow,
+    filter: Option<SampleFilter>,
+    sort: Option<Vec<SampleSort>>,
+    limit: Option<i32>,
+    after: Option<String>,
+    before: Option<String>,
+) -> Result<SampleConnection, FieldError> {
+    if window.duration <= 0 {
+        return Err("`duration` must be a positive number of seconds".into());
+    }
+    let filter = filter
+        .map(|filter| filter.to_document(&ctx.principal))
+        .transpose()?;
+    let sort = match sort {
+        Some(sort) => sort_document(&sort)?,
+        None => doc! { window.field: 1, "_id": 1 },
+    };
+    let timestamp = ctx.clock.now();
+    let upcoming = doc! { window.field: {
+        "$gt": timestamp,
+        "$lte": timestamp + window.duration as i64,
+    } };
+    let filter = and_filters(vec![
+        Some(upcoming),
+        filter,
+        read_scope(&ctx.principal).filter,
+    ]);
+    let service = ctx.mongo().get_mongo_service("samples").unwrap();
+    let result: FindResult<Sample> =
+        service.find(filter, Some(sort), limit, after, before, None)?;
+    Ok(result.into())
+}
+
+/// Recomputes the denormalized value aggregates of a sample after its values changed
+fn refresh_value_aggregates(ctx: &Context, sample_id: &ID) -> Result<(), FieldError> {
+    let service = ctx.mongo().get_mongo_service("samples").unwrap();
+    let sample: Option<Sample> = service.find_one_by_id(sample_id.clone())?;
+    let aggregates = match sample {
+        Some(sample) => sample.value_aggregates(),
+        None => return Ok(()),
+    };
+    service.data_source().update_one(
+        id_filter(sample_id),
+        doc! { "$set": aggregates.to_document()? },
+        None,
+    )?;
+    Ok(())
+}
+
+/// Recomputes the occurrences and the bounding dates of a sample after its windows or dates
+/// changed
+fn refresh_availability(ctx: &Context, sample_id: &ID) -> Result<(), FieldError> {
+    let service = ctx.mongo().get_mongo_service("samples").unwrap();
+    let sample: Sample = match service.find_one_by_id(sample_id.clone())? {
+        Some(sample) => sample,
+        None => return Ok(()),
+    };
+    let availability = Availability::of(
+        sample.windows.as_deref().unwrap_or(&[]),
+        sample.available_date,
+        sample.expiration_date,
+    );
+    service.data_source().update_one(
+        id_filter(sample_id),
+        doc! { "$set": availability.to_document()? },
+        None,
+    )?;
+    Ok(())
+}
+
+/// Replaces the availability windows of a sample after applying `change` to its current ones
+fn change_windows<F>(
+    ctx: &Context,
+    operation: &str,
+    sample_id: ID,
+    related_id: String,
+    change: F,
+) -> Result<Sample, FieldError>
+where
+    F: FnOnce(&mut Vec<AvailabilityWindow>) -> Result<(), FieldError>,
+{
+    authorize_sample(ctx, &sample_id, acl_write_filter(&ctx.principal), "write")?;
+    let author = resolve_author(&ctx.principal, None)?;
+    let service = ctx.mongo().get_mongo_service("samples").unwrap();
+    let before = snapshot(ctx.mongo(), "samples", &sample_id);
+    let sample: Option<Sample> = service.find_one_by_id(sample_id.clone())?;
+    let mut windows = match sample {
+        Some(sample) => sample.windows.unwrap_or_default(),
+        None => return Err("Unable to find item".into()),
+    };
+    change(&mut windows)?;
+    let node_id = Some(author.node_id());
+    let update = SampleWindowsUpdate {
+        windows,
+        updated_by: author,
+        impersonated_by: ctx.principal.impersonated_by.clone(),
+    };
+    let _: Sample = service.update_one(sample_id.clone(), update, node_id)?;
+    refresh_availability(ctx, &sample_id)?;
+    let after = snapshot(ctx.mongo(), "samples", &sample_id);
+    reindex(ctx, &sample_id, &after);
+    evict(ctx, SampleWrite::Updated, &sample_id, &before, &after);
+    audit(ctx, operation, &sample_id, vec![related_id], before, after)?;
+    match service.find_one_by_id(sample_id)? {
+        Some(sample) => Ok(sample),
+        None => Err("Unable to find item".into()),
+    }
+}
+
+/// Checks the principal may change a sample of its tenant under the given ACL restriction.
+/// Samples of other tenants are reported as missing.
+fn authorize_sample(
+    ctx: &Context,
+    sample_id: &ID,
+    restriction: Option<Document>,
+    access: &str,
+) -> Result<(), FieldError> {
+    let service = ctx.mongo().get_mongo_service("samples").unwrap();
+    let collection = service.data_source();
+    let tenant = Some(tenant_filter(&ctx.principal));
+    let restricted = restriction.is_some();
+    let allowed = and_filters(vec![
+        Some(id_filter(sample_id)),
+        tenant.clone(),
+        restriction,
+    ]);
+    if collection.count_documents(allowed, None)? > 0 {
+        return Ok(());
+    }
+    let exists = and_filters(vec![Some(id_filter(sample_id)), tenant]);
+    if restricted && collection.count_documents(exists, None)? > 0 {
+        return Err(no_access(access));
     }
-    return true;
+    Err("Unable to find item".into())
+}
+
+/// Replaces the ACL of a sample after applying `change` to its current entries
+fn change_acl<F>(ctx: &Context, operation: &str, id: ID, change: F) -> Result<Sample, FieldError>
+where
+    F: FnOnce(&mut Vec<AclEntry>),
+{
+    authorize_sample(ctx, &id, acl_owner_filter(&ctx.principal), "owner")?;
+    let author = resolve_author(&ctx.principal, None)?;
+    let service = ctx.mongo().get_mongo_service("samples").unwrap();
+    let before = snapshot(ctx.mongo(), "samples", &id);
+    let sample: Option<Sample> = service.find_one_by_id(id.clone())?;
+    let mut acl = match sample {
+        Some(sample) => sample.acl.unwrap_or_default(),
+        None => return Err("Unable to find item".into()),
+    };
+    change(&mut acl);
+    let node_id = Some(author.node_id());
+    let update = SampleAclUpdate {
+        acl,
+        updated_by: author,
+        impersonated_by: ctx.principal.impersonated_by.clone(),
+    };
+    let sample: Sample = service.update_one(id.clone(), update, node_id)?;
+    let after = snapshot(ctx.mongo(), "samples", &id);
+    reindex(ctx, &id, &after);
+    evict(ctx, SampleWrite::Updated, &id, &before, &after);
+    audit(ctx, operation, &id, vec![], before, after)?;
+    Ok(sample)
 }
 
 #[juniper::object(Context = Context)]
@@ -274,14 +1049,28 @@ impl Mutation {
     // samples
     fn create_sample(
         ctx: &Context,
-        mut new_sample: NewSample,
+        new_sample: NewSample,
         created_by_id: Option<ID>,
     ) -> Result<Sample, FieldError> {
-        if !has_auth(ctx) && *DISABLE_AUTH != 1 {
-            return Err("Unauthorized".into());
-        }
-        let service = ctx.clients.mongo.get_mongo_service("samples").unwrap();
-        let inserted_id: ID = service.insert_one(new_sample, created_by_id)?;
+        authorize(&ctx.principal, "createSample")?;
+        let author = resolve_author(&ctx.principal, created_by_id)?;
+        let service = ctx.mongo().get_mongo_service("samples").unwrap();
+        let node_id = Some(author.node_id());
+        let new_sample = SampleInsert {
+            sample: new_sample,
+            created_by: author.clone(),
+            updated_by: author.clone(),
+            owner_id: author.id,
+            tenant_id: ctx.principal.tenant.clone(),
+            impersonated_by: ctx.principal.impersonated_by.clone(),
+            aggregates: ValueAggregates::of(&[]),
+        };
+        let inserted_id: ID = service.insert_one(new_sample, node_id)?;
+        refresh_availability(ctx, &inserted_id)?;
+        let after = snapshot(ctx.mongo(), "samples", &inserted_id);
+        reindex(ctx, &inserted_id, &after);
+        evict(ctx, SampleWrite::Created, &inserted_id, &None, &after);
+        audit(ctx, "createSample", &inserted_id, vec![], None, after)?;
         let maybe_item = service.find_one_by_id(inserted_id)?;
         match maybe_item {
             Some(item) => Ok(item),
@@ -295,23 +1084,42 @@ impl Mutation {
         update_sample: UpdateSample,
         updated_by_id: Option<ID>,
     ) -> Result<Sample, FieldError> {
-        if !has_auth(ctx) && *DISABLE_AUTH != 1 {
-            return Err("Unauthorized".into());
-        }
         // check authorization first
-        let service = ctx.clients.mongo.get_mongo_service("samples").unwrap();
-        service
-            .update_one(id, update_sample, updated_by_id)
-            .map_err(|e| e.into())
+        authorize(&ctx.principal, "updateSample")?;
+        authorize_sample(ctx, &id, acl_write_filter(&ctx.principal), "write")?;
+        let author = resolve_author(&ctx.principal, updated_by_id)?;
+        let service = ctx.mongo().get_mongo_service("samples").unwrap();
+        let node_id = Some(author.node_id());
+        let update_sample = SampleUpdate {
+            update: update_sample,
+            updated_by: author,
+            impersonated_by: ctx.principal.impersonated_by.clone(),
+        };
+        let before = snapshot(ctx.mongo(), "samples", &id);
+        let _: Sample = service.update_one(id.clone(), update_sample, node_id)?;
+        refresh_availability(ctx, &id)?;
+        let after = snapshot(ctx.mongo(), "samples", &id);
+        reindex(ctx, &id, &after);
+        evict(ctx, SampleWrite::Updated, &id, &before, &after);
+        audit(ctx, "updateSample", &id, vec![], before, after)?;
+        match service.find_one_by_id(id)? {
+            Some(sample) => Ok(sample),
+            None => Err("Unable to find item".into()),
+        }
     }
 
     fn delete_sample(ctx: &Context, id: ID) -> Result<DeleteResponseGQL, FieldError> {
-        if !has_auth(ctx) && *DISABLE_AUTH != 1 {
-            return Err("Unauthorized".into());
-        }
-        let service = ctx.clients.mongo.get_mongo_service("samples").unwrap();
-        match service.delete_one_by_id(id) {
-            Ok(result) => Ok(result.into()),
+        authorize(&ctx.principal, "deleteSample")?;
+        authorize_sample(ctx, &id, acl_owner_filter(&ctx.principal), "owner")?;
+        let service = ctx.mongo().get_mongo_service("samples").unwrap();
+        let before = snapshot(ctx.mongo(), "samples", &id);
+        match service.delete_one_by_id(id.clone()) {
+            Ok(result) => {
+                reindex(ctx, &id, &None);
+                evict(ctx, SampleWrite::Deleted, &id, &before, &None);
+                audit(ctx, "deleteSample", &id, vec![], before, None)?;
+                Ok(result.into())
+            }
             Err(e) => Err(e.into()),
         }
     }
@@ -323,12 +1131,31 @@ impl Mutation {
         new_values: Vec<NewEmbedded>,
         created_by_id: Option<ID>,
     ) -> Result<Sample, FieldError> {
-        if !has_auth(ctx) && *DISABLE_AUTH != 1 {
-            return Err("Unauthorized".into());
-        }
-        let service = ctx.clients.mongo.get_mongo_service("samples").unwrap();
-        let _ids =
-            service.insert_embedded(sample_id.clone(), "values", new_values, created_by_id)?;
+        authorize(&ctx.principal, "addValuesToSample")?;
+        authorize_sample(ctx, &sample_id, acl_write_filter(&ctx.principal), "write")?;
+        let author = resolve_author(&ctx.principal, created_by_id)?;
+        let service = ctx.mongo().get_mongo_service("samples").unwrap();
+        let before = snapshot(ctx.mongo(), "samples", &sample_id);
+        let ids: Vec<ID> = service.insert_embedded(
+            sample_id.clone(),
+            "values",
+            new_values,
+            Some(author.node_id()),
+        )?;
+        stamp_updated_by(ctx, &sample_id, &author)?;
+        refresh_value_aggregates(ctx, &sample_id)?;
+        let after = snapshot(ctx.mongo(), "samples", &sample_id);
+        let related_ids = ids.iter().map(id_string).collect();
+        reindex(ctx, &sample_id, &after);
+        evict(ctx, SampleWrite::Values, &sample_id, &before, &after);
+        audit(
+            ctx,
+            "addValuesToSample",
+            &sample_id,
+            related_ids,
+            before,
+            after,
+        )?;
         let maybe_item = service.find_one_by_id(sample_id)?;
         match maybe_item {
             Some(item) => Ok(item),
@@ -341,12 +1168,27 @@ impl Mutation {
         sample_id: ID,
         embedded_id: ID,
     ) -> Result<DeleteResponseGQL, FieldError> {
-        if !has_auth(ctx) && *DISABLE_AUTH != 1 {
-            return Err("Unauthorized".into());
-        }
-        let service = ctx.clients.mongo.get_mongo_service("samples").unwrap();
-        match service.delete_embedded(sample_id, "values", embedded_id) {
-            Ok(result) => Ok(result.into()),
+        authorize(&ctx.principal, "removeValueFromSample")?;
+        authorize_sample(ctx, &sample_id, acl_write_filter(&ctx.principal), "write")?;
+        let service = ctx.mongo().get_mongo_service("samples").unwrap();
+        let before = snapshot(ctx.mongo(), "samples", &sample_id);
+        match service.delete_embedded(sample_id.clone(), "values", embedded_id.clone()) {
+            Ok(result) => {
+                refresh_value_aggregates(ctx, &sample_id)?;
+                let after = snapshot(ctx.mongo(), "samples", &sample_id);
+                let related_ids = vec![id_string(&embedded_id)];
+                reindex(ctx, &sample_id, &after);
+                evict(ctx, SampleWrite::Values, &sample_id, &before, &after);
+                audit(
+                    ctx,
+                    "removeValueFromSample",
+                    &sample_id,
+                    related_ids,
+                    before,
+                    after,
+                )?;
+                Ok(result.into())
+            }
             Err(e) => Err(e.into()),
         }
     }
@@ -358,19 +1200,250 @@ impl Mutation {
         update_value: UpdateEmbedded,
         updated_by_id: Option<ID>,
     ) -> Result<Sample, FieldError> {
-        if !has_auth(ctx) && *DISABLE_AUTH != 1 {
-            return Err("Unauthorized".into());
+        authorize(&ctx.principal, "updateValueForSample")?;
+        authorize_sample(ctx, &sample_id, acl_write_filter(&ctx.principal), "write")?;
+        let author = resolve_author(&ctx.principal, updated_by_id)?;
+        let service = ctx.mongo().get_mongo_service("samples").unwrap();
+        let before = snapshot(ctx.mongo(), "samples", &sample_id);
+        let related_ids = vec![id_string(&embedded_id)];
+        let mut sample: Sample = service.update_embedded(
+            sample_id.clone(),
+            "values",
+            embedded_id,
+            update_value,
+            Some(author.node_id()),
+        )?;
+        stamp_updated_by(ctx, &sample_id, &author)?;
+        refresh_value_aggregates(ctx, &sample_id)?;
+        let after = snapshot(ctx.mongo(), "samples", &sample_id);
+        reindex(ctx, &sample_id, &after);
+        evict(ctx, SampleWrite::Values, &sample_id, &before, &after);
+        audit(
+            ctx,
+            "updateValueForSample",
+            &sample_id,
+            related_ids,
+            before,
+            after,
+        )?;
+        sample.updated_by = Some(author);
+        sample.impersonated_by = ctx.principal.impersonated_by.clone();
+        Ok(sample)
+    }
+
+    // availability windows
+    fn add_availability_window(
+        ctx: &Context,
+        sample_id: ID,
+        window: NewAvailabilityWindow,
+    ) -> Result<Sample, FieldError> {
+        authorize(&ctx.principal, "addAvailabilityWindow")?;
+        let window = window.into_window(Uuid::new_v4().to_string())?;
+        let window_id = window.id.clone();
+        change_windows(
+            ctx,
+            "addAvailabilityWindow",
+            sample_id,
+            window_id,
+            |windows| {
+                if windows.len() >= MAX_WINDOWS {
+                    return Err(format!(
+                        "Samples can have at most {} availability windows",
+                        MAX_WINDOWS
+                    )
+                    .into());
+                }
+                windows.push(window);
+                Ok(())
+            },
+        )
+    }
+
+    fn remove_availability_window(
+        ctx: &Context,
+        sample_id: ID,
+        window_id: String,
+    ) -> Result<Sample, FieldError> {
+        authorize(&ctx.principal, "removeAvailabilityWindow")?;
+        let related_id = window_id.clone();
+        change_windows(
+            ctx,
+            "removeAvailabilityWindow",
+            sample_id,
+            related_id,
+            |windows| {
+                let count = windows.len();
+                windows.retain(|window| window.id != window_id);
+                if windows.len() == count {
+                    return Err("Unable to find availability window".into());
+                }
+                Ok(())
+            },
+        )
+    }
+
+    // sharing
+    fn share_sample(
+        ctx: &Context,
+        id: ID,
+        principal: String,
+        access: Access,
+    ) -> Result<Sample, FieldError> {
+        authorize(&ctx.principal, "shareSample")?;
+        AclEntry::validate_principal(&principal)?;
+        change_acl(ctx, "shareSample", id, |acl| {
+            acl.retain(|entry| entry.principal != principal);
+            acl.push(AclEntry { principal, access });
+        })
+    }
+
+    fn unshare_sample(ctx: &Context, id: ID, principal: String) -> Result<Sample, FieldError> {
+        authorize(&ctx.principal, "unshareSample")?;
+        change_acl(ctx, "unshareSample", id, |acl| {
+            acl.retain(|entry| entry.principal != principal)
+        })
+    }
+
+    fn transfer_sample_ownership(
+        ctx: &Context,
+        id: ID,
+        owner_id: String,
+    ) -> Result<Sample, FieldError> {
+        authorize(&ctx.principal, "transferSampleOwnership")?;
+        authorize_sample(ctx, &id, acl_owner_filter(&ctx.principal), "owner")?;
+        let author = resolve_author(&ctx.principal, None)?;
+        let service = ctx.mongo().get_mongo_service("samples").unwrap();
+        let node_id = Some(author.node_id());
+        let update = SampleOwnerUpdate {
+            owner_id,
+            updated_by: author,
+            impersonated_by: ctx.principal.impersonated_by.clone(),
+        };
+        let before = snapshot(ctx.mongo(), "samples", &id);
+        let sample: Sample = service.update_one(id.clone(), update, node_id)?;
+        let after = snapshot(ctx.mongo(), "samples", &id);
+        reindex(ctx, &id, &after);
+        evict(ctx, SampleWrite::Updated, &id, &before, &after);
+        audit(ctx, "transferSampleOwnership", &id, vec![], before, after)?;
+        Ok(sample)
+    }
+
+    // api keys
+    fn create_api_key(ctx: &Context, new_api_key: NewApiKey) -> Result<ApiKeySecret, FieldError> {
+        authorize(&ctx.principal, "createApiKey")?;
+        validate_scopes(&ctx.principal, &new_api_key.scopes)?;
+        let author = resolve_author(&ctx.principal, None)?;
+        let service = ctx.clients.mongo.get_mongo_service("api_keys").unwrap();
+        let (secret, prefix, hash) = generate_secret();
+        let api_key = ApiKeyInsert {
+            name: new_api_key.name,
+            prefix,
+            hash,
+            scopes: new_api_key.scopes,
+            expires_at: new_api_key.expires_at.map(|expires| expires.timestamp()),
+            impersonated_by: ctx.principal.impersonated_by.clone(),
+            tenant_id: ctx.principal.tenant.clone(),
+        };
+        let inserted_id: ID = service.insert_one(api_key, Some(author.node_id()))?;
+        let after = snapshot(&ctx.clients.mongo, "api_keys", &inserted_id);
+        audit(ctx, "createApiKey", &inserted_id, vec![], None, after)?;
+        match service.find_one_by_id(inserted_id)? {
+            Some(api_key) => Ok(ApiKeySecret { api_key, secret }),
+            None => Err("Unable to retrieve api key after insert".into()),
         }
-        let service = ctx.clients.mongo.get_mongo_service("samples").unwrap();
-        service
-            .update_embedded(
-                sample_id,
-                "values",
-                embedded_id,
-                update_value,
-                updated_by_id,
-            )
-            .map_err(|e| e.into())
+    }
+
+    fn rotate_api_key(
+        ctx: &Context,
+        id: ID,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<ApiKeySecret, FieldError> {
+        authorize(&ctx.principal, "rotateApiKey")?;
+        let author = resolve_author(&ctx.principal, None)?;
+        let service = ctx.clients.mongo.get_mongo_service("api_keys").unwrap();
+        let existing = find_api_key(ctx, &id)?;
+        if existing.revoked_at.is_some() {
+            return Err("Revoked api keys can not be rotated".into());
+        }
+        validate_scopes(&ctx.principal, &existing.scopes)?;
+        let (secret, prefix, hash) = generate_secret();
+        let rotation = ApiKeyRotation {
+            prefix,
+            hash,
+            rotated_at: now() as i64,
+            expires_at: expires_at.map(|expires| expires.timestamp()),
+            impersonated_by: ctx.principal.impersonated_by.clone(),
+        };
+        let before = snapshot(&ctx.clients.mongo, "api_keys", &id);
+        let api_key: ApiKey = service.update_one(id.clone(), rotation, Some(author.node_id()))?;
+        let after = snapshot(&ctx.clients.mongo, "api_keys", &id);
+        audit(ctx, "rotateApiKey", &id, vec![], before, after)?;
+        Ok(ApiKeySecret { api_key, secret })
+    }
+
+    fn revoke_api_key(ctx: &Context, id: ID) -> Result<ApiKey, FieldError> {
+        authorize(&ctx.principal, "revokeApiKey")?;
+        find_api_key(ctx, &id)?;
+        let author = resolve_author(&ctx.principal, None)?;
+        let service = ctx.clients.mongo.get_mongo_service("api_keys").unwrap();
+        let revocation = ApiKeyRevocation {
+            revoked_at: now() as i64,
+            impersonated_by: ctx.principal.impersonated_by.clone(),
+        };
+        let before = snapshot(&ctx.clients.mongo, "api_keys", &id);
+        let api_key: ApiKey = service.update_one(id.clone(), revocation, Some(author.node_id()))?;
+        let after = snapshot(&ctx.clients.mongo, "api_keys", &id);
+        audit(ctx, "revokeApiKey", &id, vec![], before, after)?;
+        Ok(api_key)
+    }
+
+    // revocations
+    fn revoke_token(
+        ctx: &Context,
+        jti: String,
+        reason: Option<String>,
+    ) -> Result<Revocation, FieldError> {
+        authorize(&ctx.principal, "revokeToken")?;
+        let revocation = RevocationInsert {
+            jti: Some(jti),
+            subject: None,
+            issuer: None,
+            issued_before: None,
+            reason,
+            impersonated_by: ctx.principal.impersonated_by.clone(),
+        };
+        revoke(ctx, "revokeToken", revocation)
+    }
+
+    /// Revokes the tokens of a subject of `issuer` issued before `issuedBefore`, or every token
+    /// when omitted
+    fn revoke_subject(
+        ctx: &Context,
+        issuer: String,
+        subject: String,
+        issued_before: Option<DateTime<Utc>>,
+        reason: Option<String>,
+    ) -> Result<Revocation, FieldError> {
+        authorize(&ctx.principal, "revokeSubject")?;
+        let revocation = RevocationInsert {
+            jti: None,
+            subject: Some(subject),
+            issuer: Some(issuer),
+            issued_before: issued_before.map(|issued| issued.timestamp()),
+            reason,
+            impersonated_by: ctx.principal.impersonated_by.clone(),
+        };
+        revoke(ctx, "revokeSubject", revocation)
+    }
+
+    fn delete_revocation(ctx: &Context, id: ID) -> Result<DeleteResponseGQL, FieldError> {
+        authorize(&ctx.principal, "deleteRevocation")?;
+        let service = ctx.clients.mongo.get_mongo_service("revocations").unwrap();
+        let before = snapshot(&ctx.clients.mongo, "revocations", &id);
+        let result = service.delete_one_by_id(id.clone())?;
+        audit(ctx, "deleteRevocation", &id, vec![], before, None)?;
+        refresh_revocations(ctx.clients.get_ref());
+        Ok(result.into())
     }
 }
 
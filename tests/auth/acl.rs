@@ -0,0 +1,71 @@
+#[cfg(test)]
+mod test {
+    use bson::doc;
+    use {{crate_name}}::auth::{
+        acl_read_filter, acl_write_filter, read_scope, AclEntry, Identity, Principal, Role,
+    };
+    fn editor(id: &str, groups: Vec<&str>) -> Principal {
+        Principal {
+            identity: Some(Identity {
+                id: id.to_string(),
+                email: None,
+                name: None,
+            }),
+            role: Some(Role::Editor),
+            permissions: Role::Editor.permissions(),
+            groups: groups.into_iter().map(|group| group.to_string()).collect(),
+            impersonated_by: None,
+            tenant: None,
+            tenants: vec![],
+        }
+    }
+
+    #[test]
+    fn principals_include_user_and_groups() {
+        let principal = editor("user-1", vec!["support"]);
+        assert_eq!(
+            principal.acl_principals(),
+            vec!["user:user-1".to_string(), "group:support".to_string()]
+        );
+    }
+
+    #[test]
+    fn write_filter_requires_write_access() {
+        let principal = editor("user-1", vec![]);
+        assert_eq!(
+            acl_write_filter(&principal),
+            Some(doc! {
+                "$or": [
+                    { "owner_id": { "$in": ["user-1"] } },
+                    { "acl": { "$elemMatch": {
+                        "principal": { "$in": ["user:user-1"] },
+                        "access": "Write",
+                    } } },
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn admins_bypass_acls() {
+        let mut admin = editor("user-1", vec![]);
+        admin.permissions = Role::Admin.permissions();
+        assert_eq!(acl_read_filter(&admin), None);
+        assert_eq!(read_scope(&admin).filter, Some(doc! { "tenant_id": null }));
+    }
+
+    #[test]
+    fn scope_keys_differ_per_caller() {
+        let alice = read_scope(&editor("alice", vec![]));
+        let bob = read_scope(&editor("bob", vec![]));
+        assert_ne!(alice.key, bob.key);
+    }
+
+    #[test]
+    fn validates_acl_principals() {
+        assert!(AclEntry::validate_principal("user:alice").is_ok());
+        assert!(AclEntry::validate_principal("group:support").is_ok());
+        assert!(AclEntry::validate_principal("alice").is_err());
+        assert!(AclEntry::validate_principal("group:").is_err());
+    }
+}
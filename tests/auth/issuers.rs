@@ -0,0 +1,112 @@
+#[cfg(test)]
+mod test {
+    use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+    use serde_json::{json, Value};
+    use {{crate_name}}::auth::{ClaimRules, IssuerConfig, Issuers, JwksSource};
+
+    async fn issuers() -> Issuers {
+        let issuers = Issuers::new(vec![IssuerConfig {
+            issuer: "https://idp.example.com".to_string(),
+            aliases: vec![],
+            jwks: vec![JwksSource::File {
+                path: "./tests/mock/jwks.json".to_string(),
+            }],
+            audiences: vec!["sample-service".to_string()],
+            rules: ClaimRules {
+                domain: Some("example.com".to_string()),
+                groups: vec!["samples".to_string()],
+                ..ClaimRules::default()
+            },
+        }]);
+        issuers.refresh().await;
+        issuers
+    }
+
+    fn token(claims: Value) -> String {
+        let key = EncodingKey::from_rsa_pem(include_bytes!("../mock/test_key.pem")).unwrap();
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some("test-key".to_string());
+        encode(&header, &claims, &key).unwrap()
+    }
+
+    fn claims(iss: &str, aud: &str, email: &str) -> Value {
+        json!({
+            "iss": iss,
+            "aud": aud,
+            "sub": "user-1",
+            "email": email,
+            "email_verified": true,
+            "exp": 4102444800u64,
+            "groups": ["samples", "other"],
+        })
+    }
+
+    #[actix_rt::test]
+    async fn validates_trusted_issuer() {
+        let issuers = issuers().await;
+        let token = token(claims(
+            "https://idp.example.com",
+            "sample-service",
+            "user@example.com",
+        ));
+
+        let claims = issuers.validate(&token).unwrap();
+        assert_eq!(claims.sub, "user-1");
+        assert_eq!(claims.email, Some("user@example.com".to_string()));
+        assert_eq!(claims.extra["groups"], json!(["samples", "other"]));
+    }
+
+    #[actix_rt::test]
+    async fn rejects_untrusted_issuer() {
+        let issuers = issuers().await;
+        let token = token(claims(
+            "https://evil.example.com",
+            "sample-service",
+            "user@example.com",
+        ));
+
+        assert_eq!(
+            issuers.validate(&token).unwrap_err(),
+            "Untrusted issuer https://evil.example.com"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn rejects_wrong_audience_and_domain() {
+        let issuers = issuers().await;
+        let wrong_audience = token(claims(
+            "https://idp.example.com",
+            "another-service",
+            "user@example.com",
+        ));
+        assert!(issuers.validate(&wrong_audience).is_err());
+
+        let wrong_domain = token(claims(
+            "https://idp.example.com",
+            "sample-service",
+            "user@gmail.com",
+        ));
+        assert_eq!(
+            issuers.validate(&wrong_domain).unwrap_err(),
+            "Token is not from the example.com domain"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn rejects_unverified_email_in_domain() {
+        let issuers = issuers().await;
+        let mut unverified = claims(
+            "https://idp.example.com",
+            "sample-service",
+            "user@example.com",
+        );
+        unverified["email_verified"] = json!(false);
+        assert_eq!(
+            issuers.validate(&token(unverified.clone())).unwrap_err(),
+            "Token email is not verified"
+        );
+
+        unverified.as_object_mut().unwrap().remove("email_verified");
+        assert!(issuers.validate(&token(unverified)).is_err());
+    }
+}
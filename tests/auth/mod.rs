@@ -0,0 +1,6 @@
+mod acl;
+mod issuers;
+mod revocations;
+mod roles;
+mod scope;
+mod tenancy;
@@ -0,0 +1,61 @@
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use {{crate_name}}::auth::{Claims, RevocationList};
+
+    const ISSUER: &str = "https://idp.example.com";
+
+    fn claims(sub: &str, jti: Option<&str>, iat: Option<u64>) -> Claims {
+        Claims {
+            iss: ISSUER.to_string(),
+            sub: sub.to_string(),
+            exp: 4102444800,
+            iat,
+            jti: jti.map(|jti| jti.to_string()),
+            email: None,
+            name: None,
+            hd: None,
+            extra: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn revokes_single_token() {
+        let mut list = RevocationList::default();
+        list.revoke_token("token-1");
+        assert!(list.is_revoked(&claims("alice", Some("token-1"), Some(100))));
+        assert!(!list.is_revoked(&claims("alice", Some("token-2"), Some(100))));
+    }
+
+    #[test]
+    fn revokes_tokens_issued_before() {
+        let mut list = RevocationList::default();
+        list.revoke_subject(Some(ISSUER), "alice", Some(1000));
+        assert!(list.is_revoked(&claims("alice", None, Some(999))));
+        assert!(list.is_revoked(&claims("alice", None, None)));
+        assert!(!list.is_revoked(&claims("alice", None, Some(1000))));
+        assert!(!list.is_revoked(&claims("bob", None, Some(999))));
+
+        list.revoke_subject(Some(ISSUER), "alice", Some(2000));
+        assert!(list.is_revoked(&claims("alice", None, Some(1500))));
+    }
+
+    #[test]
+    fn revokes_every_token_of_subject() {
+        let mut list = RevocationList::default();
+        list.revoke_subject(Some(ISSUER), "alice", None);
+        list.revoke_subject(Some(ISSUER), "alice", Some(1000));
+        assert!(list.is_revoked(&claims("alice", None, Some(5000))));
+    }
+
+    #[test]
+    fn subjects_are_scoped_to_their_issuer() {
+        let mut list = RevocationList::default();
+        list.revoke_subject(Some("https://other.example.com"), "alice", None);
+        assert!(!list.is_revoked(&claims("alice", None, Some(5000))));
+
+        // revocations stored before issuers were recorded apply to every issuer
+        list.revoke_subject(None, "bob", None);
+        assert!(list.is_revoked(&claims("bob", None, Some(5000))));
+    }
+}
@@ -0,0 +1,47 @@
+#[cfg(test)]
+mod test {
+    use {{crate_name}}::auth::{authorize, Identity, Permission, Principal, Role};
+
+    fn principal_with_role(role: Role) -> Principal {
+        Principal {
+            identity: Some(Identity {
+                id: "user-1".to_string(),
+                email: Some("user@gmail.com".to_string()),
+                name: None,
+            }),
+            role: Some(role),
+            permissions: role.permissions(),
+            groups: vec![],
+            impersonated_by: None,
+            tenant: None,
+            tenants: vec![],
+        }
+    }
+
+    #[test]
+    fn viewer_can_only_read() {
+        let viewer = principal_with_role(Role::Viewer);
+        assert!(authorize(&viewer, "allSamples").is_ok());
+        assert!(authorize(&viewer, "createSample").is_err());
+        assert!(!viewer.has_permission(Permission::WriteSamples));
+    }
+
+    #[test]
+    fn only_admin_can_delete() {
+        let editor = principal_with_role(Role::Editor);
+        assert!(authorize(&editor, "updateSample").is_ok());
+        let error = authorize(&editor, "deleteSample").unwrap_err();
+        assert_eq!(error.message(), "Forbidden: requires samples:delete");
+
+        let admin = principal_with_role(Role::Admin);
+        assert!(authorize(&admin, "deleteSample").is_ok());
+    }
+
+    #[test]
+    fn anonymous_is_unauthenticated() {
+        let anonymous = Principal::anonymous();
+        assert!(authorize(&anonymous, "sampleById").is_ok());
+        let error = authorize(&anonymous, "createSample").unwrap_err();
+        assert_eq!(error.message(), "Unauthorized");
+    }
+}
@@ -0,0 +1,47 @@
+#[cfg(test)]
+mod test {
+    use bson::doc;
+    use {{crate_name}}::auth::{read_scope, Permission, Principal, Role};
+
+    #[test]
+    fn anonymous_only_reads_published_samples() {
+        let scope = read_scope(&Principal::anonymous());
+        assert_eq!(
+            scope.filter,
+            Some(doc! { "$and": [
+                { "tenant_id": null },
+                { "published": { "$ne": false } },
+                { "$or": [{ "owner_id": { "$in": [] } }, { "acl.principal": { "$in": [] } }] },
+            ] })
+        );
+        assert_eq!(scope.key, "-/published:");
+    }
+
+    #[test]
+    fn editors_read_unpublished_samples() {
+        let editor = Principal {
+            identity: None,
+            role: Some(Role::Editor),
+            permissions: Role::Editor.permissions(),
+            groups: vec![],
+            impersonated_by: None,
+            tenant: None,
+            tenants: vec![],
+        };
+        assert!(editor.has_permission(Permission::ReadAudit));
+        let scope = read_scope(&editor);
+        assert_eq!(
+            scope.filter,
+            Some(doc! { "$and": [
+                { "tenant_id": null },
+                { "$or": [{ "owner_id": { "$in": [] } }, { "acl.principal": { "$in": [] } }] },
+            ] })
+        );
+        assert_ne!(scope.key, read_scope(&Principal::anonymous()).key);
+    }
+
+    #[test]
+    fn viewers_can_not_read_audit_fields() {
+        assert!(!Role::Viewer.permissions().contains(&Permission::ReadAudit));
+    }
+}
@@ -0,0 +1,85 @@
+#[cfg(test)]
+mod test {
+    use bson::doc;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use {{crate_name}}::auth::{
+        resolve_tenant, tenant_filter, validate_tenant, Claims, Permission, Principal, TenantHeader,
+    };
+
+    fn claims(tenant: Option<&str>) -> Option<Claims> {
+        let mut extra = HashMap::new();
+        if let Some(tenant) = tenant {
+            extra.insert("tenant".to_string(), json!(tenant));
+        }
+        Some(Claims {
+            iss: "https://idp.example.com".to_string(),
+            sub: "alice".to_string(),
+            exp: 4102444800,
+            iat: None,
+            jti: None,
+            email: None,
+            name: None,
+            hd: None,
+            extra,
+        })
+    }
+
+    fn header(tenant: &str) -> Option<TenantHeader> {
+        Some(TenantHeader(tenant.to_string()))
+    }
+
+    #[test]
+    fn claim_takes_precedence() {
+        let principal = Principal::anonymous();
+        assert_eq!(
+            resolve_tenant(&principal, &claims(Some("unit-a")), &None),
+            Ok(Some("unit-a".to_string()))
+        );
+        assert_eq!(
+            resolve_tenant(&principal, &claims(Some("unit-a")), &header("unit-a")),
+            Ok(Some("unit-a".to_string()))
+        );
+        assert!(resolve_tenant(&principal, &claims(Some("unit-a")), &header("unit-b")).is_err());
+    }
+
+    #[test]
+    fn header_requires_membership() {
+        let mut principal = Principal::anonymous();
+        assert!(resolve_tenant(&principal, &claims(None), &header("unit-b")).is_err());
+        assert_eq!(resolve_tenant(&principal, &None, &None), Ok(None));
+
+        principal.tenants = vec!["unit-b".to_string()];
+        assert_eq!(
+            resolve_tenant(&principal, &claims(Some("unit-a")), &header("unit-b")),
+            Ok(Some("unit-b".to_string()))
+        );
+        assert!(resolve_tenant(&principal, &claims(None), &header("unit-c")).is_err());
+    }
+
+    #[test]
+    fn header_picks_any_tenant_with_permission() {
+        let mut principal = Principal::anonymous();
+        principal.permissions.insert(Permission::AccessAllTenants);
+        assert_eq!(
+            resolve_tenant(&principal, &claims(None), &header("unit-c")),
+            Ok(Some("unit-c".to_string()))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_tenants() {
+        assert!(validate_tenant("unit_a-1").is_ok());
+        assert!(validate_tenant("").is_err());
+        assert!(validate_tenant("unit.a").is_err());
+        assert!(validate_tenant("a/b").is_err());
+    }
+
+    #[test]
+    fn filters_by_tenant() {
+        let mut principal = Principal::anonymous();
+        assert_eq!(tenant_filter(&principal), doc! { "tenant_id": null });
+        principal.tenant = Some("unit-a".to_string());
+        assert_eq!(tenant_filter(&principal), doc! { "tenant_id": "unit-a" });
+    }
+}
@@ -0,0 +1,65 @@
+#[cfg(test)]
+mod test {
+    use bson::{doc, oid::ObjectId};
+    use {{crate_name}}::cache::changes::change_tags;
+    use {{crate_name}}::cache::Tag;
+
+    fn tenant() -> Option<String> {
+        Some("acme".to_string())
+    }
+
+    #[test]
+    fn inserts_and_updates_evict_the_sample_and_its_name() {
+        let oid = ObjectId::with_string("5f192d9900e0306000d188e1").unwrap();
+        let sample = doc! { "_id": oid.clone(), "name": "Sample 1", "tenant_id": "acme" };
+        for operation in &["insert", "update", "replace"] {
+            let event = doc! {
+                "operationType": *operation,
+                "ns": { "db": "samples", "coll": "samples" },
+                "documentKey": { "_id": oid.clone() },
+                "fullDocument": sample.clone(),
+            };
+            let tags = change_tags(&event, "samples").unwrap();
+            assert!(tags.contains(&Tag::Sample(
+                tenant(),
+                "5f192d9900e0306000d188e1".to_string()
+            )));
+            assert!(tags.contains(&Tag::Samples(tenant())));
+            assert!(tags.contains(&Tag::Name(tenant(), "Sample 1".to_string())));
+        }
+    }
+
+    #[test]
+    fn deletes_in_tenant_databases_evict_the_sample() {
+        let oid = ObjectId::with_string("5f192d9900e0306000d188e1").unwrap();
+        let delete = doc! {
+            "operationType": "delete",
+            "ns": { "db": "samples-acme", "coll": "samples" },
+            "documentKey": { "_id": oid },
+        };
+        let tags = change_tags(&delete, "samples").unwrap();
+        assert_eq!(
+            tags,
+            vec![
+                Tag::Sample(tenant(), "5f192d9900e0306000d188e1".to_string()),
+                Tag::Samples(tenant()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_changes_evict_everything() {
+        let oid = ObjectId::with_string("5f192d9900e0306000d188e1").unwrap();
+        // the shared database holds samples of every tenant
+        let delete = doc! {
+            "operationType": "delete",
+            "ns": { "db": "samples", "coll": "samples" },
+            "documentKey": { "_id": oid.clone() },
+        };
+        assert!(change_tags(&delete, "samples").is_none());
+        // the sample was deleted before the update was looked up
+        let update = doc! { "operationType": "update", "documentKey": { "_id": oid } };
+        assert!(change_tags(&update, "samples").is_none());
+        assert!(change_tags(&doc! { "operationType": "dropDatabase" }, "samples").is_none());
+    }
+}
@@ -0,0 +1,180 @@
+mod changes;
+mod shared;
+
+#[cfg(test)]
+mod test {
+    use bson::doc;
+    use juniper::FieldError;
+    use std::cell::Cell;
+    use std::thread::sleep;
+    use std::time::Duration;
+    use {{crate_name}}::cache::{
+        list_tags, uses_values, write_tags, QueryCache, SampleWrite, Tag,
+    };
+
+    fn tenant() -> Option<String> {
+        Some("acme".to_string())
+    }
+
+    fn cached(cache: &QueryCache<i32>, no_cache: bool, value: i32, tags: Vec<Tag>) -> i32 {
+        cache
+            .get_or_build(no_cache, "key".to_string(), || Ok((value, tags)))
+            .unwrap()
+    }
+
+    #[test]
+    fn results_are_reused_until_a_tag_is_invalidated() {
+        let cache = QueryCache::with_settings("test", 60, 10, true);
+        let tags = list_tags(&tenant(), vec!["1".to_string()], false);
+        assert_eq!(cached(&cache, false, 1, tags.clone()), 1);
+        assert_eq!(cached(&cache, false, 2, tags.clone()), 1);
+
+        cache.invalidate(&[Tag::Values(tenant())]);
+        assert_eq!(cached(&cache, false, 2, tags.clone()), 1);
+
+        cache.invalidate(&[Tag::Sample(tenant(), "1".to_string())]);
+        assert!(cache.is_empty());
+        assert_eq!(cached(&cache, false, 2, tags), 2);
+    }
+
+    #[test]
+    fn tags_are_scoped_to_tenants() {
+        let cache = QueryCache::with_settings("test", 60, 10, true);
+        cached(&cache, false, 1, vec![Tag::Samples(tenant())]);
+        cache.invalidate(&[Tag::Samples(Some("other".to_string()))]);
+        assert_eq!(cache.len(), 1);
+        cache.invalidate(&[Tag::Samples(tenant())]);
+        assert_eq!(cache.len(), 0);
+    }
+
+    #[test]
+    fn no_cache_rebuilds_and_stores_the_result() {
+        let cache = QueryCache::with_settings("test", 60, 10, true);
+        cached(&cache, false, 1, vec![]);
+        assert_eq!(cached(&cache, true, 2, vec![]), 2);
+        assert_eq!(cached(&cache, false, 3, vec![]), 2);
+    }
+
+    #[test]
+    fn disabled_caches_always_build() {
+        for cache in vec![
+            QueryCache::with_settings("test", 0, 10, true),
+            QueryCache::with_settings("test", 60, 0, true),
+            QueryCache::with_settings("test", 60, 10, false),
+        ] {
+            assert_eq!(cached(&cache, false, 1, vec![]), 1);
+            assert_eq!(cached(&cache, false, 2, vec![]), 2);
+            assert!(cache.is_empty());
+        }
+    }
+
+    #[test]
+    fn errors_are_not_cached() {
+        let cache: QueryCache<i32> = QueryCache::with_settings("test", 60, 10, true);
+        let builds = Cell::new(0);
+        for _ in 0..2 {
+            let result = cache.get_or_build(false, "key".to_string(), || {
+                builds.set(builds.get() + 1);
+                Err(FieldError::from("failed"))
+            });
+            assert!(result.is_err());
+        }
+        assert_eq!(builds.get(), 2);
+    }
+
+    #[test]
+    fn results_built_during_an_invalidation_are_not_stored() {
+        let cache = QueryCache::with_settings("test", 60, 10, true);
+        let value = cache
+            .get_or_build(false, "key".to_string(), || {
+                cache.invalidate(&[Tag::Samples(tenant())]);
+                Ok((1, vec![Tag::Samples(tenant())]))
+            })
+            .unwrap();
+        assert_eq!(value, 1);
+        assert!(cache.is_empty());
+    }
+
+    fn expiring(cache: &QueryCache<i32>, value: i32, valid_for: Duration) -> i32 {
+        cache
+            .get_or_build_expiring(false, "key".to_string(), || {
+                Ok((value, vec![], Some(valid_for)))
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn results_expire_when_they_stop_holding() {
+        let cache = QueryCache::with_settings("test", 60, 10, true);
+        assert_eq!(expiring(&cache, 1, Duration::from_millis(50)), 1);
+        assert_eq!(expiring(&cache, 2, Duration::from_millis(50)), 1);
+        sleep(Duration::from_millis(100));
+        assert_eq!(expiring(&cache, 3, Duration::from_secs(3600)), 3);
+        assert_eq!(expiring(&cache, 4, Duration::from_secs(3600)), 3);
+    }
+
+    #[test]
+    fn results_holding_for_no_time_are_not_stored() {
+        let cache = QueryCache::with_settings("test", 60, 10, true);
+        assert_eq!(expiring(&cache, 1, Duration::from_secs(0)), 1);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn capacity_bounds_the_entries() {
+        let cache = QueryCache::with_settings("test", 60, 2, true);
+        for key in &["a", "b", "c"] {
+            cache
+                .get_or_build(false, key.to_string(), || Ok((1, vec![])))
+                .unwrap();
+        }
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn updates_invalidate_membership_and_both_names() {
+        let before = Some(doc! { "name": "Old" });
+        let after = Some(doc! { "name": "New" });
+        assert_eq!(
+            write_tags(&tenant(), SampleWrite::Updated, "1", &before, &after),
+            vec![
+                Tag::Sample(tenant(), "1".to_string()),
+                Tag::Samples(tenant()),
+                Tag::Name(tenant(), "Old".to_string()),
+                Tag::Name(tenant(), "New".to_string()),
+            ]
+        );
+        assert_eq!(
+            write_tags(&tenant(), SampleWrite::Deleted, "1", &before, &None),
+            vec![
+                Tag::Sample(tenant(), "1".to_string()),
+                Tag::Samples(tenant()),
+                Tag::Name(tenant(), "Old".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn value_writes_leave_membership_alone() {
+        let sample = Some(doc! { "name": "Sample" });
+        assert_eq!(
+            write_tags(&tenant(), SampleWrite::Values, "1", &sample, &sample),
+            vec![
+                Tag::Sample(tenant(), "1".to_string()),
+                Tag::Values(tenant())
+            ]
+        );
+    }
+
+    #[test]
+    fn filters_on_values_are_detected() {
+        assert!(uses_values(&doc! { "min_value": { "$gt": 0.5 } }));
+        assert!(uses_values(
+            &doc! { "$and": [{ "name": "One" }, { "values.value": 1 }] }
+        ));
+        assert!(uses_values(&doc! { "avg_value": -1 }));
+        assert!(!uses_values(
+            &doc! { "name": "values", "available_date": 1 }
+        ));
+    }
+}
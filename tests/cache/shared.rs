@@ -0,0 +1,93 @@
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "redis-cache")]
+    #[test]
+    fn replicas_share_results_and_invalidations() {
+        use std::sync::Arc;
+        use std::thread::sleep;
+        use std::time::Duration;
+        use uuid::Uuid;
+        use {{crate_name}}::cache::memory::MemoryBackend;
+        use {{crate_name}}::cache::shared::{SharedBackend, SharedCache};
+        use {{crate_name}}::cache::{QueryCache, Tag};
+
+        let url = std::env::var("CACHE_REDIS_URL").unwrap_or("redis://127.0.0.1:6379/".to_string());
+        let prefix = format!("test-{}:", Uuid::new_v4());
+        let replica = || {
+            let shared = Arc::new(SharedCache::connect(&url, &prefix, 60, 4).unwrap());
+            let near = MemoryBackend::new(Duration::from_secs(60), 10);
+            let backend = SharedBackend::new("test", near, shared.clone());
+            let cache: QueryCache<i32> =
+                QueryCache::with_settings("test", 60, 10, true).with_backend(Box::new(backend));
+            (shared, Arc::new(cache))
+        };
+        let (shared_a, cache_a) = replica();
+        let (shared_b, cache_b) = replica();
+        let subscribed = cache_b.clone();
+        shared_b.subscribe(move |tags| match tags {
+            Some(tags) => subscribed.invalidate(tags),
+            None => subscribed.clear(),
+        });
+        sleep(Duration::from_millis(200));
+
+        let tags = vec![Tag::Sample(None, "1".to_string())];
+        let build = |value: i32| {
+            let tags = tags.clone();
+            move || Ok((value, tags))
+        };
+        assert_eq!(
+            cache_a
+                .get_or_build(false, "key".to_string(), build(1))
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            cache_b
+                .get_or_build(false, "key".to_string(), build(2))
+                .unwrap(),
+            1
+        );
+        assert_eq!(cache_b.len(), 1);
+
+        // a write through replica a evicts the shared entry and the near cache of replica b
+        cache_a.invalidate(&tags);
+        shared_a.invalidate(&tags);
+        sleep(Duration::from_millis(200));
+        assert!(cache_b.is_empty());
+        assert_eq!(
+            cache_b
+                .get_or_build(false, "key".to_string(), build(3))
+                .unwrap(),
+            3
+        );
+        assert_eq!(
+            cache_a
+                .get_or_build(false, "key".to_string(), build(4))
+                .unwrap(),
+            3
+        );
+    }
+
+    #[cfg(feature = "redis-cache")]
+    #[test]
+    fn only_invalidations_of_their_tags_reject_results_built_meanwhile() {
+        use uuid::Uuid;
+        use {{crate_name}}::cache::shared::SharedCache;
+        use {{crate_name}}::cache::Tag;
+
+        let url = std::env::var("CACHE_REDIS_URL").unwrap_or("redis://127.0.0.1:6379/".to_string());
+        let prefix = format!("test-{}:", Uuid::new_v4());
+        let shared = SharedCache::connect(&url, &prefix, 60, 4).unwrap();
+        let acme = vec![Tag::Samples(Some("acme".to_string()))];
+
+        let generation = shared.generation().unwrap();
+        shared.invalidate(&[Tag::Samples(Some("other".to_string()))]);
+        shared.put(generation, "test", "fresh", b"1", &acme, None);
+        assert!(shared.get("test", "fresh").is_some());
+
+        let generation = shared.generation().unwrap();
+        shared.invalidate(&acme);
+        shared.put(generation, "test", "stale", b"2", &acme, None);
+        assert!(shared.get("test", "stale").is_none());
+    }
+}
@@ -0,0 +1,115 @@
+extern crate {{crate_name}};
+
+// the query caches are configured once per process, requests served through them are tested in
+// a binary of their own
+#[allow(dead_code)]
+mod utils;
+
+#[cfg(test)]
+mod test {
+    use crate::utils;
+
+    use actix_web::{test, App};
+    use {{crate_name}}::routes::app_routes;
+    {% raw %}
+    const COUNTS: utils::GqlQuery = utils::GqlQuery {
+        operation_name: "counts",
+        query: r#"
+            query counts {
+                allSamples(limit: 1) {
+                    totalCount
+                }
+                sampleByNames(names: ["Cached Sample"]) {
+                    totalCount
+                }
+            }"#,
+    };
+
+    fn total_counts(resp: &[u8]) -> (i64, i64) {
+        let json: serde_json::Value = serde_json::from_slice(resp).unwrap();
+        (
+            json["data"]["allSamples"]["totalCount"].as_i64().unwrap(),
+            json["data"]["sampleByNames"]["totalCount"].as_i64().unwrap(),
+        )
+    }
+
+    #[actix_rt::test]
+    async fn writes_evict_cached_results() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_cached_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .set_json(&COUNTS)
+            .uri("/test_path/graphql")
+            .to_request();
+        let resp = test::read_response(&mut app, req).await;
+        let (all, named) = total_counts(&resp);
+        assert_eq!(named, 0);
+
+        // served from the cache
+        let req = test::TestRequest::post()
+            .set_json(&COUNTS)
+            .uri("/test_path/graphql")
+            .to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(total_counts(&resp), (all, named));
+
+        let query = utils::GqlQuery {
+            operation_name: "createSample",
+            query: r#"
+                mutation createSample {
+                    createSample(newSample: { name: "Cached Sample" }) {
+                        id
+                    }
+                }"#,
+        };
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        let id = json["data"]["createSample"]["id"].clone();
+
+        let req = test::TestRequest::post()
+            .set_json(&COUNTS)
+            .uri("/test_path/graphql")
+            .to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(total_counts(&resp), (all + 1, 1));
+
+        let query = utils::GqlQuery {
+            operation_name: "deleteSample",
+            query: &format!(
+                r#"
+                mutation deleteSample {{
+                    deleteSample(id: {id}) {{
+                        success
+                    }}
+                }}"#,
+                id = id
+            ),
+        };
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        assert_eq!(json["data"]["deleteSample"]["success"], true);
+
+        let req = test::TestRequest::post()
+            .set_json(&COUNTS)
+            .uri("/test_path/graphql")
+            .to_request();
+        let resp = test::read_response(&mut app, req).await;
+        assert_eq!(total_counts(&resp), (all, 0));
+    }{% endraw %}
+}
@@ -0,0 +1,57 @@
+#[cfg(test)]
+mod test {
+    use bson::{doc, oid::ObjectId};
+    use {{crate_name}}::db::search_index::IndexedSample;
+
+    #[test]
+    fn samples_are_read_from_documents() {
+        let oid = ObjectId::with_string("5f192d9900e0306000d188e1").unwrap();
+        let document = doc! {
+            "_id": oid,
+            "name": "Sample 1",
+            "description": "This is the first sample - ACTIVE",
+            "tenant_id": "acme",
+        };
+        let sample = IndexedSample::from_document(&document).unwrap();
+        assert_eq!(sample.id, "5f192d9900e0306000d188e1");
+        assert_eq!(sample.tenant_id, Some("acme".to_string()));
+        assert_eq!(sample.name, "Sample 1");
+        assert!(IndexedSample::from_document(&doc! { "name": "no id" }).is_none());
+    }
+
+    #[cfg(feature = "fuzzy-search")]
+    #[test]
+    fn search_tolerates_typos_and_prefixes() {
+        use {{crate_name}}::db::search_index::SearchIndex;
+
+        let dir = std::env::temp_dir().join(format!("search-index-{}", std::process::id()));
+        let index = SearchIndex::open(dir.to_str().unwrap()).unwrap();
+        let sample = |id: &str, tenant_id: Option<&str>, name: &str| IndexedSample {
+            id: id.to_string(),
+            tenant_id: tenant_id.map(|tenant| tenant.to_string()),
+            name: name.to_string(),
+            description: Some("This is a sample".to_string()),
+        };
+        index
+            .upsert(&[
+                sample("1", None, "Strawberry"),
+                sample("2", None, "Blueberry"),
+                sample("3", Some("acme"), "Strawberry"),
+            ])
+            .unwrap();
+
+        assert_eq!(index.search(&None, "strawbery", 0, 10).unwrap(), vec!["1"]);
+        assert_eq!(index.search(&None, "blue", 0, 10).unwrap(), vec!["2"]);
+        assert!(index.search(&None, "blue", 1, 10).unwrap().is_empty());
+        assert_eq!(
+            index
+                .search(&Some("acme".to_string()), "strawberry", 0, 10)
+                .unwrap(),
+            vec!["3"]
+        );
+
+        index.remove(&None, "1").unwrap();
+        assert!(index.search(&None, "strawberry", 0, 10).unwrap().is_empty());
+        let _result = std::fs::remove_dir_all(dir);
+    }
+}
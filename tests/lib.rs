@@ -1,5 +1,9 @@
 extern crate {{crate_name}};
 
+mod auth;
+mod cache;
+mod db;
+mod models;
 mod routes;
 mod schema;
 mod utils;
@@ -0,0 +1,142 @@
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+    use {{crate_name}}::models::{
+        Availability, AvailabilityWindow, Frequency, NewAvailabilityWindow, Occurrence, Recurrence,
+        MATERIALIZATION_HORIZON, MAX_OCCURRENCES,
+    };
+
+    fn window(start: i64, end: Option<i64>, rule: Option<&str>) -> AvailabilityWindow {
+        AvailabilityWindow {
+            id: "w".to_string(),
+            start,
+            end,
+            rule: rule.map(|rule| rule.to_string()),
+        }
+    }
+
+    #[test]
+    fn parses_rules() {
+        assert_eq!(
+            Recurrence::parse("RRULE:FREQ=WEEKLY;INTERVAL=2;COUNT=3"),
+            Ok(Recurrence {
+                frequency: Frequency::Weekly,
+                interval: 2,
+                count: Some(3),
+                until: None,
+            })
+        );
+        assert_eq!(
+            Recurrence::parse("FREQ=YEARLY;UNTIL=20300101T000000Z")
+                .unwrap()
+                .until,
+            Some(Utc.ymd(2030, 1, 1).and_hms(0, 0, 0).timestamp())
+        );
+        assert!(Recurrence::parse("INTERVAL=2").is_err());
+        assert!(Recurrence::parse("FREQ=HOURLY").is_err());
+        assert!(Recurrence::parse("FREQ=DAILY;BYDAY=MO").is_err());
+        assert!(Recurrence::parse("FREQ=DAILY;COUNT=0").is_err());
+    }
+
+    #[test]
+    fn expands_recurring_windows() {
+        let start = Utc.ymd(2020, 1, 31).and_hms(0, 0, 0).timestamp();
+        let end = Utc.ymd(2020, 2, 1).and_hms(0, 0, 0).timestamp();
+        let occurrences = window(start, Some(end), Some("FREQ=MONTHLY;COUNT=2")).occurrences();
+        assert_eq!(
+            occurrences,
+            vec![
+                Occurrence {
+                    start,
+                    end: Some(end),
+                },
+                Occurrence {
+                    start: Utc.ymd(2020, 2, 29).and_hms(0, 0, 0).timestamp(),
+                    end: Some(Utc.ymd(2020, 3, 1).and_hms(0, 0, 0).timestamp()),
+                },
+            ]
+        );
+        let daily = window(0, Some(10), Some("FREQ=DAILY;UNTIL=19700103T000000Z"));
+        assert_eq!(daily.occurrences().len(), 3);
+        let unbounded = window(0, Some(10), Some("FREQ=DAILY"));
+        assert_eq!(unbounded.occurrences().len(), MAX_OCCURRENCES);
+    }
+
+    #[test]
+    fn stops_at_the_horizon() {
+        let start = Utc.ymd(2020, 1, 31).and_hms(0, 0, 0).timestamp();
+        let sparse = window(start, Some(start + 10), Some("FREQ=YEARLY;INTERVAL=300"));
+        assert_eq!(sparse.occurrences().len(), 1);
+        let yearly = window(start, Some(start + 10), Some("FREQ=YEARLY"));
+        let occurrences = yearly.occurrences();
+        assert_eq!(occurrences.len(), 101);
+        assert!(occurrences
+            .iter()
+            .all(|occurrence| occurrence.start <= start + MATERIALIZATION_HORIZON));
+        let until = window(
+            0,
+            Some(10),
+            Some("FREQ=MONTHLY;INTERVAL=1000;UNTIL=99991231T000000Z"),
+        );
+        assert_eq!(until.occurrences().len(), 2);
+    }
+
+    #[test]
+    fn availability_spans_the_windows() {
+        let availability = Availability::of(
+            &[
+                window(5000, Some(6000), None),
+                window(100, Some(200), Some("FREQ=DAILY;COUNT=2")),
+            ],
+            Some(0),
+            Some(10),
+        );
+        assert_eq!(availability.available_date, Some(100));
+        assert_eq!(availability.expiration_date, Some(6000));
+        assert_eq!(availability.occurrences.len(), 3);
+        assert_eq!(availability.occurrences[1].start, 86500);
+
+        let open = Availability::of(&[window(100, None, None)], None, None);
+        assert_eq!(open.expiration_date, None);
+        let unbounded = Availability::of(&[window(100, Some(200), Some("FREQ=DAILY"))], None, None);
+        assert_eq!(unbounded.expiration_date, None);
+    }
+
+    #[test]
+    fn availability_without_windows_keeps_the_dates() {
+        let availability = Availability::of(&[], Some(100), Some(200));
+        assert_eq!(
+            availability.occurrences,
+            vec![Occurrence {
+                start: 100,
+                end: Some(200),
+            }]
+        );
+        assert_eq!(availability.available_date, Some(100));
+        assert_eq!(availability.expiration_date, Some(200));
+        assert!(Availability::of(&[], None, Some(200))
+            .occurrences
+            .is_empty());
+    }
+
+    #[test]
+    fn validates_new_windows() {
+        let new = |start: i64, end: Option<i64>, rule: Option<&str>| NewAvailabilityWindow {
+            start: Utc.timestamp(start, 0),
+            end: end.map(|end| Utc.timestamp(end, 0)),
+            rule: rule.map(|rule| rule.to_string()),
+        };
+        assert!(new(100, Some(200), Some("FREQ=DAILY"))
+            .into_window("w".to_string())
+            .is_ok());
+        assert!(new(100, Some(100), None)
+            .into_window("w".to_string())
+            .is_err());
+        assert!(new(100, None, Some("FREQ=DAILY"))
+            .into_window("w".to_string())
+            .is_err());
+        assert!(new(100, Some(200), Some("FREQ=SOMETIMES"))
+            .into_window("w".to_string())
+            .is_err());
+    }
+}
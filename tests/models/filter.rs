@@ -0,0 +1,110 @@
+#[cfg(test)]
+mod test {
+    use bson::doc;
+    use {{crate_name}}::auth::Principal;
+    use {{crate_name}}::models::{SampleFilter, StringFilter};
+
+    fn name(filter: StringFilter) -> SampleFilter {
+        SampleFilter {
+            name: Some(filter),
+            ..SampleFilter::default()
+        }
+    }
+
+    #[test]
+    fn contains_is_escaped() {
+        let filter = name(StringFilter {
+            contains: Some("a.*(b".to_string()),
+            ..StringFilter::default()
+        });
+        assert_eq!(
+            filter.to_document(&Principal::unrestricted()).unwrap(),
+            doc! { "name": { "$regex": "a\\.\\*\\(b", "$options": "i" } }
+        );
+    }
+
+    #[test]
+    fn operators_are_bound_as_literals() {
+        let filter = name(StringFilter {
+            eq: Some("{ \"$gt\": \"\" }".to_string()),
+            ..StringFilter::default()
+        });
+        assert_eq!(
+            filter.to_document(&Principal::unrestricted()).unwrap(),
+            doc! { "name": "{ \"$gt\": \"\" }" }
+        );
+    }
+
+    #[test]
+    fn composes_not_and_or() {
+        let filter = SampleFilter {
+            or: Some(vec![
+                name(StringFilter {
+                    eq: Some("a".to_string()),
+                    ..StringFilter::default()
+                }),
+                SampleFilter {
+                    created_by: Some("user-1".to_string()),
+                    ..SampleFilter::default()
+                },
+            ]),
+            not: Some(Box::new(name(StringFilter {
+                eq: Some("b".to_string()),
+                ..StringFilter::default()
+            }))),
+            ..SampleFilter::default()
+        };
+        assert_eq!(
+            filter.to_document(&Principal::unrestricted()).unwrap(),
+            doc! { "$and": [
+                { "$or": [{ "name": "a" }, { "created_by.id": "user-1" }] },
+                { "$nor": [{ "name": "b" }] },
+            ] }
+        );
+    }
+
+    #[test]
+    fn skips_empty_not() {
+        let filter = SampleFilter {
+            not: Some(Box::new(SampleFilter::default())),
+            ..name(StringFilter {
+                eq: Some("a".to_string()),
+                ..StringFilter::default()
+            })
+        };
+        assert_eq!(
+            filter.to_document(&Principal::unrestricted()).unwrap(),
+            doc! { "name": "a" }
+        );
+        let filter = SampleFilter {
+            not: Some(Box::new(SampleFilter::default())),
+            ..SampleFilter::default()
+        };
+        assert_eq!(
+            filter.to_document(&Principal::unrestricted()).unwrap(),
+            doc! {}
+        );
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let mut filter = SampleFilter::default();
+        for _ in 0..10 {
+            filter = SampleFilter {
+                not: Some(Box::new(filter)),
+                ..SampleFilter::default()
+            };
+        }
+        assert!(filter.to_document(&Principal::unrestricted()).is_err());
+    }
+
+    #[test]
+    fn created_by_requires_read_audit() {
+        let filter = SampleFilter {
+            created_by: Some("user-1".to_string()),
+            ..SampleFilter::default()
+        };
+        let error = filter.to_document(&Principal::anonymous()).unwrap_err();
+        assert_eq!(error.message(), "Forbidden: requires samples:read_audit");
+    }
+}
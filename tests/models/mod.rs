@@ -0,0 +1,8 @@
+mod availability;
+mod filter;
+mod search;
+mod sort;
+mod statistics;
+mod status;
+mod timeline;
+mod values;
@@ -0,0 +1,79 @@
+#[cfg(test)]
+mod test {
+    use bson::doc;
+    use {{crate_name}}::models::{
+        highlight, page_offset, search_fields, search_terms, text_filter,
+    };
+
+    #[test]
+    fn terms_skip_negations_and_keep_phrases() {
+        assert_eq!(
+            search_terms("first \"Active sample\" -expired"),
+            vec!["first", "active sample"]
+        );
+    }
+
+    #[test]
+    fn only_indexed_fields_are_searchable() {
+        assert_eq!(search_fields(None).unwrap(), vec!["name", "description"]);
+        assert!(search_fields(Some(vec!["owner_id".to_string()])).is_err());
+    }
+
+    #[test]
+    fn fields_narrow_the_text_query() {
+        let all = text_filter("first", &search_fields(None).unwrap(), None).unwrap();
+        assert_eq!(all, doc! { "$text": { "$search": "first" } });
+
+        let name = text_filter("a.b", &["name".to_string()], Some("english".to_string())).unwrap();
+        assert_eq!(
+            name,
+            doc! { "$and": [
+                { "$text": { "$search": "a.b", "$language": "english" } },
+                { "$or": [{ "name": { "$regex": "a\\.b", "$options": "i" } }] },
+            ] }
+        );
+        assert!(text_filter("first", &[], Some("en glish".to_string())).is_err());
+    }
+
+    #[test]
+    fn matches_are_highlighted() {
+        let terms = search_terms("samples");
+        assert_eq!(
+            highlight("This is the first sample - ACTIVE", &terms).unwrap(),
+            "This is the first <em>sample</em> - ACTIVE"
+        );
+        assert_eq!(highlight("Nothing here", &terms), None);
+    }
+
+    #[test]
+    fn offsets_follow_cursors() {
+        assert_eq!(
+            page_offset(Some(2), &None, &None, None).unwrap(),
+            (0, Some(2))
+        );
+        assert_eq!(
+            page_offset(Some(2), &Some("offset:1".to_string()), &None, None).unwrap(),
+            (2, Some(2))
+        );
+        assert_eq!(
+            page_offset(Some(2), &None, &Some("offset:3".to_string()), None).unwrap(),
+            (1, Some(2))
+        );
+        // without a limit, every result before the cursor
+        assert_eq!(
+            page_offset(None, &None, &Some("offset:3".to_string()), None).unwrap(),
+            (0, Some(3))
+        );
+        assert_eq!(
+            page_offset(
+                Some(5),
+                &Some("offset:0".to_string()),
+                &Some("offset:3".to_string()),
+                None
+            )
+            .unwrap(),
+            (1, Some(2))
+        );
+        assert!(page_offset(None, &Some("5f192d99".to_string()), &None, None).is_err());
+    }
+}
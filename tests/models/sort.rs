@@ -0,0 +1,48 @@
+#[cfg(test)]
+mod test {
+    use bson::doc;
+    use {{crate_name}}::models::{
+        sort_document, SampleSort, SampleSortField, SortDirection,
+    };
+
+    #[test]
+    fn default_sort_is_newest_first() {
+        assert_eq!(
+            sort_document(&[]).unwrap(),
+            doc! { "node.date_created": -1, "_id": 1 }
+        );
+    }
+
+    #[test]
+    fn id_breaks_ties() {
+        let sort = vec![
+            SampleSort {
+                field: SampleSortField::MinValue,
+                direction: Some(SortDirection::Desc),
+            },
+            SampleSort {
+                field: SampleSortField::Name,
+                direction: None,
+            },
+        ];
+        assert_eq!(
+            sort_document(&sort).unwrap(),
+            doc! { "min_value": -1, "name": 1, "_id": 1 }
+        );
+    }
+
+    #[test]
+    fn fields_can_only_be_sorted_once() {
+        let sort = vec![
+            SampleSort {
+                field: SampleSortField::Name,
+                direction: None,
+            },
+            SampleSort {
+                field: SampleSortField::Name,
+                direction: Some(SortDirection::Desc),
+            },
+        ];
+        assert!(sort_document(&sort).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+#[cfg(test)]
+mod test {
+    use bson::doc;
+    use serde_json::json;
+    use {{crate_name}}::models::{
+        histogram_boundaries, percentile_pipeline, statistics_buckets, statistics_percentiles,
+        Embedded, EmbeddedType, ValueAggregates, ValueBreakdown, ValueStatistics,
+    };
+
+    fn embedded(embedded_type: &str, value: Option<f64>) -> Embedded {
+        serde_json::from_value(json!({
+            "_id": "e",
+            "node": { "date_created": 0, "date_modified": 0 },
+            "embedded_type": embedded_type,
+            "value": value,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn aggregates_include_zeros() {
+        let values = vec![
+            embedded("One", Some(0.)),
+            embedded("One", Some(0.5)),
+            embedded("Another", Some(1.)),
+            embedded("Another", None),
+        ];
+        assert_eq!(
+            ValueAggregates::of(&values),
+            ValueAggregates {
+                value_count: 4,
+                min_value: Some(0.),
+                max_value: Some(1.),
+                avg_value: Some(0.5),
+            }
+        );
+        let empty = ValueAggregates::of(&[]);
+        assert_eq!(empty.value_count, 0);
+        assert_eq!(empty.min_value, None);
+        assert_eq!(empty.avg_value, None);
+    }
+
+    #[test]
+    fn breakdown_per_embedded_type() {
+        let values = vec![
+            embedded("Another", Some(1.)),
+            embedded("One", Some(0.2)),
+            embedded("One", Some(0.4)),
+        ];
+        let breakdown = ValueBreakdown::of(&values);
+        assert_eq!(breakdown.len(), 2);
+        assert_eq!(breakdown[0].embedded_type, EmbeddedType::One);
+        assert_eq!(breakdown[0].value_count, 2);
+        assert_eq!(breakdown[0].max_value, Some(0.4));
+        assert_eq!(breakdown[1].embedded_type, EmbeddedType::Another);
+        assert!(ValueBreakdown::of(&[]).is_empty());
+    }
+
+    #[test]
+    fn validates_arguments() {
+        assert_eq!(statistics_percentiles(None).unwrap(), vec![50., 90., 99.]);
+        assert!(statistics_percentiles(Some(vec![101.])).is_err());
+        assert_eq!(statistics_buckets(None).unwrap(), 10);
+        assert!(statistics_buckets(Some(0)).is_err());
+        assert!(statistics_buckets(Some(101)).is_err());
+    }
+
+    #[test]
+    fn histogram_covers_the_range() {
+        assert_eq!(
+            histogram_boundaries(0., 10., 4),
+            vec![0., 2.5, 5., 7.5, 10.]
+        );
+
+        let summary = doc! { "count": 5, "min": 0., "max": 10., "avg": 4. };
+        let ranks = doc! { "p0": [{ "value": 5. }] };
+        let counts = vec![
+            doc! { "_id": 0., "count": 2 },
+            doc! { "_id": 5., "count": 1 },
+            doc! { "_id": "max", "count": 2 },
+        ];
+        let statistics = ValueStatistics::from_summary(Some(summary))
+            .with_percentiles(&[50.], Some(ranks))
+            .with_histogram(&[0., 5., 10.], &counts);
+        assert_eq!(statistics.count, 5);
+        assert_eq!(statistics.percentiles[0].value, 5.);
+        let histogram: Vec<i32> = statistics.histogram.iter().map(|b| b.count).collect();
+        assert_eq!(histogram, vec![2, 3]);
+
+        let empty = ValueStatistics::from_summary(None)
+            .with_percentiles(&[50.], None)
+            .with_single_bucket();
+        assert_eq!(empty.count, 0);
+        assert!(empty.percentiles.is_empty());
+        assert!(empty.histogram.is_empty());
+    }
+
+    #[test]
+    fn percentiles_skip_to_their_nearest_rank() {
+        let pipeline = percentile_pipeline(doc! {}, None, &[0., 50., 90., 100.], 5).unwrap();
+        let facets = pipeline.last().unwrap().get_document("$facet").unwrap();
+        let skips: Vec<i64> = (0..4)
+            .map(|i| {
+                let stages = facets.get_array(&format!("p{}", i)).unwrap();
+                stages[1].as_document().unwrap().get_i64("$skip").unwrap()
+            })
+            .collect();
+        assert_eq!(skips, vec![0, 2, 4, 4]);
+    }
+}
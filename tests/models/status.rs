@@ -0,0 +1,92 @@
+#[cfg(test)]
+mod test {
+    use bson::doc;
+    use {{crate_name}}::models::{next_transition, Occurrence, Status};
+
+    #[test]
+    fn status_follows_the_filters() {
+        let now = 1000;
+        assert_eq!(
+            Status::of(Some(0), Some(2000), None, now),
+            Some(Status::Active)
+        );
+        assert_eq!(
+            Status::of(Some(0), None, None, now),
+            Some(Status::Available)
+        );
+        assert_eq!(
+            Status::of(Some(2000), Some(3000), None, now),
+            Some(Status::Pending)
+        );
+        assert_eq!(
+            Status::of(Some(0), Some(500), None, now),
+            Some(Status::Expired)
+        );
+        assert_eq!(
+            Status::of(None, Some(500), None, now),
+            Some(Status::Expired)
+        );
+        assert_eq!(Status::of(None, Some(2000), None, now), None);
+        assert_eq!(Status::of(Some(now), Some(2000), None, now), None);
+    }
+
+    #[test]
+    fn status_between_occurrences_is_available() {
+        let occurrences = [
+            Occurrence {
+                start: 0,
+                end: Some(500),
+            },
+            Occurrence {
+                start: 1500,
+                end: Some(2000),
+            },
+        ];
+        let status = |now| Status::of(Some(0), Some(2000), Some(&occurrences), now);
+        assert_eq!(status(100), Some(Status::Active));
+        assert_eq!(status(1000), Some(Status::Available));
+        assert_eq!(status(1600), Some(Status::Active));
+        assert_eq!(status(2500), Some(Status::Expired));
+    }
+
+    #[test]
+    fn active_filter_falls_back_to_the_dates() {
+        assert_eq!(
+            Status::Active.filter(1000),
+            Some(doc! {
+                "$or": [
+                    {
+                        "occurrences": { "$elemMatch": {
+                            "start": { "$lt": 1000i64 },
+                            "end": { "$gt": 1000i64 },
+                        } },
+                    },
+                    {
+                        "occurrences": { "$exists": false },
+                        "available_date": { "$lt": 1000i64 },
+                        "expiration_date": { "$gt": 1000i64 },
+                    },
+                ]
+            })
+        );
+        assert_eq!(Status::All.filter(1000), None);
+    }
+
+    #[test]
+    fn next_transition_is_the_first_change_after_now() {
+        assert_eq!(
+            next_transition(Some(doc! { "next": 1500_i64 }), 1000),
+            Some(1500)
+        );
+        assert_eq!(
+            next_transition(Some(doc! { "next": 1500 }), 1000),
+            Some(1500)
+        );
+        // at one of its dates a sample is between statuses until the next second
+        assert_eq!(
+            next_transition(Some(doc! { "next": 1000_i64 }), 1000),
+            Some(1001)
+        );
+        assert_eq!(next_transition(None, 1000), None);
+    }
+}
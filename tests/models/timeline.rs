@@ -0,0 +1,125 @@
+#[cfg(test)]
+mod test {
+    use bson::{doc, Bson};
+    use serde_json::json;
+    use {{crate_name}}::models::{
+        timeline_events, timeline_pipeline, Occurrence, Sample, TimelineConnection, TimelineKeys,
+        TransitionKind,
+    };
+
+    fn sample(id: &str, available_date: i64, expiration_date: i64) -> Sample {
+        serde_json::from_value(json!({
+            "_id": id,
+            "name": id,
+            "available_date": available_date,
+            "expiration_date": expiration_date,
+            "node": { "date_created": 0, "date_modified": 0 },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn events_are_ordered_within_the_window() {
+        let samples = vec![sample("b", 10, 30), sample("a", 10, 50), sample("c", 0, 20)];
+        let events: Vec<(i64, TransitionKind)> = timeline_events(samples, 10, 50)
+            .iter()
+            .map(|event| (event.at, event.kind))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (10, TransitionKind::BecomesAvailable),
+                (10, TransitionKind::BecomesAvailable),
+                (20, TransitionKind::Expires),
+                (30, TransitionKind::Expires),
+            ]
+        );
+    }
+
+    #[test]
+    fn pages_follow_cursors() {
+        let samples = vec![sample("a", 10, 20), sample("b", 30, 40)];
+        let events = || timeline_events(samples.clone(), 0, 100);
+        let first = TimelineConnection::page(events(), 4, Some(3), None, None).unwrap();
+        assert_eq!(first.edges.len(), 3);
+        assert!(first.page_info.has_next_page);
+
+        let after = first.page_info.end_cursor.clone();
+        let rest = TimelineConnection::page(events(), 4, Some(3), after, None).unwrap();
+        assert_eq!(rest.edges.len(), 1);
+        assert_eq!(rest.edges[0].node.at, 40);
+        assert!(!rest.page_info.has_next_page);
+
+        let before = rest.page_info.start_cursor.clone();
+        let back = TimelineConnection::page(events(), 4, Some(2), None, before).unwrap();
+        let at: Vec<i64> = back.edges.iter().map(|edge| edge.node.at).collect();
+        assert_eq!(at, vec![20, 30]);
+        assert!(back.page_info.has_previous_page);
+
+        let invalid = Some("yesterday".to_string());
+        assert!(TimelineConnection::page(events(), 4, None, invalid, None).is_err());
+    }
+
+    #[test]
+    fn occurrences_make_up_the_events() {
+        let mut recurring = sample("a", 10, 60);
+        recurring.occurrences = Some(vec![
+            Occurrence {
+                start: 10,
+                end: Some(20),
+            },
+            Occurrence {
+                start: 50,
+                end: Some(60),
+            },
+        ]);
+        let events: Vec<(i64, TransitionKind)> = timeline_events(vec![recurring], 0, 55)
+            .iter()
+            .map(|event| (event.at, event.kind))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (10, TransitionKind::BecomesAvailable),
+                (20, TransitionKind::Expires),
+                (50, TransitionKind::BecomesAvailable),
+            ]
+        );
+    }
+
+    #[test]
+    fn pages_are_taken_from_the_keys_read_in_mongo() {
+        let samples = vec![sample("a", 10, 20), sample("b", 30, 40)];
+        let facet = doc! {
+            "items": [
+                { "at": 20_i64, "kind": 1, "sample_id": "a" },
+                { "at": 30_i64, "kind": 0, "sample_id": "b" },
+            ],
+            "total": [{ "count": 4 }],
+        };
+        let keys = TimelineKeys::from_facet(Some(facet)).unwrap();
+        assert_eq!(keys.total_count, 4);
+        assert_eq!(
+            keys.sample_ids,
+            vec![Bson::String("a".to_string()), Bson::String("b".to_string())]
+        );
+        let events = keys.select(timeline_events(samples, 0, 100));
+        let page = TimelineConnection::page(events, keys.total_count, Some(1), None, None).unwrap();
+        assert_eq!(page.total_count, 4);
+        assert_eq!(page.edges.len(), 1);
+        assert_eq!(page.edges[0].node.at, 20);
+        assert!(page.page_info.has_next_page);
+    }
+
+    #[test]
+    fn pipeline_takes_one_more_key_than_the_page() {
+        let pipeline = timeline_pipeline(doc! {}, 0, 100, &None, &None, 10).unwrap();
+        let facet = pipeline.last().unwrap().get_document("$facet").unwrap();
+        let items = facet.get_array("items").unwrap();
+        let limit = items.last().unwrap().as_document().unwrap();
+        assert_eq!(limit.get_i64("$limit").unwrap(), 11);
+
+        let invalid = Some("yesterday".to_string());
+        assert!(timeline_pipeline(doc! {}, 0, 100, &invalid, &None, 10).is_err());
+    }
+}
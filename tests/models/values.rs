@@ -0,0 +1,85 @@
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use {{crate_name}}::db::id_string;
+    use {{crate_name}}::models::{
+        bounded_page_size, sort_values, Embedded, EmbeddedConnection, EmbeddedFilter, EmbeddedSort,
+        EmbeddedSortField, EmbeddedType, SortDirection, ValueRange,
+    };
+
+    fn embedded(id: &str, embedded_type: &str, value: Option<f64>) -> Embedded {
+        serde_json::from_value(json!({
+            "_id": id,
+            "node": { "date_created": 0, "date_modified": 0 },
+            "embedded_type": embedded_type,
+            "value": value,
+        }))
+        .unwrap()
+    }
+
+    fn ids(values: &[Embedded]) -> Vec<String> {
+        values.iter().map(|value| id_string(&value.id)).collect()
+    }
+
+    #[test]
+    fn filters_by_type_and_range() {
+        let filter = EmbeddedFilter {
+            embedded_type: Some(EmbeddedType::One),
+            value_range: Some(ValueRange {
+                min: Some(0.2),
+                max: Some(0.5),
+            }),
+        };
+        assert!(filter.matches(&embedded("a", "One", Some(0.5))));
+        assert!(!filter.matches(&embedded("b", "One", Some(0.6))));
+        assert!(!filter.matches(&embedded("c", "Another", Some(0.3))));
+        assert!(!filter.matches(&embedded("d", "One", None)));
+    }
+
+    #[test]
+    fn sorts_with_stable_ties() {
+        let mut values = vec![
+            embedded("a", "One", Some(0.5)),
+            embedded("b", "Another", Some(0.1)),
+            embedded("c", "One", Some(0.5)),
+        ];
+        sort_values(
+            &mut values,
+            &[EmbeddedSort {
+                field: EmbeddedSortField::Value,
+                direction: Some(SortDirection::Desc),
+            }],
+        );
+        assert_eq!(ids(&values), vec!["a", "c", "b"]);
+    }
+
+    #[test]
+    fn pages_after_an_id() {
+        let values = vec![
+            embedded("a", "One", Some(0.1)),
+            embedded("b", "One", Some(0.2)),
+            embedded("c", "One", Some(0.3)),
+        ];
+        let first = EmbeddedConnection::page(values.clone(), Some(2), None).unwrap();
+        assert_eq!(first.edges.len(), 2);
+        assert_eq!(first.total_count, 3);
+        assert!(first.page_info.has_next_page);
+
+        let after = first.page_info.end_cursor.clone();
+        let rest = EmbeddedConnection::page(values.clone(), Some(2), after).unwrap();
+        assert_eq!(rest.edges.len(), 1);
+        assert_eq!(rest.edges[0].node.value, Some(0.3));
+        assert!(!rest.page_info.has_next_page);
+        assert!(rest.page_info.has_previous_page);
+
+        let unknown = Some("z".to_string());
+        assert!(EmbeddedConnection::page(values, None, unknown).is_err());
+    }
+
+    #[test]
+    fn values_by_type_pages_are_bounded() {
+        assert_eq!(bounded_page_size(None).unwrap(), 100);
+        assert_eq!(bounded_page_size(Some(1000)).unwrap(), 1000);
+        assert!(bounded_page_size(Some(1001)).is_err());
+    }
+}
@@ -0,0 +1,25 @@
+#[cfg(test)]
+mod graphql_tests {
+    use {{crate_name}}::routes::graphql::strip_no_cache;
+
+    #[test]
+    fn no_cache_directive_is_stripped() {
+        let (query, no_cache) = strip_no_cache("query { allSamples @noCache { totalCount } }");
+        assert_eq!(query, "query { allSamples  { totalCount } }");
+        assert!(no_cache);
+    }
+
+    #[test]
+    fn no_cache_in_strings_and_comments_is_kept() {
+        let source = "{ sampleByNames(names: [\"@noCache\"]) { totalCount } } # @noCache";
+        assert_eq!(strip_no_cache(source), (source.to_string(), false));
+        let source = "{ searchSamples(searchTerm: \"\"\"@noCache\"\"\") { totalCount } }";
+        assert_eq!(strip_no_cache(source), (source.to_string(), false));
+    }
+
+    #[test]
+    fn longer_names_are_kept() {
+        let source = "{ allSamples @noCacheAtAll { totalCount } }";
+        assert_eq!(strip_no_cache(source), (source.to_string(), false));
+    }
+}
@@ -0,0 +1,19 @@
+#[cfg(test)]
+mod test {
+    use mongodb_base_service::mock_time;
+    use std::time::{Duration, SystemTime};
+    use {{crate_name}}::schema::{Clock, FixedClock, SystemClock};
+
+    #[test]
+    fn system_clock_follows_mock_time() {
+        mock_time::set_mock_time(SystemTime::UNIX_EPOCH + Duration::from_secs(1577836800));
+        assert_eq!(SystemClock.now(), 1577836800);
+        mock_time::increase_mock_time(10000);
+        assert_eq!(SystemClock.now(), 1577836810);
+    }
+
+    #[test]
+    fn fixed_clock_is_stopped() {
+        assert_eq!(FixedClock(4102444800).now(), 4102444800);
+    }
+}
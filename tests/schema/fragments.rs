@@ -18,10 +18,14 @@ pub fn sample() -> String {
     name
     description
     values {
-        id
-        dateModified
-        embeddedType
-        value
+        edges {
+            node {
+                id
+                dateModified
+                embeddedType
+                value
+            }
+        }
     }
     "#
     .to_string()
@@ -1,3 +1,4 @@
+mod clock;
 mod fragments;
 mod mutation;
 mod query;
@@ -0,0 +1,122 @@
+#[cfg(test)]
+mod test {
+    use crate::utils;
+
+    use actix_web::{test, App};
+    use {{crate_name}}::auth::{
+        generate_secret, hash_secret, validate_scopes, Permission, Principal, Role,
+    };
+    use {{crate_name}}::routes::app_routes;
+    {% raw %}
+    #[test]
+    fn generated_secrets_are_hashed() {
+        let (secret, prefix, hash) = generate_secret();
+        assert!(secret.starts_with("sk_"));
+        assert!(secret.starts_with(&prefix));
+        assert_eq!(hash, hash_secret(&secret));
+        assert_ne!(hash, secret);
+    }
+
+    #[test]
+    fn scopes_must_be_permissions() {
+        let admin = Principal::unrestricted();
+        assert!(validate_scopes(&admin, &["samples:read".to_string()]).is_ok());
+        assert!(validate_scopes(&admin, &["samples:everything".to_string()]).is_err());
+    }
+
+    #[test]
+    fn scopes_must_be_held_by_the_caller() {
+        let mut manager = Principal::unrestricted();
+        manager.role = Some(Role::Editor);
+        manager.permissions = Role::Editor.permissions();
+        manager.permissions.insert(Permission::ManageApiKeys);
+        assert!(validate_scopes(&manager, &["samples:write".to_string()]).is_ok());
+        let error = validate_scopes(&manager, &["samples:manage_all".to_string()]).unwrap_err();
+        assert_eq!(error.message(), "Forbidden: requires samples:manage_all");
+        assert!(validate_scopes(&manager, &["tokens:revoke".to_string()]).is_err());
+    }
+
+    #[actix_rt::test]
+    async fn create_and_revoke_api_key() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_filled_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        let query = utils::GqlQuery {
+            operation_name: "createApiKey",
+            query: r#"
+                mutation createApiKey {
+                    createApiKey(newApiKey: { name: "batch job", scopes: ["samples:read"] }) {
+                        secret
+                        apiKey {
+                            id
+                            prefix
+                            scopes
+                            active
+                        }
+                    }
+                }"#,
+        };
+
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        let created = &json["data"]["createApiKey"];
+        let secret = created["secret"].as_str().unwrap();
+        let prefix = created["apiKey"]["prefix"].as_str().unwrap();
+        assert!(secret.starts_with(prefix));
+        assert_eq!(created["apiKey"]["scopes"][0], "samples:read");
+        assert_eq!(created["apiKey"]["active"], true);
+
+        let query = utils::GqlQuery {
+            operation_name: "revokeApiKey",
+            query: &format!(
+                r#"
+                mutation revokeApiKey {{
+                    revokeApiKey(id: {id}) {{
+                        active
+                    }}
+                }}"#,
+                id = created["apiKey"]["id"]
+            ),
+        };
+
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        assert_eq!(json["data"]["revokeApiKey"]["active"], false);
+
+        let query = utils::GqlQuery {
+            operation_name: "apiKeys",
+            query: r#"
+                query apiKeys {
+                    apiKeys {
+                        id
+                    }
+                }"#,
+        };
+
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        assert_eq!(json["data"]["apiKeys"], serde_json::json!([]));
+    }{% endraw %}
+}
@@ -3,8 +3,10 @@ mod test {
     use crate::utils;
 
     use actix_web::{test, App};
+    use bson::doc;
     use insta::assert_snapshot;
     use mongodb_base_service::mock_time;
+    use uuid::Uuid;
     use {{crate_name}}::routes::app_routes;
     {% raw %}
     use crate::schema::fragments;
@@ -275,5 +277,78 @@ mod test {
             "remove_value_from_sample_existing_non_existing",
             format!("{:?}", resp)
         );
+    }
+
+    #[actix_rt::test]
+    async fn value_changes_fail_when_the_audit_record_is_rejected() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_filled_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        // a sample of its own, so the validator below doesn't get in the way of other tests
+        let id = Uuid::new_v4().to_simple().to_string()[..24].to_string();
+        let query = utils::GqlQuery {
+            operation_name: "createSample",
+            query: &format!(
+                r#"
+                mutation createSample {{
+                    createSample(newSample: {{ id: "$oid:{id}", name: "Unaudited" }}) {{
+                        id
+                    }}
+                }}"#,
+                id = id
+            ),
+        };
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+        test::read_response(&mut app, req).await;
+
+        let database = utils::database();
+        database
+            .run_command(
+                doc! { "collMod": "audit_log", "validator": { "target_id": { "$ne": id.as_str() } } },
+                None,
+            )
+            .unwrap();
+
+        let query = utils::GqlQuery {
+            operation_name: "addValuesToSample",
+            query: &format!(
+                r#"
+                mutation addValuesToSample {{
+                    addValuesToSample(
+                        sampleId: "$oid:{id}"
+                        newValues: [{{ id: "12345", embeddedType: ONE, value: 0.1 }}]
+                    ) {{
+                        id
+                    }}
+                }}"#,
+                id = id
+            ),
+        };
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+        let resp = test::read_response(&mut app, req).await;
+
+        database
+            .run_command(doc! { "collMod": "audit_log", "validator": {} }, None)
+            .unwrap();
+
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        assert_eq!(json["errors"][0]["extensions"]["code"], "AUDIT_FAILED");
+        assert_eq!(
+            json["errors"][0]["message"],
+            "addValuesToSample was applied but could not be recorded in the audit log"
+        );
     }{% endraw %}
 }
@@ -5,6 +5,7 @@ mod test {
     use actix_web::{test, App};
     use insta::assert_snapshot;
     use mongodb_base_service::mock_time;
+    use uuid::Uuid;
     use {{crate_name}}::routes::app_routes;
 
     use crate::schema::fragments;
@@ -309,5 +310,456 @@ mod test {
 
         let resp = test::read_response(&mut app, req).await;
         assert_snapshot!("delete_sample_all_samples", format!("{:?}", resp));
+    }
+
+    #[actix_rt::test]
+    async fn create_sample_records_author() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_filled_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        let query = utils::GqlQuery {
+            operation_name: "createSample",
+            query: r#"
+                mutation createSample {
+                    createSample(
+                        newSample: {
+                            name: "Authored Sample"
+                        }
+                        createdById: "user-42"
+                    ) {
+                        createdById
+                        createdBy {
+                            id
+                            email
+                        }
+                        updatedBy {
+                            id
+                        }
+                    }
+                }"#,
+        };
+
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        let sample = &json["data"]["createSample"];
+        assert_eq!(sample["createdById"], "user-42");
+        assert_eq!(sample["createdBy"]["id"], "user-42");
+        assert_eq!(sample["createdBy"]["email"], serde_json::Value::Null);
+        assert_eq!(sample["updatedBy"]["id"], "user-42");
+    }
+
+    #[actix_rt::test]
+    async fn share_and_transfer_sample() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_filled_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        let query = utils::GqlQuery {
+            operation_name: "shareSample",
+            query: r#"
+                mutation shareSample {
+                    shareSample(
+                        id: "$oid:5f192d9900e0306000d188e1"
+                        principal: "group:support"
+                        access: WRITE
+                    ) {
+                        acl {
+                            principal
+                            access
+                        }
+                    }
+                    transferSampleOwnership(
+                        id: "$oid:5f192d9900e0306000d188e1"
+                        ownerId: "user-7"
+                    ) {
+                        ownerId
+                    }
+                }"#,
+        };
+
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        let acl = &json["data"]["shareSample"]["acl"];
+        assert_eq!(acl[0]["principal"], "group:support");
+        assert_eq!(acl[0]["access"], "WRITE");
+        assert_eq!(json["data"]["transferSampleOwnership"]["ownerId"], "user-7");
+    }
+
+    #[actix_rt::test]
+    async fn create_sample_acting_as_user() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_filled_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        let query = utils::GqlQuery {
+            operation_name: "createSample",
+            query: r#"
+                mutation createSample {
+                    createSample(
+                        newSample: {
+                            name: "Impersonated Sample"
+                        }
+                    ) {
+                        ownerId
+                        createdBy {
+                            id
+                        }
+                        impersonatedBy {
+                            id
+                        }
+                    }
+                }"#,
+        };
+
+        let req = test::TestRequest::post()
+            .header("x-act-as", "user-9")
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        let sample = &json["data"]["createSample"];
+        assert_eq!(sample["ownerId"], "user-9");
+        assert_eq!(sample["createdBy"]["id"], "user-9");
+        assert_ne!(sample["impersonatedBy"]["id"], serde_json::Value::Null);
+        assert_ne!(sample["impersonatedBy"]["id"], "user-9");
+    }
+
+    #[actix_rt::test]
+    async fn samples_are_scoped_to_tenant() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_filled_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        let create = utils::GqlQuery {
+            operation_name: "createSample",
+            query: r#"
+                mutation createSample {
+                    createSample(newSample: { name: "Tenant Sample" }) {
+                        name
+                    }
+                }"#,
+        };
+        let req = test::TestRequest::post()
+            .header("x-tenant-id", "unit-a")
+            .set_json(&create)
+            .uri("/test_path/graphql")
+            .to_request();
+        let _resp = test::read_response(&mut app, req).await;
+
+        let query = utils::GqlQuery {
+            operation_name: "allSamples",
+            query: r#"
+                query allSamples {
+                    allSamples {
+                        totalCount
+                        edges {
+                            node {
+                                name
+                            }
+                        }
+                    }
+                }"#,
+        };
+        let req = test::TestRequest::post()
+            .header("x-tenant-id", "unit-a")
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        let samples = &json["data"]["allSamples"];
+        assert_eq!(samples["totalCount"], 1);
+        assert_eq!(samples["edges"][0]["node"]["name"], "Tenant Sample");
+
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        let names: Vec<&str> = json["data"]["allSamples"]["edges"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|edge| edge["node"]["name"].as_str())
+            .collect();
+        assert!(!names.contains(&"Tenant Sample"));
+    }
+
+    #[actix_rt::test]
+    async fn samples_are_read_from_tenant_database() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_tenant_databases)
+                .configure(app_routes),
+        )
+        .await;
+
+        // tenant databases outlive the test, a fresh tenant starts out empty
+        let tenant = format!("unit-{}", &Uuid::new_v4().to_simple().to_string()[..8]);
+        let create = utils::GqlQuery {
+            operation_name: "createSample",
+            query: r#"
+                mutation createSample {
+                    createSample(newSample: { name: "Tenant Sample" }) {
+                        id
+                    }
+                }"#,
+        };
+        let req = test::TestRequest::post()
+            .header("x-tenant-id", tenant.as_str())
+            .set_json(&create)
+            .uri("/test_path/graphql")
+            .to_request();
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        let id = json["data"]["createSample"]["id"].as_str().unwrap();
+
+        let query = utils::GqlQuery {
+            operation_name: "sampleById",
+            query: &format!(
+                r#"
+                query sampleById {{
+                    sampleById(id: "{}") {{
+                        name
+                    }}
+                }}"#,
+                id
+            ),
+        };
+        let req = test::TestRequest::post()
+            .header("x-tenant-id", tenant.as_str())
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        assert_eq!(json["data"]["sampleById"]["name"], "Tenant Sample");
+
+        // the sample isn't in the shared database
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        assert_eq!(json["data"]["sampleById"], serde_json::Value::Null);
+    }
+
+    #[actix_rt::test]
+    async fn update_sample_is_audited() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_filled_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        let query = utils::GqlQuery {
+            operation_name: "updateSample",
+            query: r#"
+                mutation updateSample {
+                    updateSample(
+                        id: "$oid:5f192d9900e0306000d188e1"
+                        updateSample: { name: "Audited Name" }
+                    ) {
+                        name
+                    }
+                }"#,
+        };
+        let req = test::TestRequest::post()
+            .header("x-request-id", "audit-request")
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+        let _resp = test::read_response(&mut app, req).await;
+
+        let query = utils::GqlQuery {
+            operation_name: "auditTrail",
+            query: r#"
+                query auditTrail {
+                    auditTrail(sampleId: "$oid:5f192d9900e0306000d188e1", first: 1) {
+                        edges {
+                            node {
+                                operation
+                                targetId
+                                requestId
+                                changes {
+                                    field
+                                    after
+                                }
+                            }
+                        }
+                    }
+                }"#,
+        };
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        let record = &json["data"]["auditTrail"]["edges"][0]["node"];
+        assert_eq!(record["operation"], "updateSample");
+        assert_eq!(record["targetId"], "5f192d9900e0306000d188e1");
+        assert_eq!(record["requestId"], "audit-request");
+        let changes = record["changes"].as_array().unwrap();
+        let name = changes
+            .iter()
+            .find(|change| change["field"] == "name")
+            .unwrap();
+        assert_eq!(name["after"], "\"Audited Name\"");
+    }
+
+    #[actix_rt::test]
+    async fn recurring_availability_window() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_filled_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        // the pending sample 4 becomes available around the turn of every month
+        let query = utils::GqlQuery {
+            operation_name: "addAvailabilityWindow",
+            query: r#"
+                mutation addAvailabilityWindow {
+                    addAvailabilityWindow(
+                        sampleId: "$oid:5f192d9900e0306000d188e4"
+                        window: {
+                            start: "2019-12-31T12:00:00Z"
+                            end: "2020-01-01T12:00:00Z"
+                            rule: "FREQ=MONTHLY;COUNT=3"
+                        }
+                    ) {
+                        status
+                        availableDate
+                        windows {
+                            id
+                            rule
+                        }
+                    }
+                }"#,
+        };
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        let sample = &json["data"]["addAvailabilityWindow"];
+        assert_eq!(sample["status"], "ACTIVE");
+        assert_eq!(sample["availableDate"], "2019-12-31T12:00:00+00:00");
+        assert_eq!(sample["windows"][0]["rule"], "FREQ=MONTHLY;COUNT=3");
+        let window_id = sample["windows"][0]["id"].as_str().unwrap().to_string();
+
+        let query = utils::GqlQuery {
+            operation_name: "samplesByStatus",
+            query: r#"
+                query samplesByStatus {
+                    within: samplesByStatus(status: ACTIVE, asOf: "2020-01-01T01:00:00Z") {
+                        totalCount
+                    }
+                    between: samplesByStatus(status: ACTIVE, asOf: "2020-01-15T00:00:00Z") {
+                        totalCount
+                    }
+                    available: samplesByStatus(status: AVAILABLE, asOf: "2020-01-15T00:00:00Z") {
+                        edges {
+                            node {
+                                name
+                                status
+                            }
+                        }
+                    }
+                }"#,
+        };
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        assert_eq!(json["data"]["within"]["totalCount"], 3);
+        assert_eq!(json["data"]["between"]["totalCount"], 2);
+        let sample = json["data"]["available"]["edges"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|edge| edge["node"]["name"] == "Sample 4")
+            .unwrap();
+        assert_eq!(sample["node"]["status"], "AVAILABLE");
+
+        let query = utils::GqlQuery {
+            operation_name: "removeAvailabilityWindow",
+            query: &format!(
+                r#"
+                mutation removeAvailabilityWindow {{
+                    removeAvailabilityWindow(
+                        sampleId: "$oid:5f192d9900e0306000d188e4"
+                        windowId: "{}"
+                    ) {{
+                        windows {{
+                            id
+                        }}
+                    }}
+                }}"#,
+                window_id
+            ),
+        };
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        let windows = &json["data"]["removeAvailabilityWindow"]["windows"];
+        assert_eq!(windows.as_array().unwrap().len(), 0);
     }{% endraw %}
 }
@@ -4,6 +4,8 @@ mod test {
 
     use actix_web::{test, App};
     use insta::assert_snapshot;
+    use mongodb_base_service::mock_time;
+    use std::time::{Duration, SystemTime};
     use {{crate_name}}::routes::app_routes;
 
     use crate::schema::fragments;
@@ -308,5 +310,808 @@ mod test {
 
         let resp = test::read_response(&mut app, req).await;
         assert_snapshot!("sample_by_names_missing", format!("{:?}", resp));
+    }
+
+    #[actix_rt::test]
+    async fn all_samples_relay_pagination() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_filled_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        let query = utils::GqlQuery {
+            operation_name: "allSamples",
+            query: r#"
+                query allSamples {
+                    allSamples(first: 2) {
+                        pageInfo {
+                            hasNextPage
+                            endCursor
+                        }
+                        edges {
+                            cursor
+                            node {
+                                name
+                            }
+                        }
+                    }
+                }"#,
+        };
+
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        let connection = &json["data"]["allSamples"];
+        assert_eq!(connection["pageInfo"]["hasNextPage"], true);
+        assert_eq!(connection["edges"][0]["node"]["name"], "Sample 5");
+        assert_eq!(connection["edges"][1]["node"]["name"], "Sample 4");
+        assert_eq!(
+            connection["edges"][1]["cursor"],
+            connection["pageInfo"]["endCursor"]
+        );
+
+        let query = utils::GqlQuery {
+            operation_name: "allSamples",
+            query: &format!(
+                r#"
+                query allSamples {{
+                    allSamples(first: 2, after: {end_cursor}) {{
+                        edges {{
+                            node {{
+                                name
+                            }}
+                        }}
+                    }}
+                }}"#,
+                end_cursor = connection["pageInfo"]["endCursor"]
+            ),
+        };
+
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        let edges = &json["data"]["allSamples"]["edges"];
+        assert_eq!(edges[0]["node"]["name"], "Sample 3");
+        assert_eq!(edges[1]["node"]["name"], "Sample 2");
+    }
+
+    #[actix_rt::test]
+    async fn all_samples_first_and_last() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_filled_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        let query = utils::GqlQuery {
+            operation_name: "allSamples",
+            query: r#"
+                query allSamples {
+                    allSamples(first: 2, last: 2) {
+                        totalCount
+                    }
+                }"#,
+        };
+
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        assert_eq!(
+            json["errors"][0]["message"],
+            "Only one of `first` or `last` may be provided"
+        );
+    }
+
+    #[actix_rt::test]
+    async fn all_samples_filtered() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_filled_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        let query = utils::GqlQuery {
+            operation_name: "allSamples",
+            query: r#"
+                query allSamples {
+                    allSamples(filter: {
+                        or: [
+                            { name: { eq: "Sample 1" } },
+                            { name: { in: ["Sample 2", "Sample 3"] } }
+                        ]
+                        not: { name: { contains: "sample 3" } }
+                        values: { min: 0.1, embeddedType: ONE }
+                    }) {
+                        edges {
+                            node {
+                                name
+                            }
+                        }
+                    }
+                }"#,
+        };
+
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        let names: Vec<&str> = json["data"]["allSamples"]["edges"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|edge| edge["node"]["name"].as_str())
+            .collect();
+        assert!(names.contains(&"Sample 1"));
+        assert!(!names.contains(&"Sample 3"));
+    }
+
+    #[actix_rt::test]
+    async fn all_samples_sorted() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_filled_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        // every sample shares the same dateCreated, so pages only line up when ties are broken
+        let page = |after: Option<String>| {
+            let after = after
+                .map(|cursor| format!(", after: \"{}\"", cursor))
+                .unwrap_or_default();
+            format!(
+                r#"query allSamples {{
+                    allSamples(sort: [{{ field: DATE_CREATED, direction: DESC }}], first: 2{}) {{
+                        pageInfo {{ endCursor hasNextPage }}
+                        edges {{ node {{ id }} }}
+                    }}
+                }}"#,
+                after
+            )
+        };
+
+        let mut ids: Vec<String> = vec![];
+        let mut after = None;
+        loop {
+            let query = page(after.clone());
+            let query = utils::GqlQuery {
+                operation_name: "allSamples",
+                query: &query,
+            };
+            let req = test::TestRequest::post()
+                .set_json(&query)
+                .uri("/test_path/graphql")
+                .to_request();
+            let resp = test::read_response(&mut app, req).await;
+            let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+            let connection = &json["data"]["allSamples"];
+            ids.extend(
+                connection["edges"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .filter_map(|edge| edge["node"]["id"].as_str())
+                    .map(|id| id.to_string()),
+            );
+            if !connection["pageInfo"]["hasNextPage"]
+                .as_bool()
+                .unwrap_or(false)
+            {
+                break;
+            }
+            after = connection["pageInfo"]["endCursor"]
+                .as_str()
+                .map(|cursor| cursor.to_string());
+        }
+
+        let mut unique = ids.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), ids.len());
+        assert_eq!(ids.len(), 5);
+    }
+
+    #[actix_rt::test]
+    async fn all_samples_sorted_by_min_value() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_filled_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        let query = utils::GqlQuery {
+            operation_name: "allSamples",
+            query: r#"
+                query allSamples {
+                    allSamples(sort: [{ field: MIN_VALUE, direction: DESC }]) {
+                        edges {
+                            node {
+                                minValue
+                            }
+                        }
+                    }
+                }"#,
+        };
+
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        let values: Vec<f64> = json["data"]["allSamples"]["edges"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|edge| edge["node"]["minValue"].as_f64())
+            .collect();
+        assert!(!values.is_empty());
+        assert!(values.windows(2).all(|pair| pair[0] >= pair[1]));
+    }
+
+    #[actix_rt::test]
+    async fn search_samples_ranked() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_filled_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        let query = utils::GqlQuery {
+            operation_name: "searchSamples",
+            query: r#"
+                query searchSamples {
+                    searchSamples(searchTerm: "fourth -fifth") {
+                        totalCount
+                        edges {
+                            score
+                            highlights {
+                                field
+                                snippet
+                            }
+                            node {
+                                name
+                            }
+                        }
+                    }
+                }"#,
+        };
+
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        let connection = &json["data"]["searchSamples"];
+        assert_eq!(connection["totalCount"], 1);
+        let hit = &connection["edges"][0];
+        assert_eq!(hit["node"]["name"], "Sample 4");
+        assert!(hit["score"].as_f64().unwrap() > 0.);
+        assert_eq!(hit["highlights"][0]["field"], "description");
+        assert!(hit["highlights"][0]["snippet"]
+            .as_str()
+            .unwrap()
+            .contains("<em>fourth</em>"));
+    }
+
+    #[actix_rt::test]
+    async fn fuzzy_search_requires_an_index() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_filled_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        let query = utils::GqlQuery {
+            operation_name: "fuzzySearchSamples",
+            query: r#"
+                query fuzzySearchSamples {
+                    fuzzySearchSamples(searchTerm: "smaple") {
+                        totalCount
+                    }
+                }"#,
+        };
+
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        assert_eq!(json["errors"][0]["message"], "Fuzzy search is not enabled");
+    }
+
+    #[actix_rt::test]
+    async fn sample_status_counts() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_filled_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        let query = utils::GqlQuery {
+            operation_name: "sampleStatusCounts",
+            query: r#"
+                query sampleStatusCounts {
+                    sampleStatusCounts {
+                        status
+                        count
+                    }
+                    sampleByNames(names: ["Sample 1", "Sample 3", "Sample 4"]) {
+                        edges {
+                            node {
+                                name
+                                status
+                            }
+                        }
+                    }
+                }"#,
+        };
+
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        let counts: Vec<(&str, i64)> = json["data"]["sampleStatusCounts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|count| {
+                (
+                    count["status"].as_str().unwrap(),
+                    count["count"].as_i64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            counts,
+            vec![
+                ("ALL", 5),
+                ("AVAILABLE", 4),
+                ("ACTIVE", 2),
+                ("EXPIRED", 2),
+                ("PENDING", 1)
+            ]
+        );
+        let statuses: Vec<(&str, &str)> = json["data"]["sampleByNames"]["edges"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|edge| {
+                (
+                    edge["node"]["name"].as_str().unwrap(),
+                    edge["node"]["status"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert!(statuses.contains(&("Sample 1", "ACTIVE")));
+        assert!(statuses.contains(&("Sample 3", "EXPIRED")));
+        assert!(statuses.contains(&("Sample 4", "PENDING")));
+    }
+
+    #[actix_rt::test]
+    async fn samples_by_status_as_of() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_filled_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        let query = utils::GqlQuery {
+            operation_name: "samplesByStatus",
+            query: r#"
+                query samplesByStatus {
+                    samplesByStatus(status: EXPIRED, asOf: "2100-06-01T00:00:00Z") {
+                        totalCount
+                        edges {
+                            node {
+                                status
+                            }
+                        }
+                    }
+                    active: samplesByStatus(status: ACTIVE) {
+                        totalCount
+                    }
+                }"#,
+        };
+
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        let expired = &json["data"]["samplesByStatus"];
+        assert_eq!(expired["totalCount"], 5);
+        assert!(expired["edges"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|edge| edge["node"]["status"] == "EXPIRED"));
+        assert_eq!(json["data"]["active"]["totalCount"], 2);
+    }
+
+    #[actix_rt::test]
+    async fn samples_changing_within() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_filled_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        // 800 seconds before samples 1 and 2 expire and sample 4 becomes available
+        mock_time::set_mock_time(SystemTime::UNIX_EPOCH + Duration::from_secs(4102444000));
+
+        let query = utils::GqlQuery {
+            operation_name: "upcoming",
+            query: r#"
+                query upcoming {
+                    samplesExpiringWithin(duration: 3600) {
+                        edges {
+                            node {
+                                name
+                            }
+                        }
+                    }
+                    samplesBecomingAvailableWithin(duration: 3600) {
+                        edges {
+                            node {
+                                name
+                            }
+                        }
+                    }
+                    soon: samplesExpiringWithin(duration: 60) {
+                        totalCount
+                    }
+                }"#,
+        };
+
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        let names = |connection: &serde_json::Value| -> Vec<String> {
+            connection["edges"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter_map(|edge| edge["node"]["name"].as_str())
+                .map(|name| name.to_string())
+                .collect()
+        };
+        assert_eq!(
+            names(&json["data"]["samplesExpiringWithin"]),
+            vec!["Sample 1", "Sample 2", "Sample 4"]
+        );
+        assert_eq!(
+            names(&json["data"]["samplesBecomingAvailableWithin"]),
+            vec!["Sample 4"]
+        );
+        assert_eq!(json["data"]["soon"]["totalCount"], 0);
+    }
+
+    #[actix_rt::test]
+    async fn sample_timeline_pages() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_filled_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        let page = |after: &str| {
+            format!(
+                r#"query sampleTimeline {{
+                    sampleTimeline(from: "1970-01-01T00:00:00Z", to: "1970-01-02T00:00:00Z", first: 4{}) {{
+                        totalCount
+                        pageInfo {{ endCursor hasNextPage }}
+                        edges {{ node {{ at kind sample {{ name }} }} }}
+                    }}
+                }}"#,
+                after
+            )
+        };
+
+        let mut events: Vec<(String, String)> = vec![];
+        let mut after = String::new();
+        loop {
+            let query = page(&after);
+            let query = utils::GqlQuery {
+                operation_name: "sampleTimeline",
+                query: &query,
+            };
+            let req = test::TestRequest::post()
+                .set_json(&query)
+                .uri("/test_path/graphql")
+                .to_request();
+            let resp = test::read_response(&mut app, req).await;
+            let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+            let connection = &json["data"]["sampleTimeline"];
+            assert_eq!(connection["totalCount"], 6);
+            events.extend(connection["edges"].as_array().unwrap().iter().map(|edge| {
+                (
+                    edge["node"]["sample"]["name"].as_str().unwrap().to_string(),
+                    edge["node"]["kind"].as_str().unwrap().to_string(),
+                )
+            }));
+            if !connection["pageInfo"]["hasNextPage"].as_bool().unwrap() {
+                break;
+            }
+            after = format!(
+                ", after: \"{}\"",
+                connection["pageInfo"]["endCursor"].as_str().unwrap()
+            );
+        }
+
+        let expected = vec![
+            ("Sample 1", "BECOMES_AVAILABLE"),
+            ("Sample 2", "BECOMES_AVAILABLE"),
+            ("Sample 3", "BECOMES_AVAILABLE"),
+            ("Sample 5", "BECOMES_AVAILABLE"),
+            ("Sample 5", "EXPIRES"),
+            ("Sample 3", "EXPIRES"),
+        ];
+        let expected: Vec<(String, String)> = expected
+            .into_iter()
+            .map(|(name, kind)| (name.to_string(), kind.to_string()))
+            .collect();
+        assert_eq!(events, expected);
+    }
+
+    #[actix_rt::test]
+    async fn sample_value_aggregates() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_filled_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        let query = utils::GqlQuery {
+            operation_name: "sampleById",
+            query: r#"
+                query sampleById {
+                    sampleById(id: "$oid:5f192d9900e0306000d188e1") {
+                        minValue
+                        maxValue
+                        avgValue
+                        valueCount
+                        valueBreakdown {
+                            embeddedType
+                            valueCount
+                            minValue
+                        }
+                    }
+                }"#,
+        };
+
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        let sample = &json["data"]["sampleById"];
+        assert_eq!(sample["minValue"], 0.3);
+        assert_eq!(sample["maxValue"], 0.5);
+        assert!((sample["avgValue"].as_f64().unwrap() - 0.4).abs() < 1e-9);
+        assert_eq!(sample["valueCount"], 2);
+        assert_eq!(sample["valueBreakdown"][0]["embeddedType"], "ONE");
+        assert_eq!(sample["valueBreakdown"][0]["minValue"], 0.3);
+        assert_eq!(sample["valueBreakdown"][1]["embeddedType"], "ANOTHER");
+    }
+
+    #[actix_rt::test]
+    async fn value_statistics() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_filled_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        let query = utils::GqlQuery {
+            operation_name: "valueStatistics",
+            query: r#"
+                query valueStatistics {
+                    valueStatistics(percentiles: [50, 90], buckets: 2) {
+                        count
+                        min
+                        max
+                        avg
+                        percentiles {
+                            percentile
+                            value
+                        }
+                        histogram {
+                            lower
+                            upper
+                            count
+                        }
+                    }
+                    one: valueStatistics(embeddedType: ONE) {
+                        count
+                    }
+                }"#,
+        };
+
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        let statistics = &json["data"]["valueStatistics"];
+        assert_eq!(statistics["count"], 10);
+        assert_eq!(statistics["min"], 0.3);
+        assert_eq!(statistics["max"], 0.9);
+        assert!((statistics["avg"].as_f64().unwrap() - 0.74).abs() < 1e-9);
+        assert_eq!(statistics["percentiles"][0]["value"], 0.7);
+        assert_eq!(statistics["percentiles"][1]["value"], 0.9);
+        let histogram: Vec<i64> = statistics["histogram"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|bucket| bucket["count"].as_i64())
+            .collect();
+        assert_eq!(histogram, vec![2, 8]);
+        assert_eq!(json["data"]["one"]["count"], 7);
+    }
+
+    #[actix_rt::test]
+    async fn sample_values_filtered_and_paged() {
+        std::env::set_var("BASE_PATH", "test_path");
+        std::env::set_var("DISABLE_AUTH", "1");
+
+        let mut app = test::init_service(
+            App::new()
+                .configure(utils::load_filled_database)
+                .configure(app_routes),
+        )
+        .await;
+
+        let query = utils::GqlQuery {
+            operation_name: "sampleById",
+            query: r#"
+                query sampleById {
+                    sampleById(id: "$oid:5f192d9900e0306000d188e3") {
+                        values(
+                            filter: { embeddedType: ONE, valueRange: { min: 0.8 } }
+                            sort: [{ field: VALUE, direction: DESC }]
+                            first: 1
+                        ) {
+                            totalCount
+                            pageInfo {
+                                hasNextPage
+                            }
+                            edges {
+                                node {
+                                    embeddedType
+                                    value
+                                }
+                            }
+                        }
+                    }
+                    valuesByType(embeddedType: ANOTHER, first: 2) {
+                        totalCount
+                        pageInfo {
+                            hasNextPage
+                        }
+                        edges {
+                            node {
+                                sampleId
+                                embedded {
+                                    embeddedType
+                                    value
+                                }
+                            }
+                        }
+                    }
+                }"#,
+        };
+
+        let req = test::TestRequest::post()
+            .set_json(&query)
+            .uri("/test_path/graphql")
+            .to_request();
+
+        let resp = test::read_response(&mut app, req).await;
+        let json: serde_json::Value = serde_json::from_slice(&resp).unwrap();
+        let values = &json["data"]["sampleById"]["values"];
+        assert_eq!(values["totalCount"], 2);
+        assert_eq!(values["pageInfo"]["hasNextPage"], true);
+        assert_eq!(values["edges"][0]["node"]["embeddedType"], "ONE");
+        assert_eq!(values["edges"][0]["node"]["value"], 0.9);
+
+        let by_type = &json["data"]["valuesByType"];
+        assert_eq!(by_type["totalCount"], 3);
+        assert_eq!(by_type["pageInfo"]["hasNextPage"], true);
+        let edges = by_type["edges"].as_array().unwrap();
+        assert_eq!(edges.len(), 2);
+        assert_eq!(
+            edges[0]["node"]["sampleId"],
+            "$oid:5f192d9900e0306000d188e1"
+        );
+        assert_eq!(edges[0]["node"]["embedded"]["value"], 0.5);
+        assert!(edges
+            .iter()
+            .all(|edge| edge["node"]["embedded"]["embeddedType"] == "ANOTHER"));
     }{% endraw %}
 }
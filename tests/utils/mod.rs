@@ -1,4 +1,5 @@
 use actix_web::web;
+use bson::doc;
 use mongodb_base_service::{mock_time, BaseService};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::fs::File;
@@ -6,6 +7,8 @@ use std::io::prelude::*;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
+use {{crate_name}}::auth::Issuers;
+use {{crate_name}}::db::mongo::TenantDatabases;
 use {{crate_name}}::db::Clients;
 use {{crate_name}}::schema::create_schema;
 
@@ -30,11 +33,42 @@ where
     items
 }
 
+/// The database the app under test uses, for setting up what it can't do itself, e.g.
+/// collection validators. Only valid once the app is configured.
+pub fn database() -> mongodb::Database {
+    let mongo_url = std::env::var("MONGO_URL").expect("MONGO_URL must be set");
+    let db_name = std::env::var("MONGO_DB_NAME").expect("MONGO_DB_NAME must be set");
+    mongodb::Client::with_uri_str(&mongo_url)
+        .expect("Failed to initialize client.")
+        .database(&db_name)
+}
+
 pub fn load_filled_database(config: &mut web::ServiceConfig) {
     // disable cache
     std::env::set_var("CACHE_TTL", "0");
     std::env::set_var("CACHE_CAPACITY", "0");
+    load_database(config, false);
+}
+
+/// Like `load_filled_database` in database-per-tenant mode, samples of a tenant are stored in
+/// the `<MONGO_DB_NAME>-<tenant>` database
+#[allow(dead_code)]
+pub fn load_tenant_databases(config: &mut web::ServiceConfig) {
+    std::env::set_var("CACHE_TTL", "0");
+    std::env::set_var("CACHE_CAPACITY", "0");
+    load_database(config, true);
+}
+
+/// Like `load_filled_database` with the query caches enabled. The caches read their settings
+/// once per process, so only the `cached` test binary uses it.
+#[allow(dead_code)]
+pub fn load_cached_database(config: &mut web::ServiceConfig) {
+    std::env::set_var("CACHE_TTL", "60");
+    std::env::set_var("CACHE_CAPACITY", "100");
+    load_database(config, false);
+}
 
+fn load_database(config: &mut web::ServiceConfig, tenant_databases: bool) {
     let mongo_url = std::env::var("MONGO_URL").unwrap_or("mongodb://localhost:27017/".to_string());
     std::env::set_var("MONGO_URL", mongo_url);
     let db_name = std::env::var("MONGO_DB_NAME").unwrap_or("{{project-name}}-test".to_string());
@@ -43,19 +77,29 @@ pub fn load_filled_database(config: &mut web::ServiceConfig) {
     // fix time to Jan 1, 2020 so that snapshots always have the same dateModified etc...
     mock_time::set_mock_time(SystemTime::UNIX_EPOCH + Duration::from_millis(1577836800000));
 
+    let tenant_databases = if tenant_databases {
+        Some(TenantDatabases::new(
+            &std::env::var("MONGO_URL").unwrap(),
+            &std::env::var("MONGO_DB_NAME").unwrap(),
+        ))
+    } else {
+        None
+    };
     let db_clients = Arc::new(Clients {
         mongo: {{crate_name}}::db::mongo::connect(),
+        tenant_databases,
+        search_index: None,
     });
 
-    // drop and load current data
-    let dbs = vec!["samples"];
+    // clear and load current data, keeping the indexes created on connect
+    let dbs = vec!["samples", "api_keys", "revocations", "audit_log"];
     dbs.iter().for_each(|db| {
         let _result = db_clients
             .mongo
             .get_mongo_service(db)
             .unwrap()
             .data_source()
-            .drop(None);
+            .delete_many(doc! {}, None);
     });
 
     // load data
@@ -63,10 +107,12 @@ pub fn load_filled_database(config: &mut web::ServiceConfig) {
         get_data_from_file("./tests/mock/samples.json");
     let service = db_clients.mongo.get_mongo_service("samples").unwrap();
     let _result = service.insert_many(samples, None);
+    {{crate_name}}::db::migrations::migrate(&db_clients.mongo);
 
     let gql = std::sync::Arc::new(create_schema());
 
     // connect the app
     config.data(db_clients.clone());
     config.data(gql);
+    config.data(Arc::new(Issuers::new(vec![])));
 }